@@ -0,0 +1,243 @@
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path, Query as QueryParams, State};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Form, Router};
+use entity::{schema, users};
+use service::sea_orm::DbErr;
+use service::{Mutation, Query};
+
+use crate::{moderation, AppState, Flash};
+
+/// Extracts the logged in user, rejecting anyone who isn't an admin.
+///
+/// Non-admins get a plain `404` so the admin section doesn't advertise itself.
+pub struct AdminUser(pub users::Model);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<users::Model>() {
+            Some(user) if user.role == users::Role::Admin => Ok(AdminUser(user.clone())),
+            _ => Err((StatusCode::NOT_FOUND, "Not found")),
+        }
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/users", get(users))
+        .route("/users/:user_id/disable", post(toggle_disabled))
+        .route("/users/:user_id/role", post(set_role))
+        .route("/users/:user_id/reset_password", post(reset_password))
+        .route("/pastes", get(pastes))
+        .route("/pastes/:paste_id/takedown", post(takedown))
+        .route("/filters", get(filters))
+        .route("/filters", post(create_filter))
+        .route("/filters/:rule_id/toggle", post(toggle_filter))
+        .route("/filters/:rule_id/delete", post(delete_filter))
+        .route("/reports", get(moderation::moderation_queue))
+        .route("/reports/:report_id", post(moderation::moderate))
+}
+
+fn render(state: &AppState, template: &str, ctx: &tera::Context) -> Response {
+    let body = state.templates.render(template, ctx).map_err(|e| {
+        tracing::error!("Error rendering template {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "error rendering template",
+        )
+    });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn error_response(err: DbErr) -> Response {
+    match err {
+        DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        e => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+async fn dashboard(AdminUser(admin): AdminUser, state: State<AppState>) -> Response {
+    let stats = match Query::get_instance_stats(&state.conn).await {
+        Ok(stats) => stats,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &admin);
+    ctx.insert("stats", &stats);
+    render(&state, "admin.html.tera", &ctx)
+}
+
+async fn users(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    QueryParams(params): QueryParams<schema::SearchParams>,
+) -> Response {
+    let q = params.q.unwrap_or_default();
+    let page = params.page.unwrap_or_default();
+    let (users, pages) = match Query::search_users(&state.conn, q.trim(), page).await {
+        Ok(result) => result,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &admin);
+    ctx.insert("users", &users);
+    ctx.insert("q", &q);
+    ctx.insert("page", &page);
+    ctx.insert("pages", &pages);
+    render(&state, "admin_users.html.tera", &ctx)
+}
+
+async fn toggle_disabled(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Response {
+    if admin.id == user_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            "You can't disable your own account",
+        )
+            .into_response();
+    }
+
+    match Mutation::toggle_user_disabled(&state.conn, user_id).await {
+        Ok(_) => Redirect::to("/admin/users").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_role(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    Path(user_id): Path<i64>,
+    form: Form<schema::RolePost>,
+) -> Response {
+    if admin.id == user_id {
+        return (StatusCode::BAD_REQUEST, "You can't change your own role").into_response();
+    }
+
+    match Mutation::set_user_role(&state.conn, user_id, form.0.role).await {
+        Ok(_) => Redirect::to("/admin/users").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn reset_password(
+    AdminUser(_admin): AdminUser,
+    state: State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Response {
+    match Mutation::require_password_reset(&state.conn, user_id).await {
+        Ok(_) => Redirect::to("/admin/users").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn pastes(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    QueryParams(params): QueryParams<schema::SearchParams>,
+) -> Response {
+    let q = params.q.unwrap_or_default();
+    let page = params.page.unwrap_or_default();
+    let (pastes, pages) = match Query::search_pastes(&state.conn, q.trim(), page).await {
+        Ok(result) => result,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &admin);
+    ctx.insert("pastes", &pastes);
+    ctx.insert("q", &q);
+    ctx.insert("page", &page);
+    ctx.insert("pages", &pages);
+    render(&state, "admin_pastes.html.tera", &ctx)
+}
+
+async fn takedown(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    Path(paste_id): Path<String>,
+    QueryParams(params): QueryParams<schema::DomainParams>,
+    form: Form<schema::ModerationPost>,
+) -> Response {
+    let domain = params.domain.unwrap_or_default();
+    match Mutation::takedown_paste(&state.conn, &domain, &paste_id, &admin, &form.0).await {
+        Ok(_) => Redirect::to("/admin/pastes").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn filters(AdminUser(admin): AdminUser, state: State<AppState>) -> Response {
+    render_filters(&state, &admin, None).await
+}
+
+async fn render_filters(state: &AppState, admin: &users::Model, flash: Option<Flash>) -> Response {
+    let rules = match Query::get_filter_rules(&state.conn, false).await {
+        Ok(rules) => rules,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", admin);
+    ctx.insert("rules", &rules);
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+    render(state, "admin_filters.html.tera", &ctx)
+}
+
+async fn create_filter(
+    AdminUser(admin): AdminUser,
+    state: State<AppState>,
+    form: Form<schema::FilterRulePost>,
+) -> Response {
+    match Mutation::create_filter_rule(&state.conn, &form.0).await {
+        Ok(_) => Redirect::to("/admin/filters").into_response(),
+        Err(DbErr::Custom(msg)) => {
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            render_filters(&state, &admin, Some(flash)).await
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+async fn toggle_filter(
+    AdminUser(_admin): AdminUser,
+    state: State<AppState>,
+    Path(rule_id): Path<i64>,
+) -> Response {
+    match Mutation::toggle_filter_rule(&state.conn, rule_id).await {
+        Ok(_) => Redirect::to("/admin/filters").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn delete_filter(
+    AdminUser(_admin): AdminUser,
+    state: State<AppState>,
+    Path(rule_id): Path<i64>,
+) -> Response {
+    match Mutation::delete_filter_rule(&state.conn, rule_id).await {
+        Ok(_) => Redirect::to("/admin/filters").into_response(),
+        Err(e) => error_response(e),
+    }
+}
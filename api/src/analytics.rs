@@ -0,0 +1,235 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, HeaderName, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Extension;
+use chrono::Utc;
+use entity::{clicks, pastes, users};
+use maxminddb::{geoip2, Reader};
+use service::sea_orm::{ActiveValue, DatabaseConnection};
+use service::{Mutation, Query};
+use tokio::sync::mpsc;
+
+use crate::domains::SiteDomain;
+use crate::AppState;
+
+/// Clicks waiting to be written. When the writer falls this far behind new clicks are dropped
+/// rather than slowing down redirects.
+const QUEUE_SIZE: usize = 10_000;
+/// Most clicks written in a single insert.
+const BATCH_SIZE: usize = 500;
+/// Days shown in the chart on the stats page.
+const STATS_DAYS: u64 = 30;
+/// Health checks listed on the stats page.
+const STATS_LINK_CHECKS: u64 = 10;
+
+struct ClickEvent {
+    paste_domain: String,
+    paste_id: String,
+    clicked_at: chrono::NaiveDateTime,
+    referrer_host: Option<String>,
+    user_agent: &'static str,
+    ip: Option<IpAddr>,
+}
+
+/// Records short link clicks in the background so redirects never wait on the database.
+pub struct ClickRecorder {
+    tx: mpsc::Sender<ClickEvent>,
+    real_ip_header: Option<HeaderName>,
+}
+
+impl ClickRecorder {
+    /// Spawns the writer task. `geoip` is a MaxMind country (or city) database used to
+    /// resolve visitors to a country, and `real_ip_header` is the header a reverse proxy
+    /// puts the client address in.
+    pub fn start(
+        conn: DatabaseConnection,
+        geoip: Option<Reader<Vec<u8>>>,
+        real_ip_header: Option<HeaderName>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(write_clicks(conn, geoip.map(Arc::new), rx));
+        Self { tx, real_ip_header }
+    }
+
+    pub fn record(&self, paste: &pastes::Model, headers: &HeaderMap, peer: SocketAddr) {
+        let ip = match &self.real_ip_header {
+            Some(name) => headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .and_then(|v| v.trim().parse().ok()),
+            None => Some(peer.ip()),
+        };
+
+        let event = ClickEvent {
+            paste_domain: paste.domain.clone(),
+            paste_id: paste.id.clone(),
+            clicked_at: Utc::now().naive_utc(),
+            referrer_host: headers
+                .get(header::REFERER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(referrer_host),
+            user_agent: user_agent_family(
+                headers
+                    .get(header::USER_AGENT)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default(),
+            ),
+            ip,
+        };
+
+        if self.tx.try_send(event).is_err() {
+            tracing::warn!("click queue is full, dropping click for {}", paste.id);
+        }
+    }
+}
+
+async fn write_clicks(
+    conn: DatabaseConnection,
+    geoip: Option<Arc<Reader<Vec<u8>>>>,
+    mut rx: mpsc::Receiver<ClickEvent>,
+) {
+    while let Some(first) = rx.recv().await {
+        // write whatever else piled up while the previous batch was being inserted
+        let mut events = vec![first];
+        while events.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(event) => events.push(event),
+                Err(_) => break,
+            }
+        }
+
+        let batch = events
+            .into_iter()
+            .map(|event| clicks::ActiveModel {
+                paste_domain: ActiveValue::Set(event.paste_domain),
+                paste_id: ActiveValue::Set(event.paste_id),
+                clicked_at: ActiveValue::Set(event.clicked_at),
+                referrer_host: ActiveValue::Set(event.referrer_host),
+                user_agent: ActiveValue::Set(event.user_agent.to_owned()),
+                country: ActiveValue::Set(
+                    event
+                        .ip
+                        .zip(geoip.as_deref())
+                        .and_then(|(ip, reader)| country(reader, ip)),
+                ),
+                ..Default::default()
+            })
+            .collect();
+
+        if let Err(e) = Mutation::record_clicks(&conn, batch).await {
+            tracing::error!("Error recording clicks: {}", e);
+        }
+    }
+}
+
+fn country(reader: &Reader<Vec<u8>>, ip: IpAddr) -> Option<String> {
+    let record: geoip2::Country = reader.lookup(ip).ok()?;
+    record
+        .country
+        .and_then(|c| c.iso_code)
+        .map(|code| code.to_owned())
+}
+
+fn referrer_host(referrer: &str) -> Option<String> {
+    let url = url::Url::parse(referrer).ok()?;
+    url.host_str()
+        .filter(|host| host.len() <= 255)
+        .map(|host| host.to_lowercase())
+}
+
+/// Reduces a user agent to the browser (or tool) family. Only the family is stored.
+fn user_agent_family(user_agent: &str) -> &'static str {
+    let ua = user_agent.to_lowercase();
+    if ua.is_empty() {
+        return "Unknown";
+    }
+
+    // link previews from chat apps and crawlers
+    if ["bot", "crawler", "spider", "preview", "facebookexternalhit"]
+        .iter()
+        .any(|needle| ua.contains(needle))
+    {
+        "Bot"
+    } else if ua.starts_with("curl/") {
+        "curl"
+    } else if ua.starts_with("wget/") {
+        "Wget"
+    } else if ua.contains("edg/") || ua.contains("edga/") || ua.contains("edgios/") {
+        "Edge"
+    } else if ua.contains("opr/") || ua.contains("opera") {
+        "Opera"
+    } else if ua.contains("firefox/") || ua.contains("fxios/") {
+        "Firefox"
+    } else if ua.contains("chrome/") || ua.contains("crios/") || ua.contains("chromium/") {
+        "Chrome"
+    } else if ua.contains("safari/") {
+        "Safari"
+    } else {
+        "Other"
+    }
+}
+
+pub async fn stats(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+) -> Response {
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    // only the owner gets to see who clicked their links
+    let user = match current_user {
+        Some(Extension(user)) if paste.is_url && paste.belongs_to == Some(user.id) => user,
+        _ => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    let stats = match Query::get_click_stats(&state.conn, &paste, STATS_DAYS).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+    let checks = match Query::get_link_checks(&state.conn, &paste, STATS_LINK_CHECKS).await {
+        Ok(checks) => checks,
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+    let busiest_day = stats
+        .daily
+        .iter()
+        .map(|d| d.clicks)
+        .max()
+        .unwrap_or_default();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &user);
+    ctx.insert("paste", &paste);
+    ctx.insert("stats", &stats);
+    ctx.insert("checks", &checks);
+    ctx.insert("busiest_day", &busiest_day.max(1));
+
+    let body = state
+        .templates
+        .render("stats.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
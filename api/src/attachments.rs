@@ -0,0 +1,293 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Extension;
+use entity::{attachments, users};
+use futures_util::stream::{self, StreamExt};
+use service::sea_orm::{DatabaseConnection, DbErr};
+use service::{BlobError, BlobStore, Mutation, Query};
+
+use crate::domains::{self, SiteDomain};
+use crate::{moderation, AppState};
+
+/// Time between two runs of the storage cleanup.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+/// Files removed from storage in one run.
+const CLEANUP_BATCH_SIZE: u64 = 100;
+/// Attachments can be removed or taken down, so they aren't cached for long.
+const CACHE_CONTROL: &str = "public, max-age=3600";
+/// Bytes read before the rest of an upload is streamed, enough to recognise images.
+const SNIFF_LENGTH: usize = 16;
+
+/// Attaches the `file` field of a multipart upload to a paste. The file is streamed to
+/// the blob store and the upload is cut off as soon as it gets larger than allowed.
+pub async fn upload(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+    mut multipart: Multipart,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) if paste.belongs_to == Some(user.id) => paste,
+        _ => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => return (StatusCode::BAD_REQUEST, "missing file").into_response(),
+            Err(e) => return e.into_response(),
+        }
+    };
+    let filename = field.file_name().unwrap_or_default().to_owned();
+    let content_type = field.content_type().map(str::to_owned);
+
+    // the first bytes decide the content type, the rest is streamed to storage
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LENGTH {
+        match field.chunk().await {
+            Ok(Some(chunk)) => head.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(e) => return upload_error(&state, BlobError::Source(e.body_text())),
+        }
+    }
+    if head.is_empty() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "The file is empty.").into_response();
+    }
+    let content_type = service::attachment_content_type(content_type.as_deref(), &head);
+
+    let max_size = state.max_attachment_size;
+    let mut size = 0;
+    let data = stream::once(async { Ok(Bytes::from(head)) })
+        .chain(field.map(|chunk| {
+            chunk.map_err(|e| match e.status() {
+                // the request body went past the limit set on the route
+                StatusCode::PAYLOAD_TOO_LARGE => BlobError::TooLarge,
+                _ => BlobError::Source(e.body_text()),
+            })
+        }))
+        // the upload is cut off as soon as it gets larger than allowed
+        .map(move |chunk| {
+            let chunk = chunk?;
+            size += chunk.len();
+            if size > max_size {
+                return Err(BlobError::TooLarge);
+            }
+            Ok(chunk)
+        })
+        .boxed();
+
+    let key = service::new_blob_key("attachments");
+    let size = match state.blobs.put(&key, data, &content_type).await {
+        Ok(size) => size as i64,
+        Err(e) => return upload_error(&state, e),
+    };
+    let created = Mutation::create_attachment(
+        &state.conn,
+        &paste,
+        &user,
+        &filename,
+        &content_type,
+        size,
+        &key,
+    )
+    .await;
+    match created {
+        Ok(_) => {
+            Redirect::to(&domains::paste_location(&state, &site_domain, &paste)).into_response()
+        }
+        Err(e) => {
+            if let Err(e) = state.blobs.delete(&key).await {
+                tracing::error!("Error removing unused attachment {}: {}", key, e);
+            }
+            match e {
+                DbErr::Custom(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg).into_response(),
+                DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+                e => {
+                    tracing::error!("Something went wrong: {}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+                }
+            }
+        }
+    }
+}
+
+fn upload_error(state: &AppState, err: BlobError) -> Response {
+    match err {
+        BlobError::TooLarge => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Attachments can be at most {} KiB.",
+                state.max_attachment_size / 1024
+            ),
+        )
+            .into_response(),
+        BlobError::Source(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+        e => {
+            tracing::error!("Error storing attachment: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Serves an attachment. Images are shown inline, everything else is downloaded.
+pub async fn show(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path((paste_id, attachment_id)): Path<(String, i64)>,
+) -> Response {
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+    if let Some((status, message)) = moderation::hidden_status(&paste) {
+        return moderation::render_unavailable(&state, current_user.as_deref(), status, message);
+    }
+    let attachment = match Query::get_paste_attachment(&state.conn, &paste, attachment_id).await {
+        Ok(attachment) => attachment,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    let data = match state.blobs.get(&attachment.storage_key).await {
+        Ok(data) => data,
+        Err(BlobError::NotFound) => {
+            tracing::error!("File of attachment {} is missing", attachment.id);
+            return (StatusCode::NOT_FOUND, "Not found").into_response();
+        }
+        Err(e) => {
+            tracing::error!("Error reading attachment {}: {}", attachment.id, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
+    let disposition = if service::is_inline_type(&attachment.content_type) {
+        "inline"
+    } else {
+        "attachment"
+    };
+    (
+        [
+            (header::CONTENT_TYPE, attachment.content_type.clone()),
+            (
+                header::CONTENT_DISPOSITION,
+                content_disposition(disposition, &attachment.filename),
+            ),
+            // never let the browser guess a type, or run anything that slipped through
+            (header::X_CONTENT_TYPE_OPTIONS, String::from("nosniff")),
+            (
+                header::CONTENT_SECURITY_POLICY,
+                String::from("default-src 'none'; sandbox"),
+            ),
+            (header::CACHE_CONTROL, String::from(CACHE_CONTROL)),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+pub async fn delete(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path((paste_id, attachment_id)): Path<(String, i64)>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    match Mutation::detach_attachment(&state.conn, &paste, &user, attachment_id).await {
+        Ok(attachment) => {
+            // the cleanup task tries again later if this fails
+            if let Err(e) = remove(&state.conn, state.blobs.as_ref(), &attachment).await {
+                tracing::error!("Error removing attachment {}: {}", attachment.id, e);
+            }
+            Redirect::to(&domains::paste_location(&state, &site_domain, &paste)).into_response()
+        }
+        Err(DbErr::RecordNotFound(_)) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Spawns the background task that deletes the files of removed attachments and of
+/// attachments whose paste was deleted.
+pub fn start_cleanup(conn: DatabaseConnection, blobs: Arc<dyn BlobStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = cleanup(&conn, blobs.as_ref()).await {
+                tracing::error!("Error cleaning up attachments: {}", e);
+            }
+        }
+    });
+}
+
+async fn cleanup(conn: &DatabaseConnection, blobs: &dyn BlobStore) -> anyhow::Result<()> {
+    let orphans = Query::get_orphaned_attachments(conn, CLEANUP_BATCH_SIZE).await?;
+    for attachment in &orphans {
+        remove(conn, blobs, attachment).await?;
+    }
+    if !orphans.is_empty() {
+        tracing::info!("removed {} unused attachment(s)", orphans.len());
+    }
+    Ok(())
+}
+
+/// Deletes the file first, so a failure leaves the row behind to be retried.
+async fn remove(
+    conn: &DatabaseConnection,
+    blobs: &dyn BlobStore,
+    attachment: &attachments::Model,
+) -> anyhow::Result<()> {
+    blobs.delete(&attachment.storage_key).await?;
+    Mutation::delete_attachment(conn, attachment.id).await?;
+    Ok(())
+}
+
+/// `Content-Disposition` value with the filename both as an ASCII fallback and in
+/// full, encoded as in RFC 5987.
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .filter(|c| *c != '\\')
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition, fallback, encoded
+    )
+}
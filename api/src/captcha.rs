@@ -0,0 +1,313 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long an issued challenge stays valid.
+const CHALLENGE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Window over which anonymous submissions are counted to detect load.
+const LOAD_WINDOW: Duration = Duration::from_secs(60);
+/// The browser solver only inspects the first 32 bits of the hash.
+const MAX_DIFFICULTY: u32 = 32;
+
+/// Self-hosted proof-of-work captcha for anonymous submissions.
+///
+/// The server hands out an HMAC-signed challenge of the form
+/// `difficulty.issued_at.nonce.signature`. The browser has to find a number `n`
+/// such that `sha256("{challenge}:{n}")` starts with `difficulty` zero bits.
+pub struct PowCaptcha {
+    key: Vec<u8>,
+    base_difficulty: u32,
+    max_difficulty: u32,
+    load_threshold: usize,
+    recent: Mutex<VecDeque<Instant>>,
+    spent: Mutex<HashMap<String, Instant>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CaptchaError {
+    Missing,
+    Malformed,
+    BadSignature,
+    Expired,
+    Replayed,
+    WrongSolution,
+}
+
+impl PowCaptcha {
+    pub fn new(
+        secret: &[u8],
+        base_difficulty: u32,
+        max_difficulty: u32,
+        load_threshold: usize,
+    ) -> Self {
+        // derive a dedicated key so captcha signatures can't be confused with cookie signatures
+        let key = Sha256::new()
+            .chain_update(b"katbin-pow-captcha")
+            .chain_update(secret)
+            .finalize()
+            .to_vec();
+
+        let base_difficulty = base_difficulty.min(MAX_DIFFICULTY);
+        Self {
+            key,
+            base_difficulty,
+            max_difficulty: max_difficulty.clamp(base_difficulty, MAX_DIFFICULTY),
+            load_threshold: load_threshold.max(1),
+            recent: Mutex::new(VecDeque::new()),
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Difficulty for newly issued challenges. Every time the number of anonymous
+    /// submissions in the last minute doubles past the threshold, one more bit is required.
+    pub fn current_difficulty(&self) -> u32 {
+        let mut recent = self.recent.lock().unwrap();
+        if let Some(cutoff) = Instant::now().checked_sub(LOAD_WINDOW) {
+            prune_before(&mut recent, cutoff);
+        }
+
+        let mut extra = 0;
+        let mut load = recent.len() / self.load_threshold;
+        while load > 0 {
+            extra += 1;
+            load /= 2;
+        }
+
+        (self.base_difficulty + extra).min(self.max_difficulty)
+    }
+
+    pub fn issue(&self) -> String {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = format!(
+            "{}.{}.{}",
+            self.current_difficulty(),
+            unix_now(),
+            hex::encode(nonce)
+        );
+        format!("{}.{}", payload, hex::encode(self.sign(&payload)))
+    }
+
+    /// Verifies a solved challenge and marks it as spent so it can't be reused.
+    pub fn verify(
+        &self,
+        challenge: Option<&str>,
+        solution: Option<&str>,
+    ) -> Result<(), CaptchaError> {
+        let (challenge, solution) = match (challenge, solution) {
+            (Some(c), Some(s)) if !c.is_empty() && !s.is_empty() => (c, s),
+            _ => return Err(CaptchaError::Missing),
+        };
+
+        let (payload, signature) = challenge.rsplit_once('.').ok_or(CaptchaError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| CaptchaError::Malformed)?;
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| CaptchaError::BadSignature)?;
+
+        let mut parts = payload.split('.');
+        let difficulty: u32 = parts
+            .next()
+            .and_then(|d| d.parse().ok())
+            .ok_or(CaptchaError::Malformed)?;
+        let issued_at: u64 = parts
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or(CaptchaError::Malformed)?;
+        if unix_now().saturating_sub(issued_at) > CHALLENGE_TTL.as_secs() {
+            return Err(CaptchaError::Expired);
+        }
+
+        let hash = Sha256::digest(format!("{}:{}", challenge, solution).as_bytes());
+        if leading_zero_bits(&hash) < difficulty {
+            return Err(CaptchaError::WrongSolution);
+        }
+
+        let now = Instant::now();
+        {
+            let mut spent = self.spent.lock().unwrap();
+            spent.retain(|_, at| now.duration_since(*at) < CHALLENGE_TTL);
+            if spent.insert(challenge.to_owned(), now).is_some() {
+                return Err(CaptchaError::Replayed);
+            }
+        }
+
+        self.recent.lock().unwrap().push_back(now);
+        Ok(())
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+fn prune_before(recent: &mut VecDeque<Instant>, cutoff: Instant) {
+    while recent.front().is_some_and(|at| *at < cutoff) {
+        recent.pop_front();
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captcha() -> PowCaptcha {
+        PowCaptcha::new(b"secret", 8, 12, 2)
+    }
+
+    /// A challenge signed by `captcha` as if it had been issued at `issued_at`.
+    fn challenge(captcha: &PowCaptcha, difficulty: u32, issued_at: u64) -> String {
+        let payload = format!("{}.{}.00ff", difficulty, issued_at);
+        format!("{}.{}", payload, hex::encode(captcha.sign(&payload)))
+    }
+
+    fn zero_bits(challenge: &str, solution: u64) -> u32 {
+        leading_zero_bits(&Sha256::digest(
+            format!("{}:{}", challenge, solution).as_bytes(),
+        ))
+    }
+
+    /// The first solution with at least as many zero bits as the challenge asks for.
+    fn solve(challenge: &str) -> String {
+        let difficulty: u32 = challenge.split('.').next().unwrap().parse().unwrap();
+        (0..)
+            .find(|n| zero_bits(challenge, *n) >= difficulty)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn accepts_solved_challenges() {
+        let captcha = captcha();
+        let issued = captcha.issue();
+        assert!(issued.starts_with("8."));
+        assert_eq!(captcha.verify(Some(&issued), Some(&solve(&issued))), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let captcha = captcha();
+        let other = PowCaptcha::new(b"other secret", 8, 12, 2).issue();
+        assert_eq!(
+            captcha.verify(Some(&other), Some(&solve(&other))),
+            Err(CaptchaError::BadSignature)
+        );
+
+        // lowering the difficulty breaks the signature
+        let issued = captcha.issue();
+        let easier = issued.replacen("8.", "0.", 1);
+        assert_eq!(
+            captcha.verify(Some(&easier), Some("0")),
+            Err(CaptchaError::BadSignature)
+        );
+
+        assert_eq!(
+            captcha.verify(Some("8.0.00ff.zz"), Some("0")),
+            Err(CaptchaError::Malformed)
+        );
+        assert_eq!(
+            captcha.verify(Some(&issued), Some("")),
+            Err(CaptchaError::Missing)
+        );
+    }
+
+    #[test]
+    fn rejects_expired_challenges() {
+        let captcha = captcha();
+        let expired = challenge(&captcha, 8, unix_now() - CHALLENGE_TTL.as_secs() - 1);
+        assert_eq!(
+            captcha.verify(Some(&expired), Some(&solve(&expired))),
+            Err(CaptchaError::Expired)
+        );
+
+        let fresh = challenge(&captcha, 8, unix_now() - CHALLENGE_TTL.as_secs() + 5);
+        assert_eq!(captcha.verify(Some(&fresh), Some(&solve(&fresh))), Ok(()));
+    }
+
+    #[test]
+    fn rejects_replayed_challenges() {
+        let captcha = captcha();
+        let issued = captcha.issue();
+        let solution = solve(&issued);
+        assert_eq!(captcha.verify(Some(&issued), Some(&solution)), Ok(()));
+        assert_eq!(
+            captcha.verify(Some(&issued), Some(&solution)),
+            Err(CaptchaError::Replayed)
+        );
+    }
+
+    #[test]
+    fn rejects_too_few_zero_bits() {
+        let captcha = captcha();
+        let issued = captcha.issue();
+        let wrong = (0..).find(|n| zero_bits(&issued, *n) < 8).unwrap();
+        assert_eq!(
+            captcha.verify(Some(&issued), Some(&wrong.to_string())),
+            Err(CaptchaError::WrongSolution)
+        );
+        // a failed attempt doesn't spend the challenge
+        assert_eq!(captcha.verify(Some(&issued), Some(&solve(&issued))), Ok(()));
+    }
+
+    #[test]
+    fn difficulty_follows_load() {
+        let captcha = captcha();
+        let submit = |count: usize, at: Instant| {
+            let mut recent = captcha.recent.lock().unwrap();
+            recent.extend(std::iter::repeat_n(at, count));
+        };
+
+        assert_eq!(captcha.current_difficulty(), 8);
+        submit(1, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 8);
+        submit(1, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 9);
+        submit(2, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 10);
+        submit(4, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 11);
+        submit(100, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 12);
+        assert!(captcha.issue().starts_with("12."));
+
+        // submissions older than the window stop counting
+        let old = Instant::now() - LOAD_WINDOW * 2;
+        captcha.recent.lock().unwrap().clear();
+        submit(100, old);
+        submit(2, Instant::now());
+        assert_eq!(captcha.current_difficulty(), 9);
+        assert_eq!(captcha.recent.lock().unwrap().len(), 2);
+        prune_before(&mut captcha.recent.lock().unwrap(), Instant::now());
+        assert_eq!(captcha.current_difficulty(), 8);
+    }
+}
@@ -0,0 +1,232 @@
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use entity::{domains, pastes, schema, users};
+use hickory_resolver::TokioAsyncResolver;
+use service::sea_orm::DbErr;
+use service::{Mutation, Query};
+
+use crate::{AppState, Flash};
+
+/// Custom domain the request was made on, `""` for the instance's own domain.
+///
+/// Requests on hosts that aren't a verified custom domain are served as the instance itself.
+pub struct SiteDomain(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for SiteDomain {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let host = parts
+            .headers
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| parts.uri.host())
+            .unwrap_or_default();
+        // strip the port, IPv6 literals aren't custom domains anyway
+        let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+
+        if host.is_empty() || service::is_instance_host(host) {
+            return Ok(SiteDomain(String::new()));
+        }
+
+        match Query::get_verified_domain(&state.conn, host).await {
+            Ok(Some(domain)) => Ok(SiteDomain(domain.host)),
+            Ok(None) => Ok(SiteDomain(String::new())),
+            Err(e) => {
+                tracing::error!("Error looking up domain {}: {}", host, e);
+                Err((StatusCode::INTERNAL_SERVER_ERROR, "something went wrong"))
+            }
+        }
+    }
+}
+
+/// Where to send the browser after creating or editing `paste` on `site_domain`.
+pub fn paste_location(state: &AppState, site_domain: &str, paste: &pastes::Model) -> String {
+    let path = if paste.is_url {
+        format!("/v/{}", paste.id)
+    } else {
+        format!("/{}", paste.id)
+    };
+
+    if paste.domain == site_domain {
+        path
+    } else if paste.domain.is_empty() {
+        format!("{}{}", state.public_url, path)
+    } else {
+        format!("https://{}{}", paste.domain, path)
+    }
+}
+
+/// Absolute URL of a paste, using the short domain for links.
+pub fn canonical_url(state: &AppState, paste: &pastes::Model) -> String {
+    if !paste.domain.is_empty() {
+        format!("https://{}/{}", paste.domain, paste.id)
+    } else if paste.is_url {
+        format!("{}/{}", state.short_url, paste.id)
+    } else {
+        format!("{}/{}", state.public_url, paste.id)
+    }
+}
+
+/// DNS name of the TXT record proving control over `domain`.
+fn verification_record(domain: &domains::Model) -> String {
+    format!("_katbin.{}", domain.host)
+}
+
+pub async fn domains(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    render_domains(&state, &user, None).await
+}
+
+pub async fn domains_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    form: Form<schema::DomainPost>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    let flash = match Mutation::create_domain(&state.conn, &user, &form.0).await {
+        Ok(domain) => Flash {
+            info: Some(format!(
+                "{} was added, create the TXT record below and verify it.",
+                domain.host
+            )),
+            warn: None,
+        },
+        Err(DbErr::Custom(msg)) => Flash {
+            info: None,
+            warn: Some(msg),
+        },
+        Err(e) => return error_response(e),
+    };
+
+    render_domains(&state, &user, Some(flash)).await
+}
+
+pub async fn verify(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    Path(domain_id): Path<i64>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+    let domain = match Mutation::get_owned_domain(&state.conn, &user, domain_id).await {
+        Ok(domain) => domain,
+        Err(e) => return error_response(e),
+    };
+
+    let record = verification_record(&domain);
+    let found = match TokioAsyncResolver::tokio_from_system_conf() {
+        Ok(resolver) => match resolver.txt_lookup(record.as_str()).await {
+            Ok(txt) => txt
+                .iter()
+                .any(|txt| txt.to_string().trim() == domain.verification_token),
+            Err(e) => {
+                tracing::debug!("TXT lookup for {} failed: {}", record, e);
+                false
+            }
+        },
+        Err(e) => {
+            tracing::error!("Could not create a DNS resolver: {}", e);
+            false
+        }
+    };
+
+    let flash = if found {
+        let host = domain.host.clone();
+        if let Err(e) = Mutation::mark_domain_verified(&state.conn, domain).await {
+            return error_response(e);
+        }
+        Flash {
+            info: Some(format!("{} is verified and ready to use.", host)),
+            warn: None,
+        }
+    } else {
+        Flash {
+            info: None,
+            warn: Some(format!(
+                "No TXT record with the verification token was found at {}. DNS changes can take a while to show up.",
+                record
+            )),
+        }
+    };
+
+    render_domains(&state, &user, Some(flash)).await
+}
+
+pub async fn delete(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    Path(domain_id): Path<i64>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    match Mutation::delete_domain(&state.conn, &user, domain_id).await {
+        Ok(_) => Redirect::to("/users/domains").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn render_domains(state: &AppState, user: &users::Model, flash: Option<Flash>) -> Response {
+    let domains = match Query::get_user_domains(&state.conn, user.id).await {
+        Ok(domains) => domains,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", user);
+    ctx.insert(
+        "domains",
+        &domains
+            .iter()
+            .map(|d| serde_json::json!({ "domain": d, "record": verification_record(d) }))
+            .collect::<Vec<_>>(),
+    );
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+
+    let body = state
+        .templates
+        .render("domains.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn error_response(err: DbErr) -> Response {
+    match err {
+        DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        e => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
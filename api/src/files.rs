@@ -0,0 +1,210 @@
+use std::io::{Cursor, Write};
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use entity::paste_contents::{self, Codec};
+use entity::pastes;
+use service::{PasteFile, Query};
+use zip::write::SimpleFileOptions;
+
+use crate::domains::SiteDomain;
+use crate::{moderation, AppState};
+
+/// The content of a paste as plain text, the first file for pastes with several files.
+pub async fn raw(
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    headers: HeaderMap,
+    Path(paste_id): Path<String>,
+) -> Response {
+    match stored_paste(&state, &site_domain, &paste_id).await {
+        Ok(paste) => first_file(&state, paste, &headers).await,
+        Err(e) => e,
+    }
+}
+
+/// A single file of a paste as plain text.
+pub async fn raw_file(
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    headers: HeaderMap,
+    Path((paste_id, filename)): Path<(String, String)>,
+) -> Response {
+    let paste = match stored_paste(&state, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(e) => return e,
+    };
+    if paste.filename.as_deref() == Some(filename.as_str()) {
+        return first_file(&state, paste, &headers).await;
+    }
+
+    match Query::get_paste_file_content(&state.conn, &paste, &filename).await {
+        Ok(Some(content)) => stored_content(content, &paste, &headers),
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Sends the first file of a paste.
+async fn first_file(state: &AppState, paste: pastes::Model, headers: &HeaderMap) -> Response {
+    match Query::get_paste_content(&state.conn, &paste).await {
+        Ok(Some(content)) => stored_content(content, &paste, headers),
+        // short links keep their url in the paste
+        Ok(None) => plain_text(paste.content),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Sends a file of `paste`. Compressed content goes out as it is stored when the client
+/// accepts the encoding, saving the work of decompressing it.
+fn stored_content(
+    mut content: paste_contents::Model,
+    paste: &pastes::Model,
+    headers: &HeaderMap,
+) -> Response {
+    if content.content_codec == Some(Codec::Zstd) && accepts_encoding(headers, "zstd") {
+        if let Some(compressed) = content.content_compressed.take() {
+            return (
+                [
+                    (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+                    (header::CONTENT_ENCODING, "zstd"),
+                    (header::VARY, "Accept-Encoding"),
+                    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+                ],
+                compressed,
+            )
+                .into_response();
+        }
+    }
+
+    match service::decompress_content(&content) {
+        Ok(text) => plain_text(text),
+        Err(e) => {
+            tracing::error!("Error decompressing paste {}: {}", paste.id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Whether `Accept-Encoding` allows `encoding`, i.e. lists it without `q=0`.
+fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|part| {
+            let mut params = part.split(';').map(str::trim);
+            if !params
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(encoding))
+            {
+                return false;
+            }
+            params
+                .filter_map(|param| param.strip_prefix("q="))
+                .all(|q| q.parse::<f32>().is_ok_and(|q| q > 0.0))
+        })
+}
+
+/// Every file of a paste in a zip archive, inside a directory named after the paste.
+pub async fn zip(
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+) -> Response {
+    let (paste, files) = match paste_files(&state, &site_domain, &paste_id).await {
+        Ok(files) => files,
+        Err(e) => return e,
+    };
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for file in &files {
+        let name = format!("{}/{}", paste.id, file.download_name(&paste.id));
+        let written = archive
+            .start_file(name, options)
+            .and_then(|_| Ok(archive.write_all(file.content.as_bytes())?));
+        if let Err(e) = written {
+            tracing::error!("Error writing zip archive: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    }
+    let body = match archive.finish() {
+        Ok(cursor) => cursor.into_inner(),
+        Err(e) => {
+            tracing::error!("Error writing zip archive: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, String::from("application/zip")),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.zip\"", paste.id),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Looks up a visible paste without loading its content.
+async fn stored_paste(
+    state: &AppState,
+    site_domain: &str,
+    paste_id: &str,
+) -> Result<pastes::Model, Response> {
+    let paste = match Query::get_stored_paste_by_id(&state.conn, site_domain, paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "Not found").into_response()),
+    };
+    match moderation::hidden_status(&paste) {
+        Some((status, message)) => Err((status, message).into_response()),
+        None => Ok(paste),
+    }
+}
+
+/// Looks up a visible paste along with its files.
+async fn paste_files(
+    state: &AppState,
+    site_domain: &str,
+    paste_id: &str,
+) -> Result<(pastes::Model, Vec<PasteFile>), Response> {
+    let paste = match Query::get_paste_by_id(&state.conn, site_domain, paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "Not found").into_response()),
+    };
+    if let Some((status, message)) = moderation::hidden_status(&paste) {
+        return Err((status, message).into_response());
+    }
+
+    match Query::get_paste_files(&state.conn, &paste).await {
+        Ok(files) => Ok((paste, files)),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response())
+        }
+    }
+}
+
+fn plain_text(content: String) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::VARY, "Accept-Encoding"),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        content,
+    )
+        .into_response()
+}
@@ -1,24 +1,57 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
 use std::env;
-use std::sync::OnceLock;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use axum::extract::{Path, Request, State};
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Path, Request, State};
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Redirect, Response};
-use axum::routing::{get_service, post};
+use axum::routing::{get_service, post, MethodRouter};
 use axum::{routing::get, Router};
 use axum::{Extension, Form};
-use entity::{pastes, schema, users};
+use entity::{schema, users};
+// the migration crate's own sea-orm, see `apply_migrations`
+use migration::sea_orm::{ConnectionTrait, DbBackend, Statement, TransactionTrait};
+use migration::{IntoSchemaManagerConnection, Migrator, MigratorTrait, SchemaManager};
 use serde::{Deserialize, Serialize};
 use service::sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr, SqlErr};
-use service::{Mutation, Query};
+use service::{BlobStore, LocalBlobStore, Mutation, Query, S3BlobStore};
 use tera::Tera;
+use tower::Service;
 use tower_cookies::{Cookie, CookieManagerLayer, Cookies, Key};
 use tower_http::services::ServeDir;
 
+use crate::analytics::ClickRecorder;
+use crate::captcha::PowCaptcha;
+use crate::domains::SiteDomain;
+use crate::limits::{PasteForm, PasteLimits};
+use crate::link_checker::LinkCheckerConfig;
+use crate::middleware::ApiToken;
+
 const COOKIE_NAME: &str = "current_user";
+/// Ids that no route uses yet, kept free for later. Custom URLs can't use them, nor the
+/// first path segment of any route, see [`Routes`].
+const KEPT_IDS: &[&str] = &[
+    "new", "login", "logout", "register", "settings", "health", "robots", "favicon",
+];
 static KEY: OnceLock<Key> = OnceLock::new();
 
+mod admin;
+mod analytics;
+mod attachments;
+mod captcha;
+mod domains;
+mod files;
+mod limits;
+mod link_checker;
+mod links;
 mod middleware;
+mod moderation;
+mod qr;
+mod search;
+mod tokens;
 
 #[tokio::main]
 async fn start() -> anyhow::Result<()> {
@@ -30,32 +63,293 @@ async fn start() -> anyhow::Result<()> {
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not found in environment");
     let port = env::var("PORT").expect("PORT not found in environment");
     let key = env::var("SECRET_KEY").expect("SECRET_KEY not found in environment");
+    // comma separated list of accounts that are promoted to admins on startup
+    let admin_emails: Vec<String> = env::var("ADMIN_EMAILS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|e| e.trim().to_owned())
+        .filter(|e| !e.is_empty())
+        .collect();
 
     KEY.set(Key::from(key.as_bytes())).unwrap();
 
+    // proof-of-work captcha for anonymous pastes, enabled by setting a base difficulty
+    let captcha = env::var("POW_DIFFICULTY").ok().map(|difficulty| {
+        let difficulty: u32 = difficulty.parse().expect("POW_DIFFICULTY must be a number");
+        let max_difficulty = env::var("POW_MAX_DIFFICULTY")
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(difficulty + 6);
+        let load_threshold = env::var("POW_LOAD_THRESHOLD")
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(30);
+        Arc::new(PowCaptcha::new(
+            key.as_bytes(),
+            difficulty,
+            max_difficulty,
+            load_threshold,
+        ))
+    });
+
+    // where the instance is reachable from, used for every absolute link it generates
+    let public_url = env::var("PUBLIC_URL").unwrap_or_else(|_| {
+        tracing::warn!("PUBLIC_URL not set, generated links will point to localhost");
+        format!("http://localhost:{}", port)
+    });
+    let public_url = parse_base_url("PUBLIC_URL", &public_url);
+    // optional separate (usually shorter) domain for short links
+    let short_url = env::var("SHORT_URL")
+        .map(|url| parse_base_url("SHORT_URL", &url))
+        .unwrap_or_else(|_| public_url.clone());
+    service::set_instance_hosts(
+        [&public_url, &short_url]
+            .iter()
+            .filter_map(|url| url::Url::parse(url).ok()?.host_str().map(str::to_owned))
+            .collect(),
+    );
+
+    // how paste ids are generated: pronounceable (default), base62 or words
+    let key_style: service::KeyStyle = env::var("KEY_STYLE")
+        .map(|style| style.parse().unwrap_or_else(|e| panic!("KEY_STYLE: {}", e)))
+        .unwrap_or(service::KeyStyle::Pronounceable);
+    // characters (or words) in a generated id, ids grow once they start colliding
+    let key_length = env::var("KEY_LENGTH")
+        .map(|length| length.parse().expect("KEY_LENGTH must be a number"))
+        .unwrap_or(match key_style {
+            service::KeyStyle::Words => 3,
+            _ => 10,
+        });
+    // characters used by the base62 style, e.g. to leave out look-alikes like 0/O and l/1
+    let key_alphabet = env::var("KEY_ALPHABET").ok();
+    service::set_key_config(
+        service::KeyConfig::new(key_style, key_length, key_alphabet.as_deref())
+            .unwrap_or_else(|e| panic!("invalid key settings: {}", e)),
+    );
+
+    // domains that can't be shortened, one per line
+    if let Ok(path) = env::var("BLOCKED_DOMAINS_FILE") {
+        let count =
+            service::load_domain_blocklist(&path).expect("failed to load BLOCKED_DOMAINS_FILE");
+        tracing::info!("loaded {} blocked domain(s) from {}", count, path);
+    }
+
+    service::set_compression_threshold(compression_threshold());
+    // directory of the embedded search index, used instead of Postgres' text search
+    open_search_index()?;
+
+    // apply pending migrations before starting, with `katbin --migrate` or AUTO_MIGRATE=1
+    if env::args().any(|arg| arg == "--migrate")
+        || env::var("AUTO_MIGRATE").is_ok_and(|v| v == "1" || v == "true")
+    {
+        let applied = apply_migrations(&db_url).await?;
+        tracing::info!("applied {} migration(s)", applied);
+    } else {
+        check_schema(&migration::sea_orm::Database::connect(&db_url).await?).await?;
+    }
+
     // make db connection
-    let mut opt = ConnectOptions::new(db_url);
+    let opt = ConnectOptions::new(db_url);
     // opt.sqlx_logging(env::var("DB_LOG").is_ok());
     let conn = Database::connect(opt)
         .await
         .expect("database connection failed");
 
-    let templates = Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*"))
+    let promoted = Mutation::promote_admins(&conn, &admin_emails)
+        .await
+        .expect("promoting admins failed");
+    if promoted > 0 {
+        tracing::info!("promoted {} account(s) from ADMIN_EMAILS", promoted);
+    }
+
+    // country lookups for link clicks, using a MaxMind GeoLite2/GeoIP2 database file
+    let geoip = env::var("GEOIP_DATABASE").ok().map(|path| {
+        maxminddb::Reader::open_readfile(&path).expect("failed to open GEOIP_DATABASE")
+    });
+    // header set by a reverse proxy with the real client address, e.g. X-Forwarded-For
+    let real_ip_header = env::var("REAL_IP_HEADER").ok().map(|name| {
+        name.parse::<axum::http::HeaderName>()
+            .expect("REAL_IP_HEADER is not a valid header name")
+    });
+    // always show the "you are being redirected" page, whatever the link settings say
+    let force_redirect_preview =
+        env::var("FORCE_REDIRECT_PREVIEW").is_ok_and(|v| v == "1" || v == "true");
+
+    let clicks = Arc::new(ClickRecorder::start(conn.clone(), geoip, real_ip_header));
+
+    // seconds between rounds of short link health checks, disabled when not set
+    if let Ok(interval) = env::var("LINK_CHECK_INTERVAL") {
+        let env_number = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|_| panic!("{} must be a number", name))
+                })
+                .unwrap_or(default)
+        };
+        let config = LinkCheckerConfig {
+            interval: Duration::from_secs(
+                interval
+                    .parse()
+                    .expect("LINK_CHECK_INTERVAL must be a number"),
+            ),
+            recheck_after: Duration::from_secs(env_number("LINK_CHECK_RECHECK_HOURS", 24) * 3600),
+            timeout: Duration::from_secs(env_number("LINK_CHECK_TIMEOUT", 10)),
+            failures: env_number("LINK_CHECK_FAILURES", 3).max(1),
+            // also check links to loopback, private and link-local addresses
+            allow_private: env::var("LINK_CHECK_PRIVATE").is_ok_and(|v| v == "1" || v == "true"),
+        };
+        link_checker::start(conn.clone(), config).expect("failed to start the link checker");
+    }
+
+    // where attachments are stored: a local directory (default) or an S3 compatible bucket
+    let blobs: Arc<dyn BlobStore> = match env::var("STORAGE_BACKEND").as_deref() {
+        Err(_) | Ok("local") => Arc::new(LocalBlobStore::new(
+            env::var("STORAGE_PATH").unwrap_or_else(|_| String::from("./uploads")),
+        )),
+        Ok("s3") => {
+            let s3_var = |name: &str| {
+                env::var(name).unwrap_or_else(|_| panic!("{} not found in environment", name))
+            };
+            Arc::new(
+                S3BlobStore::new(
+                    &s3_var("S3_ENDPOINT"),
+                    &s3_var("S3_BUCKET"),
+                    &env::var("S3_REGION").unwrap_or_else(|_| String::from("us-east-1")),
+                    &s3_var("S3_ACCESS_KEY"),
+                    &s3_var("S3_SECRET_KEY"),
+                )
+                .unwrap_or_else(|e| panic!("{}", e)),
+            )
+        }
+        Ok(other) => panic!("unknown STORAGE_BACKEND {}, expected local or s3", other),
+    };
+    // largest attachment in bytes, 10 MiB by default
+    let max_attachment_size: usize = env::var("MAX_ATTACHMENT_SIZE")
+        .map(|size| size.parse().expect("MAX_ATTACHMENT_SIZE must be a number"))
+        .unwrap_or(10 * 1024 * 1024);
+    attachments::start_cleanup(conn.clone(), blobs.clone());
+    // largest paste in bytes, all files together, for anonymous, registered and admin users
+    let paste_size = |name: &str, default: usize| {
+        env::var(name)
+            .map(|size| {
+                size.parse()
+                    .unwrap_or_else(|_| panic!("{} must be a number", name))
+            })
+            .unwrap_or(default)
+    };
+    let paste_limits = PasteLimits {
+        anonymous: paste_size("MAX_PASTE_SIZE_ANONYMOUS", 1024 * 1024),
+        registered: paste_size("MAX_PASTE_SIZE_REGISTERED", 2 * 1024 * 1024),
+        admin: paste_size("MAX_PASTE_SIZE_ADMIN", 16 * 1024 * 1024),
+    };
+
+    let mut templates = Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*"))
         .expect("tera initialization failed");
+    templates.register_function("public_url", constant_function(public_url.clone()));
+    templates.register_function("short_url", constant_function(short_url.clone()));
 
-    let state: AppState = AppState { templates, conn };
+    let state: AppState = AppState {
+        templates,
+        conn,
+        captcha,
+        clicks,
+        force_redirect_preview,
+        public_url,
+        short_url,
+        blobs,
+        max_attachment_size,
+        paste_limits,
+    };
+
+    let routes = routes(max_attachment_size);
+    let reserved: Vec<&str> = routes
+        .reserved
+        .iter()
+        .map(String::as_str)
+        .chain(KEPT_IDS.iter().copied())
+        .collect();
+    service::set_reserved_ids(&reserved);
+
+    let app = routes
+        .router
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::current_user_middleware,
+        ))
+        .layer(CookieManagerLayer::new())
+        .with_state(state);
 
-    let app = Router::new()
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
+        .await
+        .unwrap();
+
+    tracing::info!("listening on http://{}", listener.local_addr().unwrap());
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Every route of the app. State and middleware are added by `start`.
+fn routes(max_attachment_size: usize) -> Routes {
+    Routes::new()
         .route("/", get(root))
         .route("/", post(create_paste))
         .route("/:paste_id", get(show_paste))
         .route("/:paste_id/edit", get(edit))
         .route("/:paste_id/edit", post(post_edit))
+        .route("/:paste_id/rename", get(rename))
+        .route("/:paste_id/rename", post(rename_post))
+        .route("/:paste_id/qr.svg", get(qr::svg))
+        .route("/:paste_id/qr.png", get(qr::png))
+        .route(
+            "/:paste_id/attachments",
+            // the handler enforces the real limit while reading, this leaves room for
+            // the rest of the form
+            post(attachments::upload).layer(DefaultBodyLimit::max(max_attachment_size + 64 * 1024)),
+        )
+        .route(
+            "/:paste_id/attachments/:attachment_id",
+            get(attachments::show),
+        )
+        .route(
+            "/:paste_id/attachments/:attachment_id/delete",
+            post(attachments::delete),
+        )
+        .route("/:paste_id/files.zip", get(files::zip))
+        .route("/raw/:paste_id", get(files::raw))
+        .route("/raw/:paste_id/:filename", get(files::raw_file))
+        .route("/:paste_id/report", get(moderation::report))
+        .route("/:paste_id/report", post(moderation::report_post))
         .route("/v/:paste_id", get(show_paste))
+        .route("/v/:paste_id/stats", get(analytics::stats))
+        .route("/v/:paste_id/settings", get(links::settings))
+        .route("/v/:paste_id/settings", post(links::settings_post))
+        .route("/links/import", get(links::import))
+        .route("/links/import", post(links::import_post))
+        .route("/api/links/batch", post(links::batch))
+        .route("/api/limits", get(limits::limits))
+        .route("/api/search", get(search::search_api))
         .route("/users/log_in", get(login))
         .route("/users/log_in", post(login_post))
         .route("/users/register", get(register))
         .route("/users/register", post(register_post))
+        .route("/users/reset_password", get(reset_password))
+        .route("/users/reset_password", post(reset_password_post))
+        .route("/users/pastes", get(search::search))
+        .route("/users/domains", get(domains::domains))
+        .route("/users/domains", post(domains::domains_post))
+        .route("/users/domains/:domain_id/verify", post(domains::verify))
+        .route("/users/domains/:domain_id/delete", post(domains::delete))
+        .route("/users/tokens", get(tokens::tokens))
+        .route("/users/tokens", post(tokens::tokens_post))
+        .route("/users/tokens/:token_id/delete", post(tokens::delete))
+        .nest("/admin", admin::router())
         .nest_service(
             "/static",
             get_service(ServeDir::new(concat!(
@@ -69,27 +363,87 @@ async fn start() -> anyhow::Result<()> {
                 )
             }),
         )
-        .layer(axum::middleware::from_fn_with_state(
-            state.clone(),
-            middleware::current_user_middleware,
-        ))
-        .layer(CookieManagerLayer::new())
-        .with_state(state);
+}
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
-        .await
-        .unwrap();
+/// A router that remembers the first path segment of its routes. Custom URLs can't use
+/// them, a paste called `users` would never be reachable.
+struct Routes {
+    router: Router<AppState>,
+    reserved: BTreeSet<String>,
+}
 
-    tracing::info!("listening on http://{}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await?;
+impl Routes {
+    fn new() -> Self {
+        Routes {
+            router: Router::new(),
+            reserved: BTreeSet::new(),
+        }
+    }
 
-    Ok(())
+    fn route(mut self, path: &str, method_router: MethodRouter<AppState>) -> Self {
+        self.reserve(path);
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    fn nest(mut self, path: &str, router: Router<AppState>) -> Self {
+        self.reserve(path);
+        self.router = self.router.nest(path, router);
+        self
+    }
+
+    fn nest_service<T>(mut self, path: &str, service: T) -> Self
+    where
+        T: Service<Request, Error = Infallible> + Clone + Send + 'static,
+        T::Response: IntoResponse,
+        T::Future: Send + 'static,
+    {
+        self.reserve(path);
+        self.router = self.router.nest_service(path, service);
+        self
+    }
+
+    fn reserve(&mut self, path: &str) {
+        let segment = path
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        // `/:paste_id` and friends are the pastes themselves
+        if !segment.is_empty() && !segment.starts_with([':', '*']) {
+            self.reserved.insert(segment.to_lowercase());
+        }
+    }
+}
+
+/// Validates a base URL from the environment and strips the trailing slash.
+fn parse_base_url(name: &str, value: &str) -> String {
+    let url =
+        url::Url::parse(value).unwrap_or_else(|e| panic!("{} is not a valid URL: {}", name, e));
+    if url.host_str().is_none() {
+        panic!("{} must include a host", name);
+    }
+    url.as_str().trim_end_matches('/').to_owned()
+}
+
+/// A tera function returning the same string every time.
+fn constant_function(value: String) -> impl tera::Function {
+    move |_: &std::collections::HashMap<String, tera::Value>| Ok(tera::Value::String(value.clone()))
 }
 
 #[derive(Clone)]
 struct AppState {
     templates: Tera,
     conn: DatabaseConnection,
+    captcha: Option<Arc<PowCaptcha>>,
+    clicks: Arc<ClickRecorder>,
+    force_redirect_preview: bool,
+    public_url: String,
+    short_url: String,
+    blobs: Arc<dyn BlobStore>,
+    /// Largest attachment in bytes.
+    max_attachment_size: usize,
+    paste_limits: PasteLimits,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -102,47 +456,72 @@ struct Flash {
 async fn root(
     current_user: Option<Extension<users::Model>>,
     state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
 ) -> Result<Html<String>, (StatusCode, &'static str)> {
     let mut ctx = tera::Context::new();
-    if let Some(user) = current_user {
-        ctx.insert("current_user", &user.0);
+    ctx.insert(
+        "max_paste_size",
+        &limits::format_size(state.paste_limits.for_user(current_user.as_deref())),
+    );
+    match current_user {
+        Some(user) => {
+            // custom domains the paste can be created on
+            let domains: Vec<_> = Query::get_user_domains(&state.conn, user.id)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Something went wrong: {}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong")
+                })?
+                .into_iter()
+                .filter(|d| d.verified_at.is_some())
+                .collect();
+            ctx.insert("domains", &domains);
+            ctx.insert("site_domain", &site_domain);
+            ctx.insert("current_user", &user.0);
+        }
+        None => insert_pow_challenge(&state, &mut ctx),
     }
+    ctx.insert("max_files", &service::MAX_PASTE_FILES);
 
     let body = state
         .templates
         .render("index.html.tera", &ctx)
         .map_err(|e| {
             tracing::error!("Error rendering template {}", e);
-            return (
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "error rendering template",
-            );
+            )
         })?;
 
     Ok(Html(body))
 }
 
+/// Adds a fresh proof-of-work challenge to the context when the captcha is enabled.
+fn insert_pow_challenge(state: &AppState, ctx: &mut tera::Context) {
+    if let Some(captcha) = state.captcha.as_ref() {
+        ctx.insert("pow_challenge", &captcha.issue());
+    }
+}
+
 async fn edit(
     current_user: Option<Extension<users::Model>>,
     state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
     Path(paste_id): Path<String>,
 ) -> Response {
     // check if a paste exists for the given paste id
-    let split_paste: Vec<_> = paste_id.split(".").collect();
-    let mut extension = "";
-    if split_paste.len() == 2 {
-        extension = split_paste[1];
-    }
-    let paste = Query::get_paste_by_id(&state.conn, split_paste[0])
+    let split_paste: Vec<_> = paste_id.split('.').collect();
+    let paste = Query::get_paste_by_id(&state.conn, &site_domain, split_paste[0])
         .await
         .map_err(|err| match err {
             DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found"),
             _ => (StatusCode::NOT_FOUND, "Not found"),
         });
-    if paste.is_err() {
-        return paste.unwrap_err().into_response();
-    }
-    let paste = paste.unwrap();
+    let paste = match paste {
+        Ok(paste) => paste,
+        Err(e) => return e.into_response(),
+    };
 
     // check if a user is logged in
     if current_user.is_none() {
@@ -154,10 +533,24 @@ async fn edit(
         return Redirect::to(format!("/{}", paste_id).as_str()).into_response();
     }
 
+    if let Some((status, message)) = moderation::hidden_status(&paste) {
+        return moderation::render_unavailable(&state, Some(&current_user), status, message);
+    }
+
     let mut ctx = tera::Context::new();
     ctx.insert("current_user", &current_user.0);
     ctx.insert("is_edit", &true);
-    ctx.insert("content", &paste.content);
+    ctx.insert(
+        "max_paste_size",
+        &limits::format_size(state.paste_limits.for_user(Some(&current_user))),
+    );
+    match Query::get_paste_files(&state.conn, &paste).await {
+        Ok(files) => insert_files(&mut ctx, files),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    }
 
     let body = state
         .templates
@@ -178,60 +571,309 @@ async fn edit(
 
 async fn post_edit(
     current_user: Option<Extension<users::Model>>,
+    api_token: Option<Extension<ApiToken>>,
     state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
     Path(paste_id): Path<String>,
-    form: Form<pastes::Model>,
-) -> Result<Redirect, (StatusCode, &'static str)> {
-    let form = form.0;
+    PasteForm(form): PasteForm,
+) -> Response {
     let user = current_user.map(|u| u.0);
 
     // if paste_id contains a ".", split on it
-    let split_paste: Vec<_> = paste_id.split(".").collect();
-    let mut extension = "";
-    if split_paste.len() == 2 {
-        extension = split_paste[1];
+    let split_paste: Vec<_> = paste_id.split('.').collect();
+
+    if api_token.is_none() && form.secrets.is_none() {
+        let findings: Vec<_> = form.contents().flat_map(service::scan_secrets).collect();
+        if !findings.is_empty() {
+            let action = format!("/{}/edit", split_paste[0]);
+            return render_secrets_warning(&state, user.as_ref(), &form, &findings, &action);
+        }
     }
 
-    let paste = Mutation::update_paste_content(&state.conn, &form, user, split_paste[0])
-        .await
-        .map_err(|err| match err {
-            DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found"),
-            _ => (StatusCode::NOT_FOUND, "Not found"),
-        })?;
+    let paste = match Mutation::update_paste_content(
+        &state.conn,
+        &form,
+        user.clone(),
+        &site_domain,
+        split_paste[0],
+    )
+    .await
+    {
+        Ok(paste) => paste,
+        Err(DbErr::Custom(msg)) if api_token.is_some() => {
+            return (StatusCode::UNPROCESSABLE_ENTITY, msg).into_response()
+        }
+        Err(DbErr::Custom(msg)) => {
+            return render_editor(
+                &state,
+                user.as_ref(),
+                &form,
+                &msg,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        }
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
 
-    let redirect_url = if paste.is_url {
-        format!("/v/{}", paste.id)
-    } else {
-        format!("/{}", paste.id)
+    Redirect::to(&domains::paste_location(&state, &site_domain, &paste)).into_response()
+}
+
+async fn rename(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) if paste.belongs_to == Some(user.id) => paste,
+        _ => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    render_rename(&state, &user, &paste, None).await
+}
+
+async fn rename_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+    form: Form<schema::RenamePost>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
     };
-    Ok(Redirect::to(&redirect_url))
+
+    match Mutation::rename_paste(&state.conn, &site_domain, &paste_id, &user, &form.new_id).await {
+        Ok(paste) => {
+            Redirect::to(&domains::paste_location(&state, &site_domain, &paste)).into_response()
+        }
+        Err(DbErr::Custom(msg)) => {
+            let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+                Ok(paste) => paste,
+                Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+            };
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            render_rename(&state, &user, &paste, Some(flash)).await
+        }
+        Err(DbErr::RecordNotFound(_)) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+async fn render_rename(
+    state: &AppState,
+    user: &users::Model,
+    paste: &entity::pastes::Model,
+    flash: Option<Flash>,
+) -> Response {
+    let aliases = match Query::get_paste_aliases(&state.conn, paste).await {
+        Ok(aliases) => aliases,
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", user);
+    ctx.insert("paste", paste);
+    ctx.insert("aliases", &aliases);
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+
+    let body = state
+        .templates
+        .render("rename.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Adds the files of a paste to the editor's context: `content`, `filename` and
+/// `language` of the first one and the others as `files`.
+fn insert_files(ctx: &mut tera::Context, mut files: Vec<service::PasteFile>) {
+    if files.is_empty() {
+        return;
+    }
+    let first = files.remove(0);
+    ctx.insert("content", &first.content);
+    ctx.insert("filename", &first.filename);
+    ctx.insert("language", &first.language);
+    ctx.insert("files", &files);
+    ctx.insert("max_files", &service::MAX_PASTE_FILES);
+}
+
+/// Re-renders the paste editor with the submitted content and a warning.
+fn render_editor(
+    state: &AppState,
+    current_user: Option<&users::Model>,
+    form: &schema::PastePost,
+    warning: &str,
+    status: StatusCode,
+) -> Response {
+    let mut ctx = tera::Context::new();
+    insert_files(&mut ctx, service::form_files(form));
+    ctx.insert(
+        "max_paste_size",
+        &limits::format_size(state.paste_limits.for_user(current_user)),
+    );
+    ctx.insert(
+        "flash",
+        &Flash {
+            info: None,
+            warn: Some(warning.to_owned()),
+        },
+    );
+    match current_user {
+        Some(user) => ctx.insert("current_user", user),
+        None => insert_pow_challenge(state, &mut ctx),
+    }
+
+    let body = state
+        .templates
+        .render("index.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => (status, Html(body)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Interstitial asking whether a paste that looks like it contains credentials
+/// should be published anyway or redacted first.
+fn render_secrets_warning(
+    state: &AppState,
+    current_user: Option<&users::Model>,
+    form: &schema::PastePost,
+    findings: &[service::SecretFinding],
+    action: &str,
+) -> Response {
+    let mut ctx = tera::Context::new();
+    ctx.insert("form", form);
+    ctx.insert("findings", findings);
+    ctx.insert("action", action);
+    if let Some(user) = current_user {
+        ctx.insert("current_user", user);
+    }
+
+    let body = state
+        .templates
+        .render("secrets.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
 }
 
 async fn create_paste(
     current_user: Option<Extension<users::Model>>,
+    api_token: Option<Extension<ApiToken>>,
     state: State<AppState>,
-    form: Form<pastes::Model>,
+    SiteDomain(site_domain): SiteDomain,
+    PasteForm(form): PasteForm,
 ) -> Response {
-    let form = form.0;
     let user = current_user.map(|u| u.0);
 
-    let create_result = Mutation::create_paste(&state.conn, &form, user).await;
+    // checked before the captcha so the solved challenge is still valid when the form comes back
+    if api_token.is_none() && form.secrets.is_none() {
+        let findings: Vec<_> = form.contents().flat_map(service::scan_secrets).collect();
+        if !findings.is_empty() {
+            return render_secrets_warning(&state, user.as_ref(), &form, &findings, "/");
+        }
+    }
+
+    // logged in users and API tokens are exempt from the proof-of-work check
+    if let (None, Some(captcha)) = (user.as_ref(), state.captcha.as_ref()) {
+        if let Err(err) =
+            captcha.verify(form.pow_challenge.as_deref(), form.pow_solution.as_deref())
+        {
+            tracing::debug!("Proof-of-work verification failed: {:?}", err);
+            return render_editor(
+                &state,
+                None,
+                &form,
+                "The anti-spam check could not be verified, please try again.",
+                StatusCode::FORBIDDEN,
+            );
+        }
+    }
+
+    let is_anonymous = user.is_none();
+    let domain = form.domain.clone().unwrap_or_else(|| site_domain.clone());
+    let create_result = Mutation::create_paste(&state.conn, &form, user.clone(), &domain).await;
     if let Err(error) = create_result {
+        if let DbErr::Custom(msg) = error {
+            if api_token.is_some() {
+                return (StatusCode::UNPROCESSABLE_ENTITY, msg).into_response();
+            }
+            return render_editor(
+                &state,
+                user.as_ref(),
+                &form,
+                &msg,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            );
+        }
+
         match error.sql_err() {
             Some(sql_err) => match sql_err {
                 SqlErr::UniqueConstraintViolation(_) => {
                     // this key already exists
                     // re-render the index page with a flash
                     let mut ctx = tera::Context::new();
-                    if form.custom_url.is_some() {
-                        ctx.insert(
-                            "flash",
-                            &Flash {
-                                info: None,
-                                warn: Some(String::from("This custom URL has already been taken.")),
-                            },
-                        );
+                    let warning = if form.custom_url.as_deref().is_some_and(|c| !c.is_empty()) {
+                        "This custom URL has already been taken."
+                    } else {
+                        "Could not find a free URL for this paste, please try again."
+                    };
+                    if api_token.is_some() {
+                        return (StatusCode::CONFLICT, warning).into_response();
+                    }
+                    ctx.insert(
+                        "flash",
+                        &Flash {
+                            info: None,
+                            warn: Some(String::from(warning)),
+                        },
+                    );
+                    if is_anonymous {
+                        insert_pow_challenge(&state, &mut ctx);
                     }
+                    ctx.insert(
+                        "max_paste_size",
+                        &limits::format_size(state.paste_limits.for_user(user.as_ref())),
+                    );
 
                     let body = state
                         .templates
@@ -262,42 +904,63 @@ async fn create_paste(
     }
 
     let paste = create_result.unwrap();
-    let redirect_url = if paste.is_url {
-        format!("/v/{}", paste.id)
-    } else {
-        format!("/{}", paste.id)
-    };
-    Redirect::to(&redirect_url).into_response()
+    Redirect::to(&domains::paste_location(&state, &site_domain, &paste)).into_response()
 }
 
 async fn show_paste(
     current_user: Option<Extension<users::Model>>,
     state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
     Path(paste_id): Path<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     request: Request,
 ) -> Response {
     // if paste_id contains a ".", split on it
-    let split_paste: Vec<_> = paste_id.split(".").collect();
+    let split_paste: Vec<_> = paste_id.split('.').collect();
     let mut extension = "";
     if split_paste.len() == 2 {
         extension = split_paste[1];
     }
 
-    let paste = Query::get_paste_by_id(&state.conn, split_paste[0])
+    let paste = Query::get_paste_by_id(&state.conn, &site_domain, split_paste[0])
         .await
         .map_err(|err| match err {
             DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found"),
             _ => (StatusCode::NOT_FOUND, "Not found"),
         });
-    if paste.is_err() {
-        return paste.unwrap_err().into_response();
+    let paste = match paste {
+        Ok(paste) => paste,
+        Err(e) => return e.into_response(),
+    };
+
+    // old id of a renamed paste, or a custom URL typed in another case
+    if paste.id != split_paste[0] {
+        let prefix = if request.uri().path().starts_with("/v/") {
+            "/v/"
+        } else {
+            "/"
+        };
+        let location = match extension {
+            "" => format!("{}{}", prefix, paste.id),
+            extension => format!("{}{}.{}", prefix, paste.id, extension),
+        };
+        return Redirect::permanent(&location).into_response();
     }
 
-    let paste = paste.unwrap();
+    if let Some((status, message)) = moderation::hidden_status(&paste) {
+        return moderation::render_unavailable(&state, current_user.as_deref(), status, message);
+    }
 
     if !request.uri().to_string().contains("/v/") && paste.is_url {
         tracing::debug!("Path is not for display, redirect to URL");
-        return Redirect::temporary(&paste.content).into_response();
+        return links::follow(
+            &state,
+            current_user.as_deref(),
+            &paste,
+            request.headers(),
+            peer,
+        )
+        .await;
     }
 
     let show_edit = match (paste.belongs_to, current_user.as_ref()) {
@@ -305,26 +968,43 @@ async fn show_paste(
         _ => false,
     };
 
+    let files = match Query::get_paste_files(&state.conn, &paste).await {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+    let attachments = match Query::get_paste_attachments(&state.conn, &paste).await {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
     let mut ctx = tera::Context::new();
     ctx.insert("paste", &paste);
     ctx.insert("extension", extension);
     ctx.insert("show_edit", &show_edit);
+    ctx.insert("files", &files);
+    ctx.insert("attachments", &attachments);
+    ctx.insert("max_attachments", &service::MAX_ATTACHMENTS_PER_PASTE);
     if let Some(user) = current_user {
         ctx.insert("current_user", &user.0);
     }
 
     let body = state.templates.render("show.html.tera", &ctx).map_err(|e| {
         tracing::error!("Error rendering template {}", e);
-        return (
+        (
             StatusCode::INTERNAL_SERVER_ERROR,
             "error rendering template",
-        );
+        )
     });
-    if body.is_err() {
-        return body.unwrap_err().into_response();
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
     }
-
-    Html(body.unwrap()).into_response()
 }
 
 async fn login(state: State<AppState>) -> Result<Html<String>, (StatusCode, &'static str)> {
@@ -374,10 +1054,10 @@ async fn login_post(
                             "error rendering template",
                         )
                     });
-                if body_res.is_err() {
-                    return body_res.unwrap_err().into_response();
+                match body_res {
+                    Ok(body) => Html(body).into_response(),
+                    Err(e) => e.into_response(),
                 }
-                return Html(body_res.unwrap()).into_response();
             }
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response(),
         }
@@ -413,7 +1093,7 @@ async fn register_post(
     let form = form.0;
     let user_res = Mutation::register(&state.conn, &form).await;
 
-    if let Some(_) = current_user {
+    if current_user.is_some() {
         return Redirect::to("/").into_response();
     }
 
@@ -439,10 +1119,10 @@ async fn register_post(
                             "error rendering template",
                         )
                     });
-                if body_res.is_err() {
-                    return body_res.unwrap_err().into_response();
+                match body_res {
+                    Ok(body) => Html(body).into_response(),
+                    Err(e) => e.into_response(),
                 }
-                return Html(body_res.unwrap()).into_response();
             }
             DbErr::Custom(custom) => {
                 let mut ctx = tera::Context::new();
@@ -464,14 +1144,14 @@ async fn register_post(
                             "error rendering template",
                         )
                     });
-                if body_res.is_err() {
-                    return body_res.unwrap_err().into_response();
+                match body_res {
+                    Ok(body) => Html(body).into_response(),
+                    Err(e) => e.into_response(),
                 }
-                return Html(body_res.unwrap()).into_response();
             }
             e => {
                 tracing::error!("Something went wrong: {}", e);
-                return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+                (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
             }
         }
     } else {
@@ -479,10 +1159,262 @@ async fn register_post(
     }
 }
 
+async fn reset_password(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+) -> Response {
+    let Some(current_user) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &current_user.0);
+    if current_user.password_reset_required {
+        ctx.insert(
+            "flash",
+            &Flash {
+                info: None,
+                warn: Some(String::from(
+                    "An administrator has asked you to choose a new password.",
+                )),
+            },
+        );
+    }
+
+    let body = state
+        .templates
+        .render("reset_password.html.tera", &ctx)
+        .map_err(|err| {
+            tracing::error!("error rendering template {}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn reset_password_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    form: Form<schema::PasswordResetPost>,
+) -> Response {
+    let Some(current_user) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    match Mutation::reset_password(&state.conn, current_user.0.clone(), &form.0).await {
+        Ok(_) => Redirect::to("/").into_response(),
+        Err(DbErr::Custom(msg)) => {
+            let mut ctx = tera::Context::new();
+            ctx.insert("current_user", &current_user.0);
+            ctx.insert(
+                "flash",
+                &Flash {
+                    info: None,
+                    warn: Some(msg),
+                },
+            );
+
+            let body = state
+                .templates
+                .render("reset_password.html.tera", &ctx)
+                .map_err(|err| {
+                    tracing::error!("error rendering template {}", err);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "error rendering template",
+                    )
+                });
+            match body {
+                Ok(body) => Html(body).into_response(),
+                Err(e) => e.into_response(),
+            }
+        }
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Compresses pastes stored before compression was turned on, or while the threshold
+/// was higher. Run as `katbin backfill-compression`.
+#[tokio::main]
+async fn backfill_compression() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not found in environment");
+    service::set_compression_threshold(compression_threshold());
+
+    let conn = Database::connect(ConnectOptions::new(db_url)).await?;
+    let count = Mutation::compress_stored_contents(&conn).await?;
+    println!("compressed {} paste content(s)", count);
+    Ok(())
+}
+
+/// Indexes every paste again, for a new embedded search index or after changes to how
+/// pastes are indexed. Run as `katbin rebuild-search-index`.
+#[tokio::main]
+async fn rebuild_search_index() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not found in environment");
+    open_search_index()?;
+
+    let conn = Database::connect(ConnectOptions::new(db_url)).await?;
+    let count = Mutation::rebuild_search_index(&conn).await?;
+    println!("indexed {} paste(s)", count);
+    Ok(())
+}
+
+/// Applies the pending database migrations, so a single binary can set up its own
+/// database, e.g. `DATABASE_URL=sqlite://katbin.db?mode=rwc katbin migrate` with the
+/// sqlite feature. Run as `katbin migrate`.
+#[tokio::main]
+async fn migrate() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not found in environment");
+
+    let applied = apply_migrations(&db_url).await?;
+    println!("applied {} migration(s)", applied);
+    Ok(())
+}
+
+/// Key of the Postgres advisory lock held while migrating, "katbin" in ASCII.
+const MIGRATION_LOCK: i64 = 0x6b617462696e;
+
+/// Applies the pending migrations in one transaction and returns how many there were.
+/// Instances starting at the same time wait for each other on an advisory lock, the
+/// ones after the first find nothing left to do. Fails without changing anything when
+/// [`check_schema`] does.
+async fn apply_migrations(db_url: &str) -> anyhow::Result<usize> {
+    // the migrations are on a newer sea-orm than the service and need their own connection
+    let conn = migration::sea_orm::Database::connect(db_url).await?;
+    let txn = conn.begin().await?;
+    // SQLite locks the whole database for the first write of a transaction instead
+    if txn.get_database_backend() == DbBackend::Postgres {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_advisory_xact_lock($1)",
+            [MIGRATION_LOCK.into()],
+        ))
+        .await?;
+    }
+
+    check_schema(&txn).await?;
+    let pending = Migrator::get_pending_migrations(&txn).await?.len();
+    Migrator::up(&txn, None).await?;
+    txn.commit().await?;
+    Ok(pending)
+}
+
+/// Fails when the database has migrations this build doesn't know, as it would run
+/// against a schema a newer version migrated it to. Only reads, a database nothing was
+/// applied to yet is left without a migrations table.
+async fn check_schema<'c>(db: impl IntoSchemaManagerConnection<'c>) -> anyhow::Result<()> {
+    let manager = SchemaManager::new(db);
+    // listing the applied migrations would create the table
+    let table = Migrator::migration_table_name().to_string();
+    if !manager.has_table(&table).await? {
+        return Ok(());
+    }
+
+    let known: Vec<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_owned())
+        .collect();
+    let unknown: Vec<String> = Migrator::get_migration_models(manager.get_connection())
+        .await?
+        .into_iter()
+        .map(|model| model.version)
+        .filter(|version| !known.contains(version))
+        .collect();
+    if !unknown.is_empty() {
+        anyhow::bail!(
+            "the database has migrations this version of katbin doesn't know ({}), it was migrated by a newer version",
+            unknown.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tantivy")]
+fn open_search_index() -> anyhow::Result<()> {
+    if let Ok(path) = env::var("SEARCH_INDEX_PATH") {
+        service::open_search_index(std::path::Path::new(&path))?;
+        tracing::info!("using the search index in {}", path);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tantivy"))]
+fn open_search_index() -> anyhow::Result<()> {
+    if env::var("SEARCH_INDEX_PATH").is_ok() {
+        anyhow::bail!("SEARCH_INDEX_PATH is set, but katbin was built without the tantivy feature");
+    }
+    Ok(())
+}
+
+/// Size in bytes from which paste content is stored compressed, 0 turns it off.
+fn compression_threshold() -> usize {
+    env::var("COMPRESSION_THRESHOLD")
+        .map(|size| {
+            size.parse()
+                .expect("COMPRESSION_THRESHOLD must be a number")
+        })
+        .unwrap_or(service::DEFAULT_COMPRESSION_THRESHOLD)
+}
+
 pub fn main() {
-    let result = start();
+    let result = match env::args().nth(1).as_deref() {
+        Some("backfill-compression") => backfill_compression(),
+        Some("rebuild-search-index") => rebuild_search_index(),
+        Some("migrate") => migrate(),
+        _ => start(),
+    };
 
     if let Some(err) = result.err() {
         println!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_prefixes_are_reserved() {
+        let reserved = routes(1024).reserved;
+        for segment in ["admin", "api", "links", "raw", "static", "users", "v"] {
+            assert!(reserved.contains(segment), "{} isn't reserved", segment);
+        }
+        assert!(!reserved.iter().any(|s| s.is_empty() || s.starts_with(':')));
+    }
+
+    #[tokio::test]
+    async fn schema_check_only_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("katbin.db").display()
+        );
+        let db = migration::sea_orm::Database::connect(&url).await.unwrap();
+        let table = Migrator::migration_table_name().to_string();
+
+        check_schema(&db).await.unwrap();
+        assert!(!SchemaManager::new(&db).has_table(&table).await.unwrap());
+
+        Migrator::up(&db, None).await.unwrap();
+        check_schema(&db).await.unwrap();
+
+        db.execute_unprepared(
+            "INSERT INTO seaql_migrations (version, applied_at) VALUES ('m29990101_000001_future', 0)",
+        )
+        .await
+        .unwrap();
+        assert!(check_schema(&db).await.is_err());
     }
 }
@@ -0,0 +1,144 @@
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::{FromRequest, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use entity::schema;
+use entity::users::{self, Role};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::middleware::ApiToken;
+use crate::{moderation, AppState};
+
+/// Largest paste in bytes, counting the content of all its files, by who is posting it.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct PasteLimits {
+    pub anonymous: usize,
+    pub registered: usize,
+    pub admin: usize,
+}
+
+impl PasteLimits {
+    pub fn for_user(&self, user: Option<&users::Model>) -> usize {
+        match user.map(|u| u.role) {
+            None => self.anonymous,
+            Some(Role::User) => self.registered,
+            Some(Role::Admin) => self.admin,
+        }
+    }
+}
+
+/// A paste form, read as a stream and given up on as soon as it can't fit the poster's
+/// [`PasteLimits`]. Oversized pastes get a `413`, as a page or as JSON for API clients.
+pub struct PasteForm(pub schema::PastePost);
+
+#[async_trait]
+impl FromRequest<AppState> for PasteForm {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let user = request.extensions().get::<users::Model>().cloned();
+        let json =
+            request.extensions().get::<ApiToken>().is_some() || wants_json(request.headers());
+        let limit = state.paste_limits.for_user(user.as_ref());
+        // percent-encoding can triple the size of the content, the rest of the form is small
+        let max_body = limit.saturating_mul(3).saturating_add(64 * 1024);
+
+        let declared = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.parse::<usize>().ok());
+        if declared.is_some_and(|length| length > max_body) {
+            return Err(too_large(state, user.as_ref(), limit, json));
+        }
+
+        let (parts, body) = request.into_parts();
+        let bytes = match Limited::new(body, max_body).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) if e.is::<LengthLimitError>() => {
+                return Err(too_large(state, user.as_ref(), limit, json))
+            }
+            Err(e) => {
+                tracing::debug!("Error reading paste form: {}", e);
+                return Err(
+                    (StatusCode::BAD_REQUEST, "failed to read the request body").into_response()
+                );
+            }
+        };
+
+        // the extra files come as repeated fields, which only axum_extra's extractor reads
+        let request = Request::from_parts(parts, Body::from(bytes));
+        let form = axum_extra::extract::Form::<schema::PastePost>::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?
+            .0;
+
+        let size: usize = form.contents().map(str::len).sum();
+        if size > limit {
+            return Err(too_large(state, user.as_ref(), limit, json));
+        }
+        Ok(PasteForm(form))
+    }
+}
+
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"))
+}
+
+fn too_large(
+    state: &AppState,
+    current_user: Option<&users::Model>,
+    limit: usize,
+    json: bool,
+) -> Response {
+    let mut message = format!("Pastes can be at most {}.", format_size(limit));
+    if current_user.is_none() && state.paste_limits.registered > limit {
+        message.push_str(&format!(
+            " Log in to post pastes of up to {}.",
+            format_size(state.paste_limits.registered)
+        ));
+    }
+
+    if json {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({ "error": message, "limit": limit })),
+        )
+            .into_response();
+    }
+    moderation::render_unavailable(state, current_user, StatusCode::PAYLOAD_TOO_LARGE, &message)
+}
+
+/// A size for people, in the largest unit it's a whole number of.
+pub fn format_size(bytes: usize) -> String {
+    const KIB: usize = 1024;
+    const MIB: usize = 1024 * KIB;
+    if bytes >= MIB && bytes.is_multiple_of(MIB) {
+        format!("{} MiB", bytes / MIB)
+    } else if bytes >= KIB && bytes.is_multiple_of(KIB) {
+        format!("{} KiB", bytes / KIB)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// The limits that apply to the caller, along with the paste size limits for everyone.
+pub async fn limits(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+) -> Json<serde_json::Value> {
+    Json(json!({
+        "max_paste_size": state.paste_limits.for_user(current_user.as_deref()),
+        "paste_size_limits": state.paste_limits,
+        "max_files": service::MAX_PASTE_FILES,
+        "max_attachment_size": state.max_attachment_size,
+        "max_attachments": service::MAX_ATTACHMENTS_PER_PASTE,
+    }))
+}
@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use entity::pastes;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, StatusCode};
+use service::sea_orm::DatabaseConnection;
+use service::{Mutation, Query};
+use tokio::task::JoinSet;
+use url::{Host, Url};
+
+/// Links checked in one round.
+const BATCH_SIZE: u64 = 200;
+/// Links checked at the same time.
+const CONCURRENCY: usize = 8;
+/// Sent with every request so site owners can tell the checker apart in their logs
+/// and address it in robots.txt.
+const USER_AGENT: &str = concat!("katbin-link-checker/", env!("CARGO_PKG_VERSION"));
+/// The name matched against `User-agent` lines in robots.txt.
+const ROBOTS_AGENT: &str = "katbin";
+/// Redirects followed before a link counts as failed.
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Clone, Debug)]
+pub struct LinkCheckerConfig {
+    /// Time between two rounds of checks.
+    pub interval: Duration,
+    /// How long a link stays healthy before it's checked again.
+    pub recheck_after: Duration,
+    /// Timeout for every request, including robots.txt.
+    pub timeout: Duration,
+    /// Failed checks in a row before a link is marked broken.
+    pub failures: u64,
+    /// Whether links to loopback, private and link-local addresses are checked too. Off
+    /// by default, short links could otherwise make the checker probe the instance's own
+    /// network or a cloud metadata endpoint.
+    pub allow_private: bool,
+}
+
+/// Outcome of checking a single link.
+struct CheckResult {
+    healthy: bool,
+    status_code: Option<StatusCode>,
+    error: Option<String>,
+}
+
+/// Spawns the background task that periodically checks where short links point to.
+pub fn start(conn: DatabaseConnection, config: LinkCheckerConfig) -> anyhow::Result<()> {
+    let client = client(&config)?;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = check_links(&conn, &client, &config).await {
+                tracing::error!("Error checking links: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn client(config: &LinkCheckerConfig) -> reqwest::Result<Client> {
+    let builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(config.timeout);
+    if config.allow_private {
+        return builder.build();
+    }
+
+    // hostnames are checked when they are resolved, IP addresses in redirects here
+    let redirects = redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !has_public_host(attempt.url()) {
+            attempt.error("redirected to a private address")
+        } else {
+            attempt.follow()
+        }
+    });
+    builder
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(redirects)
+        .build()
+}
+
+/// Runs one round of checks over the links that are due.
+async fn check_links(
+    conn: &DatabaseConnection,
+    client: &Client,
+    config: &LinkCheckerConfig,
+) -> anyhow::Result<()> {
+    let checked_before = Utc::now().naive_utc() - chrono::Duration::from_std(config.recheck_after)?;
+    let links = Query::get_links_to_check(conn, checked_before, BATCH_SIZE).await?;
+    if links.is_empty() {
+        return Ok(());
+    }
+    tracing::debug!("checking {} link(s)", links.len());
+
+    // robots.txt is fetched once per origin and round
+    let mut robots: HashMap<String, Arc<Vec<String>>> = HashMap::new();
+    let mut tasks = JoinSet::new();
+    for paste in links {
+        let url = match Url::parse(&paste.content) {
+            Ok(url) if is_checkable(config, &url) => url,
+            _ => {
+                Mutation::skip_link_check(conn, &paste).await?;
+                continue;
+            }
+        };
+        let origin = url.origin().ascii_serialization();
+        let disallowed = match robots.get(&origin) {
+            Some(disallowed) => disallowed.clone(),
+            None => {
+                let disallowed = Arc::new(fetch_robots(client, &origin).await);
+                robots.insert(origin, disallowed.clone());
+                disallowed
+            }
+        };
+        if is_disallowed(&disallowed, &url) {
+            tracing::debug!("robots.txt disallows checking {}", url);
+            Mutation::skip_link_check(conn, &paste).await?;
+            continue;
+        }
+
+        if tasks.len() >= CONCURRENCY {
+            if let Some(finished) = tasks.join_next().await {
+                record(conn, config, finished?).await?;
+            }
+        }
+        let client = client.clone();
+        tasks.spawn(async move {
+            let result = check_url(&client, url).await;
+            (paste, result)
+        });
+    }
+    while let Some(finished) = tasks.join_next().await {
+        record(conn, config, finished?).await?;
+    }
+
+    Ok(())
+}
+
+async fn record(
+    conn: &DatabaseConnection,
+    config: &LinkCheckerConfig,
+    (paste, result): (pastes::Model, CheckResult),
+) -> anyhow::Result<()> {
+    let was_broken = paste.broken_at.is_some();
+    let paste = Mutation::record_link_check(
+        conn,
+        &paste,
+        result.healthy,
+        result.status_code.map(|s| s.as_u16() as i16),
+        result.error,
+        config.failures,
+    )
+    .await?;
+
+    match (was_broken, paste.broken_at.is_some()) {
+        (false, true) => tracing::info!("short link {} is broken: {}", paste.id, paste.content),
+        (true, false) => tracing::info!("short link {} works again", paste.id),
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Sends a HEAD request, falling back to GET for servers that don't handle HEAD properly.
+async fn check_url(client: &Client, url: Url) -> CheckResult {
+    let head = client.head(url.clone()).send().await;
+    if let Ok(response) = &head {
+        if !is_failure(response.status()) {
+            return CheckResult {
+                healthy: true,
+                status_code: Some(response.status()),
+                error: None,
+            };
+        }
+    }
+
+    // only the status matters, the body is never read
+    match client.get(url).send().await {
+        Ok(response) => CheckResult {
+            healthy: !is_failure(response.status()),
+            status_code: Some(response.status()),
+            error: None,
+        },
+        Err(e) => CheckResult {
+            healthy: false,
+            status_code: None,
+            error: Some(describe_error(&e)),
+        },
+    }
+}
+
+/// Only web links are checked, and only on public addresses unless configured otherwise.
+fn is_checkable(config: &LinkCheckerConfig, url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https") && (config.allow_private || has_public_host(url))
+}
+
+/// Whether the host of `url` isn't an IP address on a private network. Hostnames are
+/// checked by [`PublicResolver`] once they are resolved.
+fn has_public_host(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => is_public(ip.into()),
+        Some(Host::Ipv6(ip)) => is_public(ip.into()),
+        Some(Host::Domain(_)) => true,
+        None => false,
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local, fc00::/7
+                    || first & 0xfe00 == 0xfc00
+                    // link-local, fe80::/10
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Resolves hostnames with the system resolver and drops every address that isn't
+/// public, a hostname with only private addresses fails to connect.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Rate limiting or a login wall doesn't mean the link is gone.
+fn is_failure(status: StatusCode) -> bool {
+    (status.is_client_error() || status.is_server_error())
+        && !matches!(
+            status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+        )
+}
+
+fn describe_error(error: &reqwest::Error) -> String {
+    let description = if error.is_timeout() {
+        String::from("timed out")
+    } else if error.is_connect() {
+        String::from("could not connect")
+    } else if error.is_redirect() {
+        error
+            .source()
+            .map_or_else(|| String::from("too many redirects"), |e| e.to_string())
+    } else {
+        error.to_string()
+    };
+    description.chars().take(255).collect()
+}
+
+/// Paths the checker isn't allowed to request on `origin`. A missing or unreadable
+/// robots.txt allows everything.
+async fn fetch_robots(client: &Client, origin: &str) -> Vec<String> {
+    let response = match client.get(format!("{}/robots.txt", origin)).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return Vec::new(),
+    };
+    match response.text().await {
+        Ok(body) => disallowed_paths(&body),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The `Disallow` rules for this checker from a robots.txt file. Rules for `katbin`
+/// take precedence over the ones for `*`. `Allow` lines and wildcards aren't supported,
+/// rules are matched as path prefixes.
+fn disallowed_paths(robots: &str) -> Vec<String> {
+    let mut own = None::<Vec<String>>;
+    let mut any = None::<Vec<String>>;
+    // user agents of the group being read, and whether its rules have started
+    let mut agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((field, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match field.trim().to_lowercase().as_str() {
+            "user-agent" => {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_lowercase());
+            }
+            "disallow" | "allow" => {
+                in_rules = true;
+                let group = if agents.iter().any(|a| a.contains(ROBOTS_AGENT)) {
+                    own.get_or_insert_with(Vec::new)
+                } else if agents.iter().any(|a| a == "*") {
+                    any.get_or_insert_with(Vec::new)
+                } else {
+                    continue;
+                };
+                if field.trim().eq_ignore_ascii_case("disallow") && !value.is_empty() {
+                    group.push(value.to_owned());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    own.or(any).unwrap_or_default()
+}
+
+fn is_disallowed(disallowed: &[String], url: &Url) -> bool {
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    disallowed
+        .iter()
+        .any(|rule| path.starts_with(rule.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+
+    use axum::routing::{get, head};
+    use axum::Router;
+    use entity::schema::PastePost;
+    use migration::{Migrator, MigratorTrait};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// What the stub server answers, changed by the tests while it runs.
+    #[derive(Default)]
+    struct Stub {
+        status: AtomicU16,
+        private_hits: AtomicUsize,
+    }
+
+    /// Serves a site with a few kinds of links on a free port of 127.0.0.1.
+    async fn serve(stub: Arc<Stub>) -> SocketAddr {
+        let status = stub.clone();
+        let app = Router::new()
+            .route(
+                "/no-head",
+                head(|| async { StatusCode::METHOD_NOT_ALLOWED }).get(|| async { "ok" }),
+            )
+            .route("/gone", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "finally"
+                }),
+            )
+            .route(
+                "/robots.txt",
+                get(|| async {
+                    "User-agent: *\nDisallow: /\n\nUser-agent: katbin\nDisallow: /private\n"
+                }),
+            )
+            .route(
+                "/private/page",
+                get(move || async move {
+                    stub.private_hits.fetch_add(1, Ordering::SeqCst);
+                    "secret"
+                }),
+            )
+            .route(
+                "/flaky",
+                get(move || async move {
+                    StatusCode::from_u16(status.status.load(Ordering::SeqCst)).unwrap()
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn config(allow_private: bool) -> LinkCheckerConfig {
+        LinkCheckerConfig {
+            interval: Duration::from_secs(60),
+            recheck_after: Duration::ZERO,
+            timeout: Duration::from_secs(2),
+            failures: 2,
+            allow_private,
+        }
+    }
+
+    fn url(addr: SocketAddr, path: &str) -> Url {
+        Url::parse(&format!("http://{}{}", addr, path)).unwrap()
+    }
+
+    async fn database() -> (TempDir, DatabaseConnection) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("katbin.db").display()
+        );
+        let migrations = migration::sea_orm::Database::connect(&url).await.unwrap();
+        Migrator::up(&migrations, None).await.unwrap();
+        let conn = service::sea_orm::Database::connect(&url).await.unwrap();
+        (dir, conn)
+    }
+
+    async fn short_link(conn: &DatabaseConnection, url: Url) -> pastes::Model {
+        let form = PastePost {
+            content: url.to_string(),
+            ..Default::default()
+        };
+        let paste = Mutation::create_paste(conn, &form, None, "").await.unwrap();
+        assert!(paste.is_url);
+        paste
+    }
+
+    async fn reload(conn: &DatabaseConnection, paste: &pastes::Model) -> pastes::Model {
+        Query::get_stored_paste_by_id(conn, &paste.domain, &paste.id)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_get() {
+        let addr = serve(Arc::default()).await;
+        let client = client(&config(true)).unwrap();
+
+        let result = check_url(&client, url(addr, "/no-head")).await;
+        assert!(result.healthy);
+        assert_eq!(result.status_code, Some(StatusCode::OK));
+
+        let result = check_url(&client, url(addr, "/gone")).await;
+        assert!(!result.healthy);
+        assert_eq!(result.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn slow_links_time_out() {
+        let addr = serve(Arc::default()).await;
+        let client = client(&LinkCheckerConfig {
+            timeout: Duration::from_millis(200),
+            ..config(true)
+        })
+        .unwrap();
+
+        let result = check_url(&client, url(addr, "/slow")).await;
+        assert!(!result.healthy);
+        assert_eq!(result.status_code, None);
+        assert_eq!(result.error.as_deref(), Some("timed out"));
+    }
+
+    #[test]
+    fn robots_rules() {
+        let robots = "User-agent: *\nDisallow: /\n\n# for us\nUser-agent: Googlebot\nUser-agent: katbin\nAllow: /public\nDisallow: /private # not here\nDisallow:\n";
+        assert_eq!(disallowed_paths(robots), vec!["/private"]);
+        assert_eq!(disallowed_paths("User-agent: *\nDisallow: /\n"), vec!["/"]);
+        assert!(disallowed_paths("User-agent: other\nDisallow: /\n").is_empty());
+        assert!(disallowed_paths("").is_empty());
+
+        let rules = vec![String::from("/private"), String::from("/search?")];
+        let base = Url::parse("https://example.com").unwrap();
+        assert!(is_disallowed(&rules, &base.join("/private/page").unwrap()));
+        assert!(is_disallowed(&rules, &base.join("/search?q=x").unwrap()));
+        assert!(!is_disallowed(&rules, &base.join("/search").unwrap()));
+        assert!(!is_disallowed(&rules, &base.join("/").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn private_addresses_are_refused() {
+        let addr = serve(Arc::default()).await;
+        let config = config(false);
+
+        assert!(!is_checkable(&config, &url(addr, "/no-head")));
+        assert!(is_checkable(
+            &config,
+            &Url::parse("https://example.com/").unwrap()
+        ));
+        assert!(!is_checkable(
+            &config,
+            &Url::parse("ftp://example.com/").unwrap()
+        ));
+
+        // names are refused once they resolve to a private address
+        let client = client(&config).unwrap();
+        let local = Url::parse(&format!("http://localhost:{}/no-head", addr.port())).unwrap();
+        let result = check_url(&client, local).await;
+        assert!(!result.healthy);
+        assert_eq!(result.status_code, None);
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} isn't public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn records_broken_and_healthy_links() {
+        let stub = Arc::new(Stub::default());
+        stub.status.store(500, Ordering::SeqCst);
+        let addr = serve(stub.clone()).await;
+        let (_dir, conn) = database().await;
+        let config = config(true);
+        let client = client(&config).unwrap();
+
+        let flaky = short_link(&conn, url(addr, "/flaky")).await;
+        let private = short_link(&conn, url(addr, "/private/page")).await;
+
+        // broken once it failed as often as configured
+        check_links(&conn, &client, &config).await.unwrap();
+        assert!(reload(&conn, &flaky).await.broken_at.is_none());
+        check_links(&conn, &client, &config).await.unwrap();
+        let paste = reload(&conn, &flaky).await;
+        assert!(paste.broken_at.is_some());
+        let checks = Query::get_link_checks(&conn, &paste, 10).await.unwrap();
+        assert_eq!(checks.len(), 2);
+        assert!(checks
+            .iter()
+            .all(|c| !c.healthy && c.status_code == Some(500)));
+
+        // and healthy again after the first good check
+        stub.status.store(200, Ordering::SeqCst);
+        check_links(&conn, &client, &config).await.unwrap();
+        let paste = reload(&conn, &flaky).await;
+        assert!(paste.broken_at.is_none());
+        let checks = Query::get_link_checks(&conn, &paste, 10).await.unwrap();
+        assert_eq!(checks.len(), 3);
+        assert!(checks[0].healthy);
+
+        // robots.txt keeps the checker away from the other link
+        let paste = reload(&conn, &private).await;
+        assert!(paste.checked_at.is_some());
+        assert!(Query::get_link_checks(&conn, &paste, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(stub.private_hits.load(Ordering::SeqCst), 0);
+    }
+}
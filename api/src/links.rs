@@ -0,0 +1,403 @@
+use std::net::SocketAddr;
+
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form, Json};
+use chrono::Utc;
+use entity::{pastes, schema, users};
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::json;
+use service::sea_orm::DbErr;
+use service::{LinkBatch, Mutation, Query};
+
+use crate::domains::SiteDomain;
+use crate::{moderation, AppState, Flash};
+
+/// Sends a visitor on to the destination of a short link, honouring its redirect settings.
+pub async fn follow(
+    state: &AppState,
+    current_user: Option<&users::Model>,
+    paste: &pastes::Model,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+) -> Response {
+    if paste
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    {
+        return moderation::render_unavailable(
+            state,
+            current_user,
+            StatusCode::GONE,
+            "This link has expired.",
+        );
+    }
+
+    match Mutation::register_limited_click(&state.conn, paste).await {
+        Ok(true) => (),
+        Ok(false) => {
+            return moderation::render_unavailable(
+                state,
+                current_user,
+                StatusCode::GONE,
+                "This link has reached its click limit.",
+            )
+        }
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    }
+
+    state.clicks.record(paste, headers, peer);
+
+    if paste.preview || state.force_redirect_preview {
+        let mut ctx = tera::Context::new();
+        ctx.insert("url", &paste.content);
+        if let Some(user) = current_user {
+            ctx.insert("current_user", user);
+        }
+        return render(state, "redirect.html.tera", &ctx);
+    }
+
+    match paste.redirect_code {
+        308 => Redirect::permanent(&paste.content).into_response(),
+        // axum only has helpers for 303, 307 and 308
+        code @ (301 | 302) => (
+            StatusCode::from_u16(code as u16).unwrap(),
+            [(header::LOCATION, paste.content.as_str())],
+        )
+            .into_response(),
+        _ => Redirect::temporary(&paste.content).into_response(),
+    }
+}
+
+pub async fn settings(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+) -> Response {
+    let user = match current_user {
+        Some(Extension(user)) => user,
+        None => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) if paste.is_url && paste.belongs_to == Some(user.id) => paste,
+        _ => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    render_settings(&state, &user, &paste, None)
+}
+
+pub async fn settings_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+    form: Form<schema::LinkSettingsPost>,
+) -> Response {
+    let user = match current_user {
+        Some(Extension(user)) => user,
+        None => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    match Mutation::update_link_settings(&state.conn, &site_domain, &paste_id, &user, &form.0).await
+    {
+        Ok(paste) => {
+            let flash = Flash {
+                info: Some(String::from("Link settings saved.")),
+                warn: None,
+            };
+            render_settings(&state, &user, &paste, Some(flash))
+        }
+        Err(DbErr::Custom(msg)) => {
+            let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+                Ok(paste) => paste,
+                Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+            };
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            render_settings(&state, &user, &paste, Some(flash))
+        }
+        Err(DbErr::RecordNotFound(_)) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+fn render_settings(
+    state: &AppState,
+    user: &users::Model,
+    paste: &pastes::Model,
+    flash: Option<Flash>,
+) -> Response {
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", user);
+    ctx.insert("paste", paste);
+    ctx.insert(
+        "expires_at",
+        &paste
+            .expires_at
+            .map(|at| at.format("%Y-%m-%dT%H:%M").to_string())
+            .unwrap_or_default(),
+    );
+    ctx.insert("force_preview", &state.force_redirect_preview);
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+    render(state, "link_settings.html.tera", &ctx)
+}
+
+fn render(state: &AppState, template: &str, ctx: &tera::Context) -> Response {
+    let body = state.templates.render(template, ctx).map_err(|e| {
+        tracing::error!("Error rendering template {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "error rendering template",
+        )
+    });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Shortens many links at once, see [`Mutation::create_links`].
+///
+/// Clients can send an `Idempotency-Key` header so a retried batch doesn't create
+/// the links twice.
+pub async fn batch(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    headers: HeaderMap,
+    Json(mut form): Json<schema::LinkBatchPost>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "You need to be logged in to shorten links in bulk." })),
+        )
+            .into_response();
+    };
+    let idempotency_key = headers.get("idempotency-key").and_then(|h| h.to_str().ok());
+
+    let domain = form.domain.take().unwrap_or(site_domain);
+    match Mutation::create_links(&state.conn, &user, &domain, &form, idempotency_key).await {
+        Ok(batch) => Json(batch_response(&state, &batch)).into_response(),
+        Err(DbErr::Custom(msg)) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": msg })),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "something went wrong" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn import(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    render_import(&state, &user, &site_domain, None, None).await
+}
+
+/// Shortens the links in an uploaded CSV file with `url` and optional `custom_url` columns.
+pub async fn import_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    mut multipart: Multipart,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    let mut file = None;
+    let mut domain = None;
+    let mut idempotency_key = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return e.into_response(),
+        };
+        let name = field.name().unwrap_or_default().to_owned();
+        let value = match field.bytes().await {
+            Ok(value) => value,
+            Err(e) => return e.into_response(),
+        };
+        match name.as_str() {
+            "file" => file = Some(value),
+            "domain" => domain = Some(String::from_utf8_lossy(&value).into_owned()),
+            "idempotency_key" => {
+                idempotency_key = Some(String::from_utf8_lossy(&value).into_owned())
+            }
+            _ => (),
+        }
+    }
+
+    let links = match file.as_deref().map(parse_links_csv) {
+        Some(Ok(links)) => links,
+        Some(Err(msg)) => {
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            return render_import(&state, &user, &site_domain, Some(flash), None).await;
+        }
+        None => return (StatusCode::BAD_REQUEST, "missing file").into_response(),
+    };
+    let form = schema::LinkBatchPost {
+        links,
+        domain: None,
+    };
+    let domain = domain.unwrap_or_else(|| site_domain.clone());
+
+    match Mutation::create_links(
+        &state.conn,
+        &user,
+        &domain,
+        &form,
+        idempotency_key.as_deref(),
+    )
+    .await
+    {
+        Ok(batch) => {
+            let created = batch
+                .links
+                .iter()
+                .filter(|l| l.status == service::LinkStatus::Created)
+                .count();
+            let flash = Flash {
+                info: Some(format!(
+                    "Shortened {} of {} links.",
+                    created,
+                    batch.links.len()
+                )),
+                warn: None,
+            };
+            let results = batch_response(&state, &batch);
+            render_import(&state, &user, &site_domain, Some(flash), Some(results)).await
+        }
+        Err(DbErr::Custom(msg)) => {
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            render_import(&state, &user, &site_domain, Some(flash), None).await
+        }
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
+
+/// Reads `url,custom_url` rows, skipping a header row if there is one.
+fn parse_links_csv(data: &[u8]) -> Result<Vec<schema::LinkRow>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let mut links = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("The CSV file could not be read: {}", e))?;
+        let url = record.get(0).unwrap_or_default();
+        if (i == 0 && url.eq_ignore_ascii_case("url")) || record.iter().all(str::is_empty) {
+            continue;
+        }
+        links.push(schema::LinkRow {
+            url: url.to_owned(),
+            custom_url: record.get(1).map(str::to_owned),
+        });
+    }
+    Ok(links)
+}
+
+/// Per-row results of a batch, with the full short url of every created link.
+fn batch_response(state: &AppState, batch: &LinkBatch) -> serde_json::Value {
+    let links: Vec<_> = batch
+        .links
+        .iter()
+        .map(|link| {
+            let short_url = link.id.as_ref().map(|id| {
+                if batch.domain.is_empty() {
+                    format!("{}/{}", state.short_url, id)
+                } else {
+                    format!("https://{}/{}", batch.domain, id)
+                }
+            });
+            json!({
+                "row": link.row,
+                "url": link.url,
+                "status": link.status,
+                "id": link.id,
+                "short_url": short_url,
+                "error": link.error,
+            })
+        })
+        .collect();
+
+    json!({
+        "domain": batch.domain,
+        "replayed": batch.replayed,
+        "links": links,
+    })
+}
+
+async fn render_import(
+    state: &AppState,
+    user: &users::Model,
+    site_domain: &str,
+    flash: Option<Flash>,
+    results: Option<serde_json::Value>,
+) -> Response {
+    let domains: Vec<_> = match Query::get_user_domains(&state.conn, user.id).await {
+        Ok(domains) => domains
+            .into_iter()
+            .filter(|d| d.verified_at.is_some())
+            .collect(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", user);
+    ctx.insert("domains", &domains);
+    ctx.insert("site_domain", site_domain);
+    // a fresh key for every form, so submitting the same form twice only imports once
+    ctx.insert(
+        "idempotency_key",
+        &Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
+    );
+    ctx.insert("max_links", &service::MAX_LINK_BATCH);
+    if let Some(results) = results {
+        ctx.insert("results", &results);
+    }
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+    render(state, "import.html.tera", &ctx)
+}
@@ -1,18 +1,50 @@
 use axum::{
     extract::{Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Redirect, Response},
+    Json,
 };
 use service::Query;
 use tower_cookies::Cookies;
 
 use crate::{AppState, COOKIE_NAME, KEY};
 
+/// Marker extension for requests authenticated with an API token rather than a session cookie.
+#[derive(Clone, Copy, Debug)]
+pub struct ApiToken;
+
 pub async fn current_user_middleware(
     State(state): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> Response {
+    // API clients send `Authorization: Bearer <token>` instead of a session cookie
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_owned());
+    if let Some(token) = bearer {
+        let user = Query::get_user_by_api_token(&state.conn, &token).await;
+        if let Some(user) = user.ok().filter(|u| u.disabled_at.is_none()) {
+            // tokens can't follow the reset redirect, so refuse them until the password is set
+            if user.password_reset_required {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(serde_json::json!({
+                        "error": "You need to set a new password before using the API."
+                    })),
+                )
+                    .into_response();
+            }
+            request.extensions_mut().insert(user);
+            request.extensions_mut().insert(ApiToken);
+        }
+        return next.run(request).await;
+    }
+
     let cookies = request
         .extensions_mut()
         .get::<Cookies>()
@@ -26,12 +58,20 @@ pub async fn current_user_middleware(
         .unwrap_or_default();
 
     if !current_user.is_empty() {
+        // disabled accounts are treated as logged out
         let user = Query::get_user_by_email(&state.conn, &current_user).await;
-        if let Ok(user) = user {
+        if let Some(user) = user.ok().filter(|u| u.disabled_at.is_none()) {
+            // an admin asked this user to pick a new password, don't let them do anything else
+            let path = request.uri().path();
+            if user.password_reset_required
+                && path != "/users/reset_password"
+                && !path.starts_with("/static/")
+            {
+                return Redirect::to("/users/reset_password").into_response();
+            }
             request.extensions_mut().insert(user);
         }
     }
 
-    let response = next.run(request).await;
-    response
+    next.run(request).await
 }
@@ -0,0 +1,202 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use entity::{pastes, schema, users};
+use service::sea_orm::DbErr;
+use service::{Mutation, Query};
+
+use crate::admin::AdminUser;
+use crate::domains::SiteDomain;
+use crate::{AppState, Flash};
+
+/// Status and explanation shown instead of a paste that was taken down.
+pub fn hidden_status(paste: &pastes::Model) -> Option<(StatusCode, &'static str)> {
+    paste.hidden_at?;
+
+    match paste.hidden_reason.as_deref() {
+        Some("legal") => Some((
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            "This paste is unavailable for legal reasons.",
+        )),
+        Some("held") => Some((
+            StatusCode::FORBIDDEN,
+            "This paste is waiting to be reviewed by a moderator.",
+        )),
+        _ => Some((
+            StatusCode::GONE,
+            "This paste has been removed for violating the terms of service.",
+        )),
+    }
+}
+
+pub fn render_unavailable(
+    state: &AppState,
+    current_user: Option<&users::Model>,
+    status: StatusCode,
+    message: &str,
+) -> Response {
+    let mut ctx = tera::Context::new();
+    ctx.insert("status", &status.as_u16());
+    ctx.insert("message", message);
+    if let Some(user) = current_user {
+        ctx.insert("current_user", user);
+    }
+
+    let body = state
+        .templates
+        .render("unavailable.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => (status, Html(body)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn report(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+) -> Response {
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    render_report(&state, current_user.map(|u| u.0), &paste, None)
+}
+
+pub async fn report_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    SiteDomain(site_domain): SiteDomain,
+    Path(paste_id): Path<String>,
+    form: Form<schema::ReportPost>,
+) -> Response {
+    let form = form.0;
+    let user = current_user.map(|u| u.0);
+
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, &paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+    };
+
+    let flash =
+        match Mutation::create_report(&state.conn, &paste.domain, &paste.id, &form, user.clone())
+            .await
+        {
+            Ok(_) => Flash {
+                info: Some(String::from(
+                    "Thank you, the report has been sent to the moderators.",
+                )),
+                warn: None,
+            },
+            Err(DbErr::Custom(msg)) => Flash {
+                info: None,
+                warn: Some(msg),
+            },
+            Err(e) => {
+                tracing::error!("Something went wrong: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+            }
+        };
+
+    render_report(&state, user, &paste, Some(flash))
+}
+
+fn render_report(
+    state: &AppState,
+    current_user: Option<users::Model>,
+    paste: &pastes::Model,
+    flash: Option<Flash>,
+) -> Response {
+    let mut ctx = tera::Context::new();
+    ctx.insert("paste_id", &paste.id);
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+    if let Some(user) = current_user {
+        ctx.insert("current_user", &user);
+    }
+
+    let body = state
+        .templates
+        .render("report.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn moderation_queue(AdminUser(user): AdminUser, state: State<AppState>) -> Response {
+    let reports = Query::get_open_reports(&state.conn).await;
+    let log = Query::get_moderation_log(&state.conn, 50).await;
+    let (reports, log) = match (reports, log) {
+        (Ok(reports), Ok(log)) => (reports, log),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", &user);
+    ctx.insert(
+        "reports",
+        &reports
+            .into_iter()
+            .map(|(report, paste)| serde_json::json!({ "report": report, "paste": paste }))
+            .collect::<Vec<_>>(),
+    );
+    ctx.insert(
+        "log",
+        &log.into_iter()
+            .map(|(entry, moderator)| serde_json::json!({ "entry": entry, "moderator": moderator }))
+            .collect::<Vec<_>>(),
+    );
+
+    let body = state
+        .templates
+        .render("moderation.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn moderate(
+    AdminUser(user): AdminUser,
+    state: State<AppState>,
+    Path(report_id): Path<i64>,
+    form: Form<schema::ModerationPost>,
+) -> Response {
+    match Mutation::moderate_report(&state.conn, report_id, &user, &form.0).await {
+        Ok(_) => Redirect::to("/admin/reports").into_response(),
+        Err(DbErr::RecordNotFound(_)) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use axum::extract::{Path, Query as QueryParams, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use entity::schema;
+use qrcode::{Color, EcLevel, QrCode};
+use service::Query;
+
+use crate::domains::{self, SiteDomain};
+use crate::{moderation, AppState};
+
+/// Image width used when no size is asked for.
+const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+/// Blank modules around the code, the spec asks for at least four.
+const QUIET_ZONE: u32 = 4;
+/// Browsers and printers may keep the images around, they only change when a paste is renamed.
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
+pub async fn svg(
+    state: State<AppState>,
+    site_domain: SiteDomain,
+    Path(paste_id): Path<String>,
+    QueryParams(params): QueryParams<schema::QrParams>,
+) -> Response {
+    let (code, size) = match qr_code(&state, site_domain, &paste_id, &params).await {
+        Ok(code) => code,
+        Err(e) => return e,
+    };
+
+    let width = code.width() as u32 + 2 * QUIET_ZONE;
+    let mut path = String::new();
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let x = i as u32 % code.width() as u32 + QUIET_ZONE;
+            let y = i as u32 / code.width() as u32 + QUIET_ZONE;
+            path.push_str(&format!("M{},{}h1v1h-1z", x, y));
+        }
+    }
+    let body = format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?><svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" viewBox="0 0 {width} {width}" shape-rendering="crispEdges"><rect width="{width}" height="{width}" fill="#fff"/><path fill="#000" d="{path}"/></svg>"##
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, CACHE_CONTROL),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+pub async fn png(
+    state: State<AppState>,
+    site_domain: SiteDomain,
+    Path(paste_id): Path<String>,
+    QueryParams(params): QueryParams<schema::QrParams>,
+) -> Response {
+    let (code, size) = match qr_code(&state, site_domain, &paste_id, &params).await {
+        Ok(code) => code,
+        Err(e) => return e,
+    };
+
+    // whole pixels per module, so the code stays sharp; the image can come out a bit
+    // smaller than asked for
+    let modules = code.width() as u32;
+    let scale = (size / (modules + 2 * QUIET_ZONE)).max(1);
+    let width = (modules + 2 * QUIET_ZONE) * scale;
+    let colors = code.to_colors();
+    let mut pixels = vec![255u8; (width * width) as usize];
+    for y in 0..modules {
+        for x in 0..modules {
+            if colors[(y * modules + x) as usize] == Color::Dark {
+                for dy in 0..scale {
+                    let row = (y + QUIET_ZONE) * scale + dy;
+                    let start = (row * width + (x + QUIET_ZONE) * scale) as usize;
+                    pixels[start..start + scale as usize].fill(0);
+                }
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    let mut encoder = png::Encoder::new(&mut body, width, width);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let written = encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels));
+    if let Err(e) = written {
+        tracing::error!("Error encoding QR code: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, CACHE_CONTROL),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// The QR code for the canonical URL of a paste, along with the requested image width.
+async fn qr_code(
+    state: &AppState,
+    SiteDomain(site_domain): SiteDomain,
+    paste_id: &str,
+    params: &schema::QrParams,
+) -> Result<(QrCode, u32), Response> {
+    let ec_level = match params.ec.as_deref().map(str::to_uppercase).as_deref() {
+        None | Some("M") => EcLevel::M,
+        Some("L") => EcLevel::L,
+        Some("Q") => EcLevel::Q,
+        Some("H") => EcLevel::H,
+        Some(_) => {
+            return Err(
+                (StatusCode::BAD_REQUEST, "ec has to be one of L, M, Q or H").into_response(),
+            )
+        }
+    };
+    let size = params
+        .size
+        .unwrap_or(DEFAULT_SIZE)
+        .clamp(MIN_SIZE, MAX_SIZE);
+
+    let paste = match Query::get_paste_by_id(&state.conn, &site_domain, paste_id).await {
+        Ok(paste) => paste,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "Not found").into_response()),
+    };
+    if let Some((status, message)) = moderation::hidden_status(&paste) {
+        return Err((status, message).into_response());
+    }
+
+    let url = domains::canonical_url(state, &paste);
+    match QrCode::with_error_correction_level(url.as_bytes(), ec_level) {
+        Ok(code) => Ok((code, size)),
+        Err(e) => {
+            tracing::error!("Error generating QR code for {}: {}", url, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response())
+        }
+    }
+}
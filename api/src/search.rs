@@ -0,0 +1,121 @@
+use axum::extract::{Query as QueryParams, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use entity::{schema, users};
+use serde_json::json;
+use service::sea_orm::DbErr;
+use service::{Query, SearchHit};
+
+use crate::domains::canonical_url;
+use crate::{AppState, Flash};
+
+/// Searches the pastes of the logged in user, or every paste for admins.
+pub async fn search(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    QueryParams(params): QueryParams<schema::PasteSearchParams>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    let mut ctx = tera::Context::new();
+    match Query::search_user_pastes(&state.conn, &user, &params).await {
+        Ok((hits, pages)) => {
+            ctx.insert("results", &results(&state, &hits));
+            ctx.insert("pages", &pages);
+        }
+        Err(DbErr::Custom(msg)) => {
+            ctx.insert("results", &Vec::<serde_json::Value>::new());
+            ctx.insert("pages", &0);
+            ctx.insert(
+                "flash",
+                &Flash {
+                    info: None,
+                    warn: Some(msg),
+                },
+            );
+        }
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response();
+        }
+    }
+
+    ctx.insert("current_user", &user);
+    ctx.insert("page_title", "Search");
+    ctx.insert("q", &params.q.unwrap_or_default());
+    ctx.insert("language", &params.language.unwrap_or_default());
+    ctx.insert("from", &params.from.unwrap_or_default());
+    ctx.insert("to", &params.to.unwrap_or_default());
+    ctx.insert("page", &params.page.unwrap_or_default());
+
+    let body = state
+        .templates
+        .render("search.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// The same search as [`search`], for API clients.
+pub async fn search_api(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    QueryParams(params): QueryParams<schema::PasteSearchParams>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "You need to be logged in to search pastes." })),
+        )
+            .into_response();
+    };
+
+    match Query::search_user_pastes(&state.conn, &user, &params).await {
+        Ok((hits, pages)) => Json(json!({
+            "results": results(&state, &hits),
+            "page": params.page.unwrap_or_default(),
+            "pages": pages,
+        }))
+        .into_response(),
+        Err(DbErr::Custom(msg)) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": msg })),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Something went wrong: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "something went wrong" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn results(state: &AppState, hits: &[SearchHit]) -> Vec<serde_json::Value> {
+    hits.iter()
+        .map(|hit| {
+            json!({
+                "id": hit.paste.id,
+                "domain": hit.paste.domain,
+                "url": canonical_url(state, &hit.paste),
+                "filename": hit.paste.filename,
+                "language": hit.paste.language,
+                "inserted_at": hit.paste.inserted_at,
+                "snippet": hit.snippet,
+            })
+        })
+        .collect()
+}
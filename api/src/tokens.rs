@@ -0,0 +1,110 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Extension;
+use entity::users;
+use service::sea_orm::DbErr;
+use service::{Mutation, Query};
+
+use crate::{AppState, Flash};
+
+pub async fn tokens(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    render_tokens(&state, &user, None, None).await
+}
+
+pub async fn tokens_post(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    match Mutation::create_api_token(&state.conn, &user).await {
+        Ok(token) => render_tokens(&state, &user, Some(token), None).await,
+        Err(DbErr::Custom(msg)) => {
+            let flash = Flash {
+                info: None,
+                warn: Some(msg),
+            };
+            render_tokens(&state, &user, None, Some(flash)).await
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn delete(
+    current_user: Option<Extension<users::Model>>,
+    state: State<AppState>,
+    Path(token_id): Path<i64>,
+) -> Response {
+    let Some(Extension(user)) = current_user else {
+        return Redirect::to("/users/log_in").into_response();
+    };
+
+    match Mutation::delete_api_token(&state.conn, &user, token_id).await {
+        Ok(_) => Redirect::to("/users/tokens").into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Renders the token list, with `new_token` shown once right after it was issued.
+async fn render_tokens(
+    state: &AppState,
+    user: &users::Model,
+    new_token: Option<String>,
+    flash: Option<Flash>,
+) -> Response {
+    let tokens = match Query::get_api_tokens(&state.conn, user.id).await {
+        Ok(tokens) => tokens,
+        Err(e) => return error_response(e),
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("current_user", user);
+    ctx.insert(
+        "tokens",
+        &tokens
+            .iter()
+            .map(|t| serde_json::json!({ "id": t.id, "inserted_at": t.inserted_at }))
+            .collect::<Vec<_>>(),
+    );
+    if let Some(token) = new_token {
+        ctx.insert("new_token", &token);
+    }
+    if let Some(flash) = flash {
+        ctx.insert("flash", &flash);
+    }
+
+    let body = state
+        .templates
+        .render("tokens.html.tera", &ctx)
+        .map_err(|e| {
+            tracing::error!("Error rendering template {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "error rendering template",
+            )
+        });
+    match body {
+        Ok(body) => Html(body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn error_response(err: DbErr) -> Response {
+    match err {
+        DbErr::RecordNotFound(_) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        e => {
+            tracing::error!("Something went wrong: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// Solves the proof-of-work challenge issued with the paste form before it is submitted.
+// A plain JS SHA-256 is used so this also works outside of secure contexts (no crypto.subtle).
+(function () {
+    const K = new Uint32Array([
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ]);
+    const W = new Uint32Array(64);
+
+    function rotr(x, n) {
+        return (x >>> n) | (x << (32 - n));
+    }
+
+    // returns the first 32 bits of sha256(bytes), which is all the difficulty check needs
+    function sha256Prefix(bytes) {
+        const bitLength = bytes.length * 8;
+        const padded = new Uint8Array(((bytes.length + 9 + 63) >> 6) << 6);
+        padded.set(bytes);
+        padded[bytes.length] = 0x80;
+        const view = new DataView(padded.buffer);
+        view.setUint32(padded.length - 4, bitLength);
+
+        let h0 = 0x6a09e667, h1 = 0xbb67ae85, h2 = 0x3c6ef372, h3 = 0xa54ff53a;
+        let h4 = 0x510e527f, h5 = 0x9b05688c, h6 = 0x1f83d9ab, h7 = 0x5be0cd19;
+
+        for (let offset = 0; offset < padded.length; offset += 64) {
+            for (let i = 0; i < 16; i++) {
+                W[i] = view.getUint32(offset + i * 4);
+            }
+            for (let i = 16; i < 64; i++) {
+                const s0 = rotr(W[i - 15], 7) ^ rotr(W[i - 15], 18) ^ (W[i - 15] >>> 3);
+                const s1 = rotr(W[i - 2], 17) ^ rotr(W[i - 2], 19) ^ (W[i - 2] >>> 10);
+                W[i] = (W[i - 16] + s0 + W[i - 7] + s1) | 0;
+            }
+
+            let a = h0, b = h1, c = h2, d = h3, e = h4, f = h5, g = h6, h = h7;
+            for (let i = 0; i < 64; i++) {
+                const S1 = rotr(e, 6) ^ rotr(e, 11) ^ rotr(e, 25);
+                const ch = (e & f) ^ (~e & g);
+                const t1 = (h + S1 + ch + K[i] + W[i]) | 0;
+                const S0 = rotr(a, 2) ^ rotr(a, 13) ^ rotr(a, 22);
+                const maj = (a & b) ^ (a & c) ^ (b & c);
+                const t2 = (S0 + maj) | 0;
+                h = g; g = f; f = e; e = (d + t1) | 0;
+                d = c; c = b; b = a; a = (t1 + t2) | 0;
+            }
+
+            h0 = (h0 + a) | 0; h1 = (h1 + b) | 0; h2 = (h2 + c) | 0; h3 = (h3 + d) | 0;
+            h4 = (h4 + e) | 0; h5 = (h5 + f) | 0; h6 = (h6 + g) | 0; h7 = (h7 + h) | 0;
+        }
+
+        return h0 >>> 0;
+    }
+
+    function solve(challenge, difficulty) {
+        const encoder = new TextEncoder();
+        for (let n = 0; ; n++) {
+            const prefix = sha256Prefix(encoder.encode(challenge + ":" + n));
+            if (difficulty === 0 || Math.clz32(prefix) >= difficulty) {
+                return n;
+            }
+        }
+    }
+
+    const form = document.getElementById("page_form");
+    const challenge = form.querySelector('input[name="pow_challenge"]');
+    const solution = form.querySelector('input[name="pow_solution"]');
+
+    form.addEventListener("submit", function (e) {
+        if (solution.value) {
+            return;
+        }
+        e.preventDefault();
+        const difficulty = parseInt(challenge.value.split(".")[0], 10);
+        // leave the browser a frame to paint before the busy loop starts
+        form.classList.add("cursor-wait");
+        setTimeout(function () {
+            solution.value = solve(challenge.value, difficulty);
+            form.submit();
+        }, 0);
+    });
+})();
@@ -2,7 +2,18 @@
 
 pub mod prelude;
 
+pub mod attachments;
+pub mod clicks;
+pub mod domains;
+pub mod filter_rules;
+pub mod idempotency_keys;
+pub mod link_checks;
+pub mod moderation_actions;
+pub mod paste_aliases;
+pub mod paste_contents;
+pub mod paste_files;
 pub mod pastes;
+pub mod reports;
 pub mod users;
 pub mod users_tokens;
 pub mod schema;
\ No newline at end of file
@@ -6,6 +6,10 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "pastes")]
 pub struct Model {
+    /// Custom domain the paste lives on, empty for the instance's own domain.
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[serde(skip_deserializing)]
+    pub domain: String,
     #[sea_orm(primary_key, auto_increment = false)]
     #[serde(skip_deserializing)]
     pub id: String,
@@ -14,12 +18,38 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub content: String,
     pub belongs_to: Option<i64>,
-    #[sea_orm(ignore)]
-    pub custom_url: Option<String>,
+    pub hidden_at: Option<DateTime>,
+    pub hidden_reason: Option<String>,
+    /// HTTP status used when redirecting a short link.
+    pub redirect_code: i16,
+    /// Show an interstitial page instead of redirecting straight away.
+    pub preview: bool,
+    pub expires_at: Option<DateTime>,
+    pub max_clicks: Option<i32>,
+    /// Number of times the link was followed, only counted when `max_clicks` is set.
+    pub click_count: i32,
+    /// When the link health checker last looked at the target.
+    pub checked_at: Option<DateTime>,
+    /// Set once the target keeps failing health checks, cleared when it recovers.
+    pub broken_at: Option<DateTime>,
+    /// Name of the first file. Pastes with several files keep the others in `paste_files`.
+    pub filename: Option<String>,
+    pub language: Option<String>,
+    /// Key of the body in `paste_contents`, empty for short links which keep their url
+    /// in `content`.
+    #[serde(skip)]
+    pub content_hash: Option<String>,
+    /// Empty for pastes created before creation dates were recorded.
+    #[serde(skip_deserializing)]
+    pub inserted_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::attachments::Entity")]
+    Attachments,
+    #[sea_orm(has_many = "super::clicks::Entity")]
+    Clicks,
     #[sea_orm(
         belongs_to = "super::users::Entity",
         from = "Column::BelongsTo",
@@ -28,6 +58,64 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Users,
+    #[sea_orm(has_many = "super::link_checks::Entity")]
+    LinkChecks,
+    #[sea_orm(
+        belongs_to = "super::paste_contents::Entity",
+        from = "Column::ContentHash",
+        to = "super::paste_contents::Column::Hash",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    PasteContents,
+    #[sea_orm(has_many = "super::paste_aliases::Entity")]
+    PasteAliases,
+    #[sea_orm(has_many = "super::paste_files::Entity")]
+    PasteFiles,
+    #[sea_orm(has_many = "super::reports::Entity")]
+    Reports,
+}
+
+impl Related<super::attachments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Attachments.def()
+    }
+}
+
+impl Related<super::clicks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Clicks.def()
+    }
+}
+
+impl Related<super::link_checks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LinkChecks.def()
+    }
+}
+
+impl Related<super::paste_aliases::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasteAliases.def()
+    }
+}
+
+impl Related<super::paste_contents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasteContents.def()
+    }
+}
+
+impl Related<super::paste_files::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasteFiles.def()
+    }
+}
+
+impl Related<super::reports::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Reports.def()
+    }
 }
 
 impl Related<super::users::Entity> for Entity {
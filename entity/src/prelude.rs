@@ -1,5 +1,16 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::attachments::Entity as Attachments;
+pub use super::clicks::Entity as Clicks;
+pub use super::domains::Entity as Domains;
+pub use super::filter_rules::Entity as FilterRules;
+pub use super::idempotency_keys::Entity as IdempotencyKeys;
+pub use super::link_checks::Entity as LinkChecks;
+pub use super::moderation_actions::Entity as ModerationActions;
+pub use super::paste_aliases::Entity as PasteAliases;
+pub use super::paste_contents::Entity as PasteContents;
+pub use super::paste_files::Entity as PasteFiles;
 pub use super::pastes::Entity as Pastes;
+pub use super::reports::Entity as Reports;
 pub use super::users::Entity as Users;
 pub use super::users_tokens::Entity as UsersTokens;
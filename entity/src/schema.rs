@@ -6,3 +6,136 @@ pub struct LoginPost {
     pub password: String,
     pub remember_me: Option<bool>,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PastePost {
+    pub content: String,
+    pub custom_url: Option<String>,
+    /// Custom domain to create the paste on, defaults to the domain it was submitted from.
+    pub domain: Option<String>,
+    pub pow_challenge: Option<String>,
+    pub pow_solution: Option<String>,
+    /// What to do when the content looks like it contains credentials.
+    pub secrets: Option<SecretsPolicy>,
+    /// Name of the first file, `content` being its content.
+    pub filename: Option<String>,
+    pub language: Option<String>,
+    /// Further files, the same index in each list belongs to the same file.
+    #[serde(default)]
+    pub file_name: Vec<String>,
+    #[serde(default)]
+    pub file_language: Vec<String>,
+    #[serde(default)]
+    pub file_content: Vec<String>,
+}
+
+impl PastePost {
+    /// The content of every file, first one first.
+    pub fn contents(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.content.as_str()).chain(self.file_content.iter().map(String::as_str))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretsPolicy {
+    /// Store the content as it is.
+    #[default]
+    Publish,
+    /// Replace anything that looks like a secret before storing.
+    Redact,
+    /// Refuse to store the paste.
+    Reject,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReportPost {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModerationPost {
+    pub action: crate::moderation_actions::Action,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordResetPost {
+    pub password: String,
+    pub password_confirmation: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolePost {
+    pub role: crate::users::Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchParams {
+    pub q: Option<String>,
+    pub page: Option<u64>,
+}
+
+/// Full-text search over pastes, dates are `YYYY-MM-DD` and both ends are included.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PasteSearchParams {
+    pub q: Option<String>,
+    pub language: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub page: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilterRulePost {
+    pub kind: crate::filter_rules::Kind,
+    pub pattern: String,
+    pub action: crate::filter_rules::Action,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkSettingsPost {
+    pub redirect_code: i16,
+    /// Checkbox, present when ticked.
+    pub preview: Option<String>,
+    /// `YYYY-MM-DDTHH:MM` in UTC, empty for links that never expire.
+    pub expires_at: String,
+    /// Empty for links without a click limit.
+    pub max_clicks: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainPost {
+    pub host: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DomainParams {
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkBatchPost {
+    pub links: Vec<LinkRow>,
+    /// Custom domain to create the links on, defaults to the domain of the request.
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkRow {
+    pub url: String,
+    pub custom_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenamePost {
+    pub new_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct QrParams {
+    /// Width of the image in pixels.
+    pub size: Option<u32>,
+    /// Error correction level, one of `L`, `M`, `Q` or `H`.
+    pub ec: Option<String>,
+}
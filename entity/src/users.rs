@@ -8,7 +8,6 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
-    #[sea_orm(column_type = "custom(\"citext\")")]
     pub email: String,
     #[serde(skip_serializing)]
     pub hashed_password: String,
@@ -18,22 +17,68 @@ pub struct Model {
     pub inserted_at: DateTime,
     #[serde(skip_serializing)]
     pub updated_at: DateTime,
+    pub disabled_at: Option<DateTime>,
+    pub role: Role,
+    #[serde(skip_serializing)]
+    pub password_reset_required: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[sea_orm(string_value = "user")]
+    User,
+    #[sea_orm(string_value = "admin")]
+    Admin,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::domains::Entity")]
+    Domains,
+    #[sea_orm(has_many = "super::idempotency_keys::Entity")]
+    IdempotencyKeys,
+    #[sea_orm(has_many = "super::moderation_actions::Entity")]
+    ModerationActions,
     #[sea_orm(has_many = "super::pastes::Entity")]
     Pastes,
+    #[sea_orm(has_many = "super::reports::Entity")]
+    Reports,
     #[sea_orm(has_many = "super::users_tokens::Entity")]
     UsersTokens,
 }
 
+impl Related<super::domains::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Domains.def()
+    }
+}
+
+impl Related<super::idempotency_keys::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IdempotencyKeys.def()
+    }
+}
+
+impl Related<super::moderation_actions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModerationActions.def()
+    }
+}
+
 impl Related<super::pastes::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Pastes.def()
     }
 }
 
+impl Related<super::reports::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Reports.def()
+    }
+}
+
 impl Related<super::users_tokens::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UsersTokens.def()
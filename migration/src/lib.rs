@@ -1,12 +1,147 @@
 pub use sea_orm_migration::prelude::*;
+// for connecting with the sea-orm version the migrations are written against
+pub use sea_orm_migration::sea_orm;
+use sea_orm_migration::sea_orm::DbBackend;
 
+mod m20220119_000001_create_users_table;
+mod m20220119_000002_create_pastes_table;
+mod m20220119_000003_create_users_tokens_table;
 mod m20220120_000001_create_paste_table;
+mod m20261019_000001_create_reports_table;
+mod m20261019_000002_add_user_roles;
+mod m20261019_000003_create_filter_rules_table;
+mod m20261019_000004_create_clicks_table;
+mod m20261019_000005_add_redirect_options;
+mod m20261019_000006_create_domains_table;
+mod m20261019_000007_create_idempotency_keys_table;
+mod m20261019_000008_create_link_checks_table;
+mod m20261019_000009_create_paste_aliases_table;
+mod m20261019_000010_create_attachments_table;
+mod m20261019_000011_create_paste_files_table;
+mod m20261019_000012_add_content_compression;
+mod m20261019_000013_create_paste_contents_table;
+mod m20261019_000014_add_paste_search;
+mod m20261019_000015_lowercase_user_emails;
+mod m20261019_000016_add_paste_file_contents;
 
 pub struct Migrator;
 
+/// Whether the database is SQLite, which can't change the keys of a table once it's
+/// created. Migrations doing that rebuild the table there with [`rebuild_tables`].
+fn is_sqlite(manager: &SchemaManager) -> bool {
+    manager.get_database_backend() == DbBackend::Sqlite
+}
+
+/// Rebuilds `tables` from the definitions given, keeping their rows: each is renamed out
+/// of the way, created again and its rows copied over. Renaming a table makes the tables
+/// referencing it follow along, so those have to be rebuilt with it, after it. The
+/// indexes of the tables go away with the old ones, `indexes` are created again at the
+/// end.
+async fn rebuild_tables(
+    manager: &SchemaManager<'_>,
+    tables: Vec<TableCreateStatement>,
+    indexes: Vec<IndexCreateStatement>,
+) -> Result<(), DbErr> {
+    let mut names = Vec::new();
+    for table in &tables {
+        let Some(TableRef::Table(name)) = table.get_table_name() else {
+            return Err(DbErr::Migration("tables are rebuilt by name".to_owned()));
+        };
+        let name = name.to_string();
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(Alias::new(&name), Alias::new(format!("{}_old", name)))
+                    .to_owned(),
+            )
+            .await?;
+        names.push(name);
+    }
+
+    for (table, name) in tables.into_iter().zip(&names) {
+        let columns = table
+            .get_columns()
+            .iter()
+            .map(|column| Alias::new(column.get_column_name()))
+            .collect::<Vec<_>>();
+        manager.create_table(table).await?;
+
+        let rows = Query::select()
+            .columns(columns.clone())
+            .from(Alias::new(format!("{}_old", name)))
+            .to_owned();
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Alias::new(name))
+                    .columns(columns)
+                    .select_from(rows)
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    // the tables referencing the others go first, nothing points at those after that
+    for name in names.iter().rev() {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new(format!("{}_old", name)))
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    for index in indexes {
+        manager.create_index(index).await?;
+    }
+    Ok(())
+}
+
+/// Whether the baseline migration already ran on the database, which means it was set
+/// up before the baseline was split into the steps that come before it.
+async fn has_baseline(manager: &SchemaManager<'_>) -> Result<bool, DbErr> {
+    let db = manager.get_connection();
+    let baseline = Query::select()
+        .expr(Expr::val(1))
+        .from(Migrator::migration_table_name())
+        .and_where(
+            Expr::col(Alias::new("version"))
+                .eq(m20220120_000001_create_paste_table::Migration.name()),
+        )
+        .to_owned();
+    Ok(db
+        .query_one(db.get_database_backend().build(&baseline))
+        .await?
+        .is_some())
+}
+
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220120_000001_create_paste_table::Migration)]
+        vec![
+            // the baseline, with the steps it was split into running first
+            Box::new(m20220119_000001_create_users_table::Migration),
+            Box::new(m20220119_000002_create_pastes_table::Migration),
+            Box::new(m20220119_000003_create_users_tokens_table::Migration),
+            Box::new(m20220120_000001_create_paste_table::Migration),
+            Box::new(m20261019_000001_create_reports_table::Migration),
+            Box::new(m20261019_000002_add_user_roles::Migration),
+            Box::new(m20261019_000003_create_filter_rules_table::Migration),
+            Box::new(m20261019_000004_create_clicks_table::Migration),
+            Box::new(m20261019_000005_add_redirect_options::Migration),
+            Box::new(m20261019_000006_create_domains_table::Migration),
+            Box::new(m20261019_000007_create_idempotency_keys_table::Migration),
+            Box::new(m20261019_000008_create_link_checks_table::Migration),
+            Box::new(m20261019_000009_create_paste_aliases_table::Migration),
+            Box::new(m20261019_000010_create_attachments_table::Migration),
+            Box::new(m20261019_000011_create_paste_files_table::Migration),
+            Box::new(m20261019_000012_add_content_compression::Migration),
+            Box::new(m20261019_000013_create_paste_contents_table::Migration),
+            Box::new(m20261019_000014_add_paste_search::Migration),
+            Box::new(m20261019_000015_lowercase_user_emails::Migration),
+            Box::new(m20261019_000016_add_paste_file_contents::Migration),
+        ]
     }
 }
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if crate::has_baseline(manager).await? {
+            return Ok(());
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Pastes::Table)
+                    .col(
+                        ColumnDef::new(Pastes::Id)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Pastes::IsUrl)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(Pastes::Content).text().not_null())
+                    .col(ColumnDef::new(Pastes::BelongsTo).big_integer().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Pastes::Table, Pastes::BelongsTo)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("pastes_belongs_to_index")
+                    .table(Pastes::Table)
+                    .col(Pastes::BelongsTo)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Pastes::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Id,
+    IsUrl,
+    Content,
+    BelongsTo,
+}
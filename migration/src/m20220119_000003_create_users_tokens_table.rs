@@ -0,0 +1,92 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if crate::has_baseline(manager).await? {
+            return Ok(());
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UsersTokens::Table)
+                    .col(
+                        ColumnDef::new(UsersTokens::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UsersTokens::UserId).big_integer().not_null())
+                    .col(ColumnDef::new(UsersTokens::Token).binary().not_null())
+                    .col(
+                        ColumnDef::new(UsersTokens::Context)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UsersTokens::SentTo).string_len(255).null())
+                    .col(
+                        ColumnDef::new(UsersTokens::InsertedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UsersTokens::Table, UsersTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("users_tokens_context_token_index")
+                    .table(UsersTokens::Table)
+                    .col(UsersTokens::Context)
+                    .col(UsersTokens::Token)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("users_tokens_user_id_index")
+                    .table(UsersTokens::Table)
+                    .col(UsersTokens::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UsersTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum UsersTokens {
+    Table,
+    Id,
+    UserId,
+    Token,
+    Context,
+    SentTo,
+    InsertedAt,
+}
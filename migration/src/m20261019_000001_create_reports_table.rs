@@ -0,0 +1,208 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::DisabledAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::HiddenAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::HiddenReason).string_len(32).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Reports::Table)
+                    .col(
+                        ColumnDef::new(Reports::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Reports::PasteId).string_len(255).not_null())
+                    .col(ColumnDef::new(Reports::ReporterId).big_integer().null())
+                    .col(ColumnDef::new(Reports::Reason).text().not_null())
+                    .col(
+                        ColumnDef::new(Reports::Status)
+                            .string_len(32)
+                            .not_null()
+                            .default("open"),
+                    )
+                    .col(ColumnDef::new(Reports::InsertedAt).timestamp().not_null())
+                    .col(ColumnDef::new(Reports::ResolvedAt).timestamp().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Reports::Table, Reports::PasteId)
+                            .to(Pastes::Table, Pastes::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Reports::Table, Reports::ReporterId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("reports_status_index")
+                    .table(Reports::Table)
+                    .col(Reports::Status)
+                    .to_owned(),
+            )
+            .await?;
+
+        // the audit log keeps plain paste ids so entries survive the paste being deleted
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModerationActions::Table)
+                    .col(
+                        ColumnDef::new(ModerationActions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::PasteId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::ReportId)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::ModeratorId)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::Action)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModerationActions::Note).text().null())
+                    .col(
+                        ColumnDef::new(ModerationActions::InsertedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ModerationActions::Table, ModerationActions::ModeratorId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ModerationActions::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Reports::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::HiddenAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::HiddenReason)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::DisabledAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    DisabledAt,
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Id,
+    HiddenAt,
+    HiddenReason,
+}
+
+#[derive(DeriveIden)]
+enum Reports {
+    Table,
+    Id,
+    PasteId,
+    ReporterId,
+    Reason,
+    Status,
+    InsertedAt,
+    ResolvedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModerationActions {
+    Table,
+    Id,
+    PasteId,
+    ReportId,
+    ModeratorId,
+    Action,
+    Note,
+    InsertedAt,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FilterRules::Table)
+                    .col(
+                        ColumnDef::new(FilterRules::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FilterRules::Kind).string_len(32).not_null())
+                    .col(ColumnDef::new(FilterRules::Pattern).text().not_null())
+                    .col(
+                        ColumnDef::new(FilterRules::Action)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(FilterRules::Enabled)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .col(
+                        ColumnDef::new(FilterRules::InsertedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FilterRules::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum FilterRules {
+    Table,
+    Id,
+    Kind,
+    Pattern,
+    Action,
+    Enabled,
+    InsertedAt,
+}
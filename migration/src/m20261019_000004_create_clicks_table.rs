@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Clicks::Table)
+                    .col(
+                        ColumnDef::new(Clicks::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Clicks::PasteId).string_len(255).not_null())
+                    .col(ColumnDef::new(Clicks::ClickedAt).timestamp().not_null())
+                    .col(ColumnDef::new(Clicks::ReferrerHost).string_len(255).null())
+                    .col(ColumnDef::new(Clicks::UserAgent).string_len(32).not_null())
+                    .col(ColumnDef::new(Clicks::Country).string_len(2).null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Clicks::Table, Clicks::PasteId)
+                            .to(Pastes::Table, Pastes::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("clicks_paste_id_clicked_at_index")
+                    .table(Clicks::Table)
+                    .col(Clicks::PasteId)
+                    .col(Clicks::ClickedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Clicks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Clicks {
+    Table,
+    Id,
+    PasteId,
+    ClickedAt,
+    ReferrerHost,
+    UserAgent,
+    Country,
+}
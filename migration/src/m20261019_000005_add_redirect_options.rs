@@ -0,0 +1,118 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(
+                        ColumnDef::new(Pastes::RedirectCode)
+                            .small_integer()
+                            .not_null()
+                            .default(307),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(
+                        ColumnDef::new(Pastes::Preview)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ExpiresAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::MaxClicks).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(
+                        ColumnDef::new(Pastes::ClickCount)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::RedirectCode)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::Preview)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::MaxClicks)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::ClickCount)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    RedirectCode,
+    Preview,
+    ExpiresAt,
+    MaxClicks,
+    ClickCount,
+}
@@ -0,0 +1,413 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Domains::Table)
+                    .col(
+                        ColumnDef::new(Domains::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Domains::Host)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Domains::UserId).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Domains::VerificationToken)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Domains::VerifiedAt).timestamp().null())
+                    .col(ColumnDef::new(Domains::InsertedAt).timestamp().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Domains::Table, Domains::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // ids are unique per domain, the instance's own domain is stored as ''
+        for (table, column) in [
+            (Pastes::Table.into_iden(), Pastes::Domain.into_iden()),
+            (Reports::Table.into_iden(), Reports::PasteDomain.into_iden()),
+            (Clicks::Table.into_iden(), Clicks::PasteDomain.into_iden()),
+            (
+                ModerationActions::Table.into_iden(),
+                ModerationActions::PasteDomain.into_iden(),
+            ),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .add_column(domain(column))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("clicks_paste_id_clicked_at_index")
+                    .table(Clicks::Table)
+                    .to_owned(),
+            )
+            .await?;
+        set_paste_keys(manager, true).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("clicks_paste_clicked_at_index")
+                    .table(Clicks::Table)
+                    .col(Clicks::PasteDomain)
+                    .col(Clicks::PasteId)
+                    .col(Clicks::ClickedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // pastes on custom domains can't be kept once ids have to be globally unique again
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Pastes::Table)
+                    .and_where(Expr::col(Pastes::Domain).ne(""))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("clicks_paste_clicked_at_index")
+                    .table(Clicks::Table)
+                    .to_owned(),
+            )
+            .await?;
+        set_paste_keys(manager, false).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("clicks_paste_id_clicked_at_index")
+                    .table(Clicks::Table)
+                    .col(Clicks::PasteId)
+                    .col(Clicks::ClickedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        for (table, column) in [
+            (
+                ModerationActions::Table.into_iden(),
+                ModerationActions::PasteDomain.into_iden(),
+            ),
+            (Clicks::Table.into_iden(), Clicks::PasteDomain.into_iden()),
+            (Reports::Table.into_iden(), Reports::PasteDomain.into_iden()),
+            (Pastes::Table.into_iden(), Pastes::Domain.into_iden()),
+        ] {
+            manager
+                .alter_table(Table::alter().table(table).drop_column(column).to_owned())
+                .await?;
+        }
+
+        manager
+            .drop_table(Table::drop().table(Domains::Table).to_owned())
+            .await
+    }
+}
+
+/// Makes pastes keyed by domain and id, or by id alone when `by_domain` is false, along
+/// with the references of reports and clicks to them.
+async fn set_paste_keys(manager: &SchemaManager<'_>, by_domain: bool) -> Result<(), DbErr> {
+    if crate::is_sqlite(manager) {
+        return crate::rebuild_tables(
+            manager,
+            vec![pastes(by_domain), reports(by_domain), clicks(by_domain)],
+            vec![
+                Index::create()
+                    .name("pastes_belongs_to_index")
+                    .table(Pastes::Table)
+                    .col(Pastes::BelongsTo)
+                    .to_owned(),
+                Index::create()
+                    .name("reports_status_index")
+                    .table(Reports::Table)
+                    .col(Reports::Status)
+                    .to_owned(),
+            ],
+        )
+        .await;
+    }
+
+    let (old, new) = if by_domain {
+        ("paste_id_fkey", "paste_fkey")
+    } else {
+        ("paste_fkey", "paste_id_fkey")
+    };
+    let keys = [
+        (
+            Reports::Table.to_string(),
+            paste_key(
+                Reports::Table,
+                Reports::PasteDomain,
+                Reports::PasteId,
+                by_domain,
+            ),
+        ),
+        (
+            Clicks::Table.to_string(),
+            paste_key(
+                Clicks::Table,
+                Clicks::PasteDomain,
+                Clicks::PasteId,
+                by_domain,
+            ),
+        ),
+    ];
+    for (table, _) in &keys {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name(format!("{}_{}", table, old))
+                    .table(Alias::new(table))
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    // sea-query has no statement for changing a primary key
+    manager
+        .get_connection()
+        .execute_unprepared(if by_domain {
+            "ALTER TABLE pastes DROP CONSTRAINT pastes_pkey, ADD PRIMARY KEY (domain, id)"
+        } else {
+            "ALTER TABLE pastes DROP CONSTRAINT pastes_pkey, ADD PRIMARY KEY (id)"
+        })
+        .await?;
+
+    for (table, mut key) in keys {
+        manager
+            .create_foreign_key(key.name(format!("{}_{}", table, new)).to_owned())
+            .await?;
+    }
+    Ok(())
+}
+
+fn pastes(by_domain: bool) -> TableCreateStatement {
+    let mut table = Table::create()
+        .table(Pastes::Table)
+        .col(ColumnDef::new(Pastes::Id).string_len(255).not_null())
+        .col(
+            ColumnDef::new(Pastes::IsUrl)
+                .boolean()
+                .not_null()
+                .default(false),
+        )
+        .col(ColumnDef::new(Pastes::Content).text().not_null())
+        .col(ColumnDef::new(Pastes::BelongsTo).big_integer().null())
+        .col(ColumnDef::new(Pastes::HiddenAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::HiddenReason).string_len(32).null())
+        .col(
+            ColumnDef::new(Pastes::RedirectCode)
+                .small_integer()
+                .not_null()
+                .default(307),
+        )
+        .col(
+            ColumnDef::new(Pastes::Preview)
+                .boolean()
+                .not_null()
+                .default(false),
+        )
+        .col(ColumnDef::new(Pastes::ExpiresAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::MaxClicks).integer().null())
+        .col(
+            ColumnDef::new(Pastes::ClickCount)
+                .integer()
+                .not_null()
+                .default(0),
+        )
+        .col(domain(Pastes::Domain))
+        .foreign_key(
+            ForeignKey::create()
+                .from(Pastes::Table, Pastes::BelongsTo)
+                .to(Users::Table, Users::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .to_owned();
+    if by_domain {
+        table.primary_key(Index::create().col(Pastes::Domain).col(Pastes::Id));
+    } else {
+        table.primary_key(Index::create().col(Pastes::Id));
+    }
+    table
+}
+
+fn reports(by_domain: bool) -> TableCreateStatement {
+    Table::create()
+        .table(Reports::Table)
+        .col(id(Reports::Id))
+        .col(ColumnDef::new(Reports::PasteId).string_len(255).not_null())
+        .col(ColumnDef::new(Reports::ReporterId).big_integer().null())
+        .col(ColumnDef::new(Reports::Reason).text().not_null())
+        .col(
+            ColumnDef::new(Reports::Status)
+                .string_len(32)
+                .not_null()
+                .default("open"),
+        )
+        .col(ColumnDef::new(Reports::InsertedAt).timestamp().not_null())
+        .col(ColumnDef::new(Reports::ResolvedAt).timestamp().null())
+        .col(domain(Reports::PasteDomain))
+        .foreign_key(&mut paste_key(
+            Reports::Table,
+            Reports::PasteDomain,
+            Reports::PasteId,
+            by_domain,
+        ))
+        .foreign_key(
+            ForeignKey::create()
+                .from(Reports::Table, Reports::ReporterId)
+                .to(Users::Table, Users::Id)
+                .on_delete(ForeignKeyAction::SetNull),
+        )
+        .to_owned()
+}
+
+fn clicks(by_domain: bool) -> TableCreateStatement {
+    Table::create()
+        .table(Clicks::Table)
+        .col(id(Clicks::Id))
+        .col(ColumnDef::new(Clicks::PasteId).string_len(255).not_null())
+        .col(ColumnDef::new(Clicks::ClickedAt).timestamp().not_null())
+        .col(ColumnDef::new(Clicks::ReferrerHost).string_len(255).null())
+        .col(ColumnDef::new(Clicks::UserAgent).string_len(32).not_null())
+        .col(ColumnDef::new(Clicks::Country).string_len(2).null())
+        .col(domain(Clicks::PasteDomain))
+        .foreign_key(&mut paste_key(
+            Clicks::Table,
+            Clicks::PasteDomain,
+            Clicks::PasteId,
+            by_domain,
+        ))
+        .to_owned()
+}
+
+fn id(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .big_integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
+
+fn domain(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .string_len(255)
+        .not_null()
+        .default("")
+        .to_owned()
+}
+
+/// The reference of `table` to the paste it belongs to.
+fn paste_key(
+    table: impl IntoIden + 'static,
+    domain: impl IntoIden + 'static,
+    id: impl IntoIden + 'static,
+    by_domain: bool,
+) -> ForeignKeyCreateStatement {
+    let mut key = ForeignKey::create();
+    if by_domain {
+        key.from(table, (domain, id))
+            .to(Pastes::Table, (Pastes::Domain, Pastes::Id));
+    } else {
+        key.from(table, id).to(Pastes::Table, Pastes::Id);
+    }
+    key.on_delete(ForeignKeyAction::Cascade).to_owned()
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Domains {
+    Table,
+    Id,
+    Host,
+    UserId,
+    VerificationToken,
+    VerifiedAt,
+    InsertedAt,
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Id,
+    IsUrl,
+    Content,
+    BelongsTo,
+    HiddenAt,
+    HiddenReason,
+    RedirectCode,
+    Preview,
+    ExpiresAt,
+    MaxClicks,
+    ClickCount,
+    Domain,
+}
+
+#[derive(DeriveIden)]
+enum Reports {
+    Table,
+    Id,
+    PasteId,
+    ReporterId,
+    Reason,
+    Status,
+    InsertedAt,
+    ResolvedAt,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum Clicks {
+    Table,
+    Id,
+    PasteId,
+    ClickedAt,
+    ReferrerHost,
+    UserAgent,
+    Country,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum ModerationActions {
+    Table,
+    PasteDomain,
+}
@@ -0,0 +1,124 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LinkChecks::Table)
+                    .col(
+                        ColumnDef::new(LinkChecks::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(LinkChecks::PasteDomain)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LinkChecks::PasteId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LinkChecks::CheckedAt).timestamp().not_null())
+                    .col(ColumnDef::new(LinkChecks::Healthy).boolean().not_null())
+                    .col(
+                        ColumnDef::new(LinkChecks::StatusCode)
+                            .small_integer()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(LinkChecks::Error).string_len(255).null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(
+                                LinkChecks::Table,
+                                (LinkChecks::PasteDomain, LinkChecks::PasteId),
+                            )
+                            .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("link_checks_paste_checked_at_index")
+                    .table(LinkChecks::Table)
+                    .col(LinkChecks::PasteDomain)
+                    .col(LinkChecks::PasteId)
+                    .col(LinkChecks::CheckedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::CheckedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::BrokenAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::CheckedAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::BrokenAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(LinkChecks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Domain,
+    Id,
+    CheckedAt,
+    BrokenAt,
+}
+
+#[derive(DeriveIden)]
+enum LinkChecks {
+    Table,
+    Id,
+    PasteDomain,
+    PasteId,
+    CheckedAt,
+    Healthy,
+    StatusCode,
+    Error,
+}
@@ -0,0 +1,324 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasteAliases::Table)
+                    .col(
+                        ColumnDef::new(PasteAliases::Domain)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PasteAliases::Id).string_len(255).not_null())
+                    .col(
+                        ColumnDef::new(PasteAliases::PasteId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasteAliases::InsertedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(PasteAliases::Domain)
+                            .col(PasteAliases::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("paste_aliases_paste_fkey")
+                            .from(
+                                PasteAliases::Table,
+                                (PasteAliases::Domain, PasteAliases::PasteId),
+                            )
+                            .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // renaming a paste changes its primary key, everything pointing at it follows along
+        set_paste_keys(manager, true).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        set_paste_keys(manager, false).await?;
+
+        manager
+            .drop_table(Table::drop().table(PasteAliases::Table).to_owned())
+            .await
+    }
+}
+
+/// Makes the references of reports, clicks and link checks to their paste follow it
+/// being renamed, or stop doing so when `on_update` is false.
+async fn set_paste_keys(manager: &SchemaManager<'_>, on_update: bool) -> Result<(), DbErr> {
+    if crate::is_sqlite(manager) {
+        return crate::rebuild_tables(
+            manager,
+            vec![
+                reports(on_update),
+                clicks(on_update),
+                link_checks(on_update),
+            ],
+            vec![
+                Index::create()
+                    .name("reports_status_index")
+                    .table(Reports::Table)
+                    .col(Reports::Status)
+                    .to_owned(),
+                Index::create()
+                    .name("clicks_paste_clicked_at_index")
+                    .table(Clicks::Table)
+                    .col(Clicks::PasteDomain)
+                    .col(Clicks::PasteId)
+                    .col(Clicks::ClickedAt)
+                    .to_owned(),
+                Index::create()
+                    .name("link_checks_paste_checked_at_index")
+                    .table(LinkChecks::Table)
+                    .col(LinkChecks::PasteDomain)
+                    .col(LinkChecks::PasteId)
+                    .col(LinkChecks::CheckedAt)
+                    .to_owned(),
+            ],
+        )
+        .await;
+    }
+
+    // the key of link checks had the name Postgres gave it before
+    let link_checks_key = if on_update {
+        (
+            "link_checks_paste_domain_paste_id_fkey",
+            "link_checks_paste_fkey",
+        )
+    } else {
+        (
+            "link_checks_paste_fkey",
+            "link_checks_paste_domain_paste_id_fkey",
+        )
+    };
+    let keys = [
+        (
+            Reports::Table.into_iden(),
+            ("reports_paste_fkey", "reports_paste_fkey"),
+            paste_key(
+                Reports::Table,
+                Reports::PasteDomain,
+                Reports::PasteId,
+                on_update,
+            ),
+        ),
+        (
+            Clicks::Table.into_iden(),
+            ("clicks_paste_fkey", "clicks_paste_fkey"),
+            paste_key(
+                Clicks::Table,
+                Clicks::PasteDomain,
+                Clicks::PasteId,
+                on_update,
+            ),
+        ),
+        (
+            LinkChecks::Table.into_iden(),
+            link_checks_key,
+            paste_key(
+                LinkChecks::Table,
+                LinkChecks::PasteDomain,
+                LinkChecks::PasteId,
+                on_update,
+            ),
+        ),
+    ];
+    for (table, (old, new), mut key) in keys {
+        manager
+            .drop_foreign_key(ForeignKey::drop().name(old).table(table).to_owned())
+            .await?;
+        manager.create_foreign_key(key.name(new).to_owned()).await?;
+    }
+    Ok(())
+}
+
+fn reports(on_update: bool) -> TableCreateStatement {
+    Table::create()
+        .table(Reports::Table)
+        .col(id(Reports::Id))
+        .col(ColumnDef::new(Reports::PasteId).string_len(255).not_null())
+        .col(ColumnDef::new(Reports::ReporterId).big_integer().null())
+        .col(ColumnDef::new(Reports::Reason).text().not_null())
+        .col(
+            ColumnDef::new(Reports::Status)
+                .string_len(32)
+                .not_null()
+                .default("open"),
+        )
+        .col(ColumnDef::new(Reports::InsertedAt).timestamp().not_null())
+        .col(ColumnDef::new(Reports::ResolvedAt).timestamp().null())
+        .col(domain(Reports::PasteDomain))
+        .foreign_key(&mut paste_key(
+            Reports::Table,
+            Reports::PasteDomain,
+            Reports::PasteId,
+            on_update,
+        ))
+        .foreign_key(
+            ForeignKey::create()
+                .from(Reports::Table, Reports::ReporterId)
+                .to(Users::Table, Users::Id)
+                .on_delete(ForeignKeyAction::SetNull),
+        )
+        .to_owned()
+}
+
+fn clicks(on_update: bool) -> TableCreateStatement {
+    Table::create()
+        .table(Clicks::Table)
+        .col(id(Clicks::Id))
+        .col(ColumnDef::new(Clicks::PasteId).string_len(255).not_null())
+        .col(ColumnDef::new(Clicks::ClickedAt).timestamp().not_null())
+        .col(ColumnDef::new(Clicks::ReferrerHost).string_len(255).null())
+        .col(ColumnDef::new(Clicks::UserAgent).string_len(32).not_null())
+        .col(ColumnDef::new(Clicks::Country).string_len(2).null())
+        .col(domain(Clicks::PasteDomain))
+        .foreign_key(&mut paste_key(
+            Clicks::Table,
+            Clicks::PasteDomain,
+            Clicks::PasteId,
+            on_update,
+        ))
+        .to_owned()
+}
+
+fn link_checks(on_update: bool) -> TableCreateStatement {
+    Table::create()
+        .table(LinkChecks::Table)
+        .col(id(LinkChecks::Id))
+        .col(
+            ColumnDef::new(LinkChecks::PasteDomain)
+                .string_len(255)
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(LinkChecks::PasteId)
+                .string_len(255)
+                .not_null(),
+        )
+        .col(ColumnDef::new(LinkChecks::CheckedAt).timestamp().not_null())
+        .col(ColumnDef::new(LinkChecks::Healthy).boolean().not_null())
+        .col(
+            ColumnDef::new(LinkChecks::StatusCode)
+                .small_integer()
+                .null(),
+        )
+        .col(ColumnDef::new(LinkChecks::Error).string_len(255).null())
+        .foreign_key(&mut paste_key(
+            LinkChecks::Table,
+            LinkChecks::PasteDomain,
+            LinkChecks::PasteId,
+            on_update,
+        ))
+        .to_owned()
+}
+
+fn id(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .big_integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
+
+fn domain(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .string_len(255)
+        .not_null()
+        .default("")
+        .to_owned()
+}
+
+/// The reference of `table` to the paste it belongs to.
+fn paste_key(
+    table: impl IntoIden + 'static,
+    domain: impl IntoIden + 'static,
+    id: impl IntoIden + 'static,
+    on_update: bool,
+) -> ForeignKeyCreateStatement {
+    let mut key = ForeignKey::create()
+        .from(table, (domain, id))
+        .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+        .on_delete(ForeignKeyAction::Cascade)
+        .to_owned();
+    if on_update {
+        key.on_update(ForeignKeyAction::Cascade);
+    }
+    key
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Domain,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum PasteAliases {
+    Table,
+    Domain,
+    Id,
+    PasteId,
+    InsertedAt,
+}
+
+#[derive(DeriveIden)]
+enum Reports {
+    Table,
+    Id,
+    PasteId,
+    ReporterId,
+    Reason,
+    Status,
+    InsertedAt,
+    ResolvedAt,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum Clicks {
+    Table,
+    Id,
+    PasteId,
+    ClickedAt,
+    ReferrerHost,
+    UserAgent,
+    Country,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum LinkChecks {
+    Table,
+    Id,
+    PasteDomain,
+    PasteId,
+    CheckedAt,
+    Healthy,
+    StatusCode,
+    Error,
+}
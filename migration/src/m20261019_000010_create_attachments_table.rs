@@ -0,0 +1,102 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Attachments::Table)
+                    .col(
+                        ColumnDef::new(Attachments::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    // both are cleared when the paste is deleted, the blob is removed
+                    // from storage afterwards by the cleanup task
+                    .col(
+                        ColumnDef::new(Attachments::PasteDomain)
+                            .string_len(255)
+                            .null(),
+                    )
+                    .col(ColumnDef::new(Attachments::PasteId).string_len(255).null())
+                    .col(
+                        ColumnDef::new(Attachments::Filename)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Attachments::ContentType)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Attachments::Size).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Attachments::StorageKey)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Attachments::InsertedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("attachments_paste_fkey")
+                            .from(
+                                Attachments::Table,
+                                (Attachments::PasteDomain, Attachments::PasteId),
+                            )
+                            .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("attachments_paste_index")
+                    .table(Attachments::Table)
+                    .col(Attachments::PasteDomain)
+                    .col(Attachments::PasteId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Attachments::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Domain,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Attachments {
+    Table,
+    Id,
+    PasteDomain,
+    PasteId,
+    Filename,
+    ContentType,
+    Size,
+    StorageKey,
+    InsertedAt,
+}
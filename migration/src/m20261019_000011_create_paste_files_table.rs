@@ -0,0 +1,117 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // name and language of the first file, its content stays in pastes.content
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::Filename).string_len(255).null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::Language).string_len(32).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasteFiles::Table)
+                    .col(
+                        ColumnDef::new(PasteFiles::PasteDomain)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasteFiles::PasteId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasteFiles::Position)
+                            .small_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasteFiles::Filename)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PasteFiles::Language).string_len(32).null())
+                    .col(ColumnDef::new(PasteFiles::Content).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(PasteFiles::PasteDomain)
+                            .col(PasteFiles::PasteId)
+                            .col(PasteFiles::Position),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("paste_files_paste_fkey")
+                            .from(
+                                PasteFiles::Table,
+                                (PasteFiles::PasteDomain, PasteFiles::PasteId),
+                            )
+                            .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasteFiles::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::Filename)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::Language)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Domain,
+    Id,
+    Filename,
+    Language,
+}
+
+#[derive(DeriveIden)]
+enum PasteFiles {
+    Table,
+    PasteDomain,
+    PasteId,
+    Position,
+    Filename,
+    Language,
+    Content,
+}
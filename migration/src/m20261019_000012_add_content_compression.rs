@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // compressed pastes keep an empty content column and their bytes in content_compressed,
+        // the codec says how to get the text back
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ContentCodec).string_len(16).null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ContentCompressed).binary().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::ContentCodec)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::ContentCompressed)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    ContentCodec,
+    ContentCompressed,
+}
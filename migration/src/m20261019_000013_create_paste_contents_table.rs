@@ -0,0 +1,705 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Value};
+use sha2::{Digest, Sha256};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasteContents::Table)
+                    .col(
+                        ColumnDef::new(PasteContents::Hash)
+                            .string_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PasteContents::Content).text().not_null())
+                    .col(
+                        ColumnDef::new(PasteContents::ContentCodec)
+                            .string_len(16)
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasteContents::ContentCompressed)
+                            .binary()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(PasteContents::RefCount).integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // bodies are keyed by the SHA-256 of their text, short links keep their url inline
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ContentHash).string_len(64).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        loop {
+            let rows = db
+                .query_all(
+                    backend.build(
+                        Query::select()
+                            .columns([
+                                Pastes::Domain,
+                                Pastes::Id,
+                                Pastes::Content,
+                                Pastes::ContentCodec,
+                                Pastes::ContentCompressed,
+                            ])
+                            .from(Pastes::Table)
+                            .and_where(Expr::col(Pastes::IsUrl).eq(false))
+                            .and_where(Expr::col(Pastes::ContentHash).is_null())
+                            .limit(500),
+                    ),
+                )
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let domain: String = row.try_get("", "domain")?;
+                let id: String = row.try_get("", "id")?;
+                let codec: Option<String> = row.try_get("", "content_codec")?;
+
+                // compressed bodies have to be decompressed to be hashed like the others
+                let (hash, content, compressed) = match codec {
+                    Some(codec) => {
+                        let compressed: Vec<u8> = row.try_get("", "content_compressed")?;
+                        let content = zstd::decode_all(compressed.as_slice()).map_err(|e| {
+                            DbErr::Migration(format!("paste {} can't be decompressed: {}", id, e))
+                        })?;
+                        let hash = hex::encode(Sha256::digest(content));
+                        (hash, String::new(), Some((codec, compressed)))
+                    }
+                    None => {
+                        let content: String = row.try_get("", "content")?;
+                        (hex::encode(Sha256::digest(&content)), content, None)
+                    }
+                };
+                let (codec, compressed) = compressed.unzip();
+
+                manager
+                    .exec_stmt(
+                        Query::insert()
+                            .into_table(PasteContents::Table)
+                            .columns([
+                                PasteContents::Hash,
+                                PasteContents::Content,
+                                PasteContents::ContentCodec,
+                                PasteContents::ContentCompressed,
+                                PasteContents::RefCount,
+                            ])
+                            .values_panic([
+                                hash.clone().into(),
+                                content.into(),
+                                codec.into(),
+                                Value::Bytes(compressed.map(Box::new)).into(),
+                                0.into(),
+                            ])
+                            .on_conflict(
+                                OnConflict::column(PasteContents::Hash)
+                                    .do_nothing()
+                                    .to_owned(),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .exec_stmt(
+                        Query::update()
+                            .table(Pastes::Table)
+                            .value(Pastes::ContentHash, hash)
+                            .and_where(Expr::col(Pastes::Domain).eq(domain))
+                            .and_where(Expr::col(Pastes::Id).eq(id))
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(PasteContents::Table)
+                    .value(
+                        PasteContents::RefCount,
+                        SimpleExpr::SubQuery(
+                            None,
+                            Box::new(
+                                Query::select()
+                                    .expr(Expr::col((Pastes::Table, Pastes::ContentHash)).count())
+                                    .from(Pastes::Table)
+                                    .and_where(
+                                        Expr::col((Pastes::Table, Pastes::ContentHash))
+                                            .equals((PasteContents::Table, PasteContents::Hash)),
+                                    )
+                                    .to_owned()
+                                    .into_sub_query_statement(),
+                            ),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Pastes::Table)
+                    .value(Pastes::Content, "")
+                    .and_where(Expr::col(Pastes::ContentHash).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [Pastes::ContentCodec, Pastes::ContentCompressed] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Pastes::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        set_content_key(manager, true).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("pastes_content_hash_index")
+                    .table(Pastes::Table)
+                    .col(Pastes::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("pastes_content_hash_index")
+                    .table(Pastes::Table)
+                    .to_owned(),
+            )
+            .await?;
+        set_content_key(manager, false).await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ContentCodec).string_len(16).null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::ContentCompressed).binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let stored = |column: PasteContents| {
+            SimpleExpr::SubQuery(
+                None,
+                Box::new(
+                    Query::select()
+                        .column(column)
+                        .from(PasteContents::Table)
+                        .and_where(
+                            Expr::col((PasteContents::Table, PasteContents::Hash))
+                                .equals((Pastes::Table, Pastes::ContentHash)),
+                        )
+                        .to_owned()
+                        .into_sub_query_statement(),
+                ),
+            )
+        };
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Pastes::Table)
+                    .values([
+                        (Pastes::Content, stored(PasteContents::Content)),
+                        (Pastes::ContentCodec, stored(PasteContents::ContentCodec)),
+                        (
+                            Pastes::ContentCompressed,
+                            stored(PasteContents::ContentCompressed),
+                        ),
+                    ])
+                    .and_where(Expr::col(Pastes::ContentHash).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::ContentHash)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(PasteContents::Table).to_owned())
+            .await
+    }
+}
+
+/// Makes pastes reference the content they're stored with, or stop doing so when
+/// `referenced` is false.
+async fn set_content_key(manager: &SchemaManager<'_>, referenced: bool) -> Result<(), DbErr> {
+    if !crate::is_sqlite(manager) {
+        return if referenced {
+            manager.create_foreign_key(content_key()).await
+        } else {
+            manager
+                .drop_foreign_key(
+                    ForeignKey::drop()
+                        .name("pastes_content_fkey")
+                        .table(Pastes::Table)
+                        .to_owned(),
+                )
+                .await
+        };
+    }
+
+    // everything referencing pastes is rebuilt along with it
+    let mut tables = vec![pastes(referenced)];
+    tables.extend(paste_children());
+    crate::rebuild_tables(
+        manager,
+        tables,
+        vec![
+            Index::create()
+                .name("pastes_belongs_to_index")
+                .table(Pastes::Table)
+                .col(Pastes::BelongsTo)
+                .to_owned(),
+            Index::create()
+                .name("reports_status_index")
+                .table(Reports::Table)
+                .col(Reports::Status)
+                .to_owned(),
+            Index::create()
+                .name("clicks_paste_clicked_at_index")
+                .table(Clicks::Table)
+                .col(Clicks::PasteDomain)
+                .col(Clicks::PasteId)
+                .col(Clicks::ClickedAt)
+                .to_owned(),
+            Index::create()
+                .name("link_checks_paste_checked_at_index")
+                .table(LinkChecks::Table)
+                .col(LinkChecks::PasteDomain)
+                .col(LinkChecks::PasteId)
+                .col(LinkChecks::CheckedAt)
+                .to_owned(),
+            Index::create()
+                .name("attachments_paste_index")
+                .table(Attachments::Table)
+                .col(Attachments::PasteDomain)
+                .col(Attachments::PasteId)
+                .to_owned(),
+        ],
+    )
+    .await
+}
+
+fn content_key() -> ForeignKeyCreateStatement {
+    ForeignKey::create()
+        .name("pastes_content_fkey")
+        .from(Pastes::Table, Pastes::ContentHash)
+        .to(PasteContents::Table, PasteContents::Hash)
+        .to_owned()
+}
+
+fn pastes(referenced: bool) -> TableCreateStatement {
+    let mut table = Table::create()
+        .table(Pastes::Table)
+        .col(ColumnDef::new(Pastes::Id).string_len(255).not_null())
+        .col(
+            ColumnDef::new(Pastes::IsUrl)
+                .boolean()
+                .not_null()
+                .default(false),
+        )
+        .col(ColumnDef::new(Pastes::Content).text().not_null())
+        .col(ColumnDef::new(Pastes::BelongsTo).big_integer().null())
+        .col(ColumnDef::new(Pastes::HiddenAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::HiddenReason).string_len(32).null())
+        .col(
+            ColumnDef::new(Pastes::RedirectCode)
+                .small_integer()
+                .not_null()
+                .default(307),
+        )
+        .col(
+            ColumnDef::new(Pastes::Preview)
+                .boolean()
+                .not_null()
+                .default(false),
+        )
+        .col(ColumnDef::new(Pastes::ExpiresAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::MaxClicks).integer().null())
+        .col(
+            ColumnDef::new(Pastes::ClickCount)
+                .integer()
+                .not_null()
+                .default(0),
+        )
+        .col(domain(Pastes::Domain))
+        .col(ColumnDef::new(Pastes::CheckedAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::BrokenAt).timestamp().null())
+        .col(ColumnDef::new(Pastes::Filename).string_len(255).null())
+        .col(ColumnDef::new(Pastes::Language).string_len(32).null())
+        .col(ColumnDef::new(Pastes::ContentHash).string_len(64).null())
+        .primary_key(Index::create().col(Pastes::Domain).col(Pastes::Id))
+        .foreign_key(
+            ForeignKey::create()
+                .from(Pastes::Table, Pastes::BelongsTo)
+                .to(Users::Table, Users::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .to_owned();
+    if referenced {
+        table.foreign_key(&mut content_key());
+    }
+    table
+}
+
+/// The tables referencing pastes, as they are at this point.
+fn paste_children() -> Vec<TableCreateStatement> {
+    vec![
+        Table::create()
+            .table(Reports::Table)
+            .col(id(Reports::Id))
+            .col(ColumnDef::new(Reports::PasteId).string_len(255).not_null())
+            .col(ColumnDef::new(Reports::ReporterId).big_integer().null())
+            .col(ColumnDef::new(Reports::Reason).text().not_null())
+            .col(
+                ColumnDef::new(Reports::Status)
+                    .string_len(32)
+                    .not_null()
+                    .default("open"),
+            )
+            .col(ColumnDef::new(Reports::InsertedAt).timestamp().not_null())
+            .col(ColumnDef::new(Reports::ResolvedAt).timestamp().null())
+            .col(domain(Reports::PasteDomain))
+            .foreign_key(&mut paste_key(
+                Reports::Table,
+                Reports::PasteDomain,
+                Reports::PasteId,
+            ))
+            .foreign_key(
+                ForeignKey::create()
+                    .from(Reports::Table, Reports::ReporterId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::SetNull),
+            )
+            .to_owned(),
+        Table::create()
+            .table(Clicks::Table)
+            .col(id(Clicks::Id))
+            .col(ColumnDef::new(Clicks::PasteId).string_len(255).not_null())
+            .col(ColumnDef::new(Clicks::ClickedAt).timestamp().not_null())
+            .col(ColumnDef::new(Clicks::ReferrerHost).string_len(255).null())
+            .col(ColumnDef::new(Clicks::UserAgent).string_len(32).not_null())
+            .col(ColumnDef::new(Clicks::Country).string_len(2).null())
+            .col(domain(Clicks::PasteDomain))
+            .foreign_key(&mut paste_key(
+                Clicks::Table,
+                Clicks::PasteDomain,
+                Clicks::PasteId,
+            ))
+            .to_owned(),
+        Table::create()
+            .table(LinkChecks::Table)
+            .col(id(LinkChecks::Id))
+            .col(
+                ColumnDef::new(LinkChecks::PasteDomain)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(LinkChecks::PasteId)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(ColumnDef::new(LinkChecks::CheckedAt).timestamp().not_null())
+            .col(ColumnDef::new(LinkChecks::Healthy).boolean().not_null())
+            .col(
+                ColumnDef::new(LinkChecks::StatusCode)
+                    .small_integer()
+                    .null(),
+            )
+            .col(ColumnDef::new(LinkChecks::Error).string_len(255).null())
+            .foreign_key(&mut paste_key(
+                LinkChecks::Table,
+                LinkChecks::PasteDomain,
+                LinkChecks::PasteId,
+            ))
+            .to_owned(),
+        Table::create()
+            .table(PasteAliases::Table)
+            .col(
+                ColumnDef::new(PasteAliases::Domain)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(ColumnDef::new(PasteAliases::Id).string_len(255).not_null())
+            .col(
+                ColumnDef::new(PasteAliases::PasteId)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(PasteAliases::InsertedAt)
+                    .timestamp()
+                    .not_null(),
+            )
+            .primary_key(
+                Index::create()
+                    .col(PasteAliases::Domain)
+                    .col(PasteAliases::Id),
+            )
+            .foreign_key(&mut paste_key(
+                PasteAliases::Table,
+                PasteAliases::Domain,
+                PasteAliases::PasteId,
+            ))
+            .to_owned(),
+        Table::create()
+            .table(Attachments::Table)
+            .col(id(Attachments::Id))
+            .col(
+                ColumnDef::new(Attachments::PasteDomain)
+                    .string_len(255)
+                    .null(),
+            )
+            .col(ColumnDef::new(Attachments::PasteId).string_len(255).null())
+            .col(
+                ColumnDef::new(Attachments::Filename)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(Attachments::ContentType)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(ColumnDef::new(Attachments::Size).big_integer().not_null())
+            .col(
+                ColumnDef::new(Attachments::StorageKey)
+                    .string_len(255)
+                    .not_null()
+                    .unique_key(),
+            )
+            .col(
+                ColumnDef::new(Attachments::InsertedAt)
+                    .timestamp()
+                    .not_null(),
+            )
+            .foreign_key(
+                paste_key(
+                    Attachments::Table,
+                    Attachments::PasteDomain,
+                    Attachments::PasteId,
+                )
+                .on_delete(ForeignKeyAction::SetNull),
+            )
+            .to_owned(),
+        Table::create()
+            .table(PasteFiles::Table)
+            .col(
+                ColumnDef::new(PasteFiles::PasteDomain)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(PasteFiles::PasteId)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(PasteFiles::Position)
+                    .small_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(PasteFiles::Filename)
+                    .string_len(255)
+                    .not_null(),
+            )
+            .col(ColumnDef::new(PasteFiles::Language).string_len(32).null())
+            .col(ColumnDef::new(PasteFiles::Content).text().not_null())
+            .primary_key(
+                Index::create()
+                    .col(PasteFiles::PasteDomain)
+                    .col(PasteFiles::PasteId)
+                    .col(PasteFiles::Position),
+            )
+            .foreign_key(&mut paste_key(
+                PasteFiles::Table,
+                PasteFiles::PasteDomain,
+                PasteFiles::PasteId,
+            ))
+            .to_owned(),
+    ]
+}
+
+fn id(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .big_integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
+
+fn domain(column: impl IntoIden + 'static) -> ColumnDef {
+    ColumnDef::new(column)
+        .string_len(255)
+        .not_null()
+        .default("")
+        .to_owned()
+}
+
+/// The reference of `table` to the paste it belongs to.
+fn paste_key(
+    table: impl IntoIden + 'static,
+    domain: impl IntoIden + 'static,
+    id: impl IntoIden + 'static,
+) -> ForeignKeyCreateStatement {
+    ForeignKey::create()
+        .from(table, (domain, id))
+        .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+        .on_delete(ForeignKeyAction::Cascade)
+        .on_update(ForeignKeyAction::Cascade)
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum PasteContents {
+    Table,
+    Hash,
+    Content,
+    ContentCodec,
+    ContentCompressed,
+    RefCount,
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Id,
+    IsUrl,
+    Content,
+    BelongsTo,
+    HiddenAt,
+    HiddenReason,
+    RedirectCode,
+    Preview,
+    ExpiresAt,
+    MaxClicks,
+    ClickCount,
+    Domain,
+    CheckedAt,
+    BrokenAt,
+    Filename,
+    Language,
+    ContentCodec,
+    ContentCompressed,
+    ContentHash,
+}
+
+#[derive(DeriveIden)]
+enum Reports {
+    Table,
+    Id,
+    PasteId,
+    ReporterId,
+    Reason,
+    Status,
+    InsertedAt,
+    ResolvedAt,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum Clicks {
+    Table,
+    Id,
+    PasteId,
+    ClickedAt,
+    ReferrerHost,
+    UserAgent,
+    Country,
+    PasteDomain,
+}
+
+#[derive(DeriveIden)]
+enum LinkChecks {
+    Table,
+    Id,
+    PasteDomain,
+    PasteId,
+    CheckedAt,
+    Healthy,
+    StatusCode,
+    Error,
+}
+
+#[derive(DeriveIden)]
+enum PasteAliases {
+    Table,
+    Domain,
+    Id,
+    PasteId,
+    InsertedAt,
+}
+
+#[derive(DeriveIden)]
+enum Attachments {
+    Table,
+    Id,
+    PasteDomain,
+    PasteId,
+    Filename,
+    ContentType,
+    Size,
+    StorageKey,
+    InsertedAt,
+}
+
+#[derive(DeriveIden)]
+enum PasteFiles {
+    Table,
+    PasteDomain,
+    PasteId,
+    Position,
+    Filename,
+    Language,
+    Content,
+}
@@ -0,0 +1,164 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// The search vector of a paste: its file names, whole and in parts, weighted above the
+/// content of all its files. `{content}` is the content of the first file, the others
+/// come from paste_files. Only the first 256 KiB are indexed, a tsvector can't grow
+/// past 1 MB.
+const SEARCH_VECTOR: &str = "setweight(to_tsvector('simple', concat_ws(' ', {names},
+        regexp_replace({names}, '[^[:alnum:]]+', ' ', 'g'))), 'A')
+    || setweight(to_tsvector('simple', left(concat_ws(E'\\n', {content},
+        (SELECT string_agg(f.content, E'\\n' ORDER BY f.position) FROM paste_files f
+        WHERE f.paste_domain = pastes.domain AND f.paste_id = pastes.id)), 262144)), 'B')";
+
+/// The file names of a paste, for [`SEARCH_VECTOR`].
+const NAMES: &str = "concat_ws(' ', pastes.filename,
+    (SELECT string_agg(f.filename, ' ') FROM paste_files f
+    WHERE f.paste_domain = pastes.domain AND f.paste_id = pastes.id))";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // pastes created before this have no creation date
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(ColumnDef::new(Pastes::InsertedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("pastes_belongs_to_inserted_at_index")
+                    .table(Pastes::Table)
+                    .col(Pastes::BelongsTo)
+                    .col(Pastes::InsertedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        // SQLite databases search with the embedded index instead
+        if crate::is_sqlite(manager) {
+            return Ok(());
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .add_column(
+                        ColumnDef::new(Pastes::SearchVector)
+                            .custom(Alias::new("tsvector"))
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        db.execute_unprepared(&format!(
+            "UPDATE pastes SET search_vector = {}
+            FROM paste_contents
+            WHERE pastes.content_hash = paste_contents.hash
+                AND paste_contents.content_codec IS NULL",
+            SEARCH_VECTOR
+                .replace("{names}", NAMES)
+                .replace("{content}", "paste_contents.content")
+        ))
+        .await?;
+
+        // compressed bodies have to be decompressed to be indexed
+        let mut after = String::new();
+        loop {
+            let rows = db
+                .query_all(Statement::from_sql_and_values(
+                    manager.get_database_backend(),
+                    "SELECT hash, content_compressed FROM paste_contents
+                    WHERE content_codec IS NOT NULL AND hash > $1 ORDER BY hash LIMIT 100",
+                    [after.clone().into()],
+                ))
+                .await?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            after = last.try_get("", "hash")?;
+
+            for row in rows {
+                let hash: String = row.try_get("", "hash")?;
+                let compressed: Vec<u8> = row.try_get("", "content_compressed")?;
+                let content = zstd::decode_all(compressed.as_slice())
+                    .ok()
+                    .and_then(|content| String::from_utf8(content).ok())
+                    .ok_or_else(|| {
+                        DbErr::Migration(format!("content {} can't be decompressed", hash))
+                    })?;
+
+                db.execute(Statement::from_sql_and_values(
+                    manager.get_database_backend(),
+                    format!(
+                        "UPDATE pastes SET search_vector = {} WHERE content_hash = $2",
+                        SEARCH_VECTOR
+                            .replace("{names}", NAMES)
+                            .replace("{content}", "$1")
+                    ),
+                    [content.into(), hash.into()],
+                ))
+                .await?;
+            }
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("pastes_search_vector_index")
+                    .table(Pastes::Table)
+                    .col(Pastes::SearchVector)
+                    .full_text()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_sqlite(manager) {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Pastes::Table)
+                        .drop_column(Pastes::SearchVector)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("pastes_belongs_to_inserted_at_index")
+                    .table(Pastes::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Pastes::Table)
+                    .drop_column(Pastes::InsertedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    BelongsTo,
+    InsertedAt,
+    SearchVector,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // emails are compared lowercased instead of with citext, which only Postgres has.
+        // The citext unique index already kept out emails differing only in case.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Users::Table)
+                    .value(Users::Email, Func::lower(Expr::col(Users::Email)))
+                    .to_owned(),
+            )
+            .await?;
+
+        // SQLite databases never had the citext column
+        if crate::is_sqlite(manager) {
+            return Ok(());
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .modify_column(ColumnDef::new(Users::Email).string_len(255).not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // lowercased emails in a varchar column work the same as in a citext one, and
+        // databases created since this have no citext extension to go back to
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Email,
+}
@@ -0,0 +1,347 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+use sha2::{Digest, Sha256};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the files of a paste are stored once in paste_contents like its first one
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PasteFiles::Table)
+                    .add_column(
+                        ColumnDef::new(PasteFiles::ContentHash)
+                            .string_len(64)
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        loop {
+            let rows = db
+                .query_all(
+                    backend.build(
+                        Query::select()
+                            .columns([
+                                PasteFiles::PasteDomain,
+                                PasteFiles::PasteId,
+                                PasteFiles::Position,
+                                PasteFiles::Content,
+                            ])
+                            .from(PasteFiles::Table)
+                            .and_where(Expr::col(PasteFiles::ContentHash).is_null())
+                            .limit(500),
+                    ),
+                )
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let content: String = row.try_get("", "content")?;
+                let hash = hex::encode(Sha256::digest(&content));
+                manager
+                    .exec_stmt(
+                        Query::insert()
+                            .into_table(PasteContents::Table)
+                            .columns([
+                                PasteContents::Hash,
+                                PasteContents::Content,
+                                PasteContents::RefCount,
+                            ])
+                            .values_panic([hash.clone().into(), content.into(), 0.into()])
+                            .on_conflict(
+                                OnConflict::column(PasteContents::Hash)
+                                    .do_nothing()
+                                    .to_owned(),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .exec_stmt(
+                        Query::update()
+                            .table(PasteFiles::Table)
+                            .values([
+                                (PasteFiles::Content, "".into()),
+                                (PasteFiles::ContentHash, hash.into()),
+                            ])
+                            .cond_where(file_key(&row)?)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        count_references(manager).await?;
+
+        set_content_key(manager, true).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("paste_files_content_hash_index")
+                    .table(PasteFiles::Table)
+                    .col(PasteFiles::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("paste_files_content_hash_index")
+                    .table(PasteFiles::Table)
+                    .to_owned(),
+            )
+            .await?;
+        set_content_key(manager, false).await?;
+
+        // the contents go back into the files, decompressed where they were compressed
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        loop {
+            let rows = db
+                .query_all(
+                    backend.build(
+                        Query::select()
+                            .columns([
+                                (PasteFiles::Table, PasteFiles::PasteDomain),
+                                (PasteFiles::Table, PasteFiles::PasteId),
+                                (PasteFiles::Table, PasteFiles::Position),
+                            ])
+                            .columns([
+                                (PasteContents::Table, PasteContents::Content),
+                                (PasteContents::Table, PasteContents::ContentCodec),
+                                (PasteContents::Table, PasteContents::ContentCompressed),
+                            ])
+                            .from(PasteFiles::Table)
+                            .inner_join(
+                                PasteContents::Table,
+                                Expr::col((PasteContents::Table, PasteContents::Hash))
+                                    .equals((PasteFiles::Table, PasteFiles::ContentHash)),
+                            )
+                            .limit(500),
+                    ),
+                )
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let codec: Option<String> = row.try_get("", "content_codec")?;
+                let content = match codec {
+                    Some(_) => {
+                        let compressed: Vec<u8> = row.try_get("", "content_compressed")?;
+                        zstd::decode_all(compressed.as_slice())
+                            .ok()
+                            .and_then(|content| String::from_utf8(content).ok())
+                            .ok_or_else(|| {
+                                DbErr::Migration(String::from("a paste file can't be decompressed"))
+                            })?
+                    }
+                    None => row.try_get("", "content")?,
+                };
+                manager
+                    .exec_stmt(
+                        Query::update()
+                            .table(PasteFiles::Table)
+                            .values([
+                                (PasteFiles::Content, content.into()),
+                                (PasteFiles::ContentHash, Option::<String>::None.into()),
+                            ])
+                            .cond_where(file_key(&row)?)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        count_references(manager).await?;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(PasteContents::Table)
+                    .and_where(Expr::col(PasteContents::RefCount).lte(0))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PasteFiles::Table)
+                    .drop_column(PasteFiles::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Matches the paste file `row` was read from.
+fn file_key(row: &sea_orm_migration::sea_orm::QueryResult) -> Result<Condition, DbErr> {
+    let domain: String = row.try_get("", "paste_domain")?;
+    let id: String = row.try_get("", "paste_id")?;
+    let position: i16 = row.try_get("", "position")?;
+    Ok(Condition::all()
+        .add(Expr::col(PasteFiles::PasteDomain).eq(domain))
+        .add(Expr::col(PasteFiles::PasteId).eq(id))
+        .add(Expr::col(PasteFiles::Position).eq(position)))
+}
+
+/// Sets the reference count of every content to the pastes and files using it.
+async fn count_references(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let references = |table: DynIden, column: DynIden| {
+        SimpleExpr::SubQuery(
+            None,
+            Box::new(
+                Query::select()
+                    .expr(Expr::col((table.clone(), column.clone())).count())
+                    .from(table.clone())
+                    .and_where(
+                        Expr::col((table, column))
+                            .equals((PasteContents::Table, PasteContents::Hash)),
+                    )
+                    .to_owned()
+                    .into_sub_query_statement(),
+            ),
+        )
+    };
+    manager
+        .exec_stmt(
+            Query::update()
+                .table(PasteContents::Table)
+                .value(
+                    PasteContents::RefCount,
+                    references(Pastes::Table.into_iden(), Pastes::ContentHash.into_iden()).add(
+                        references(
+                            PasteFiles::Table.into_iden(),
+                            PasteFiles::ContentHash.into_iden(),
+                        ),
+                    ),
+                )
+                .to_owned(),
+        )
+        .await
+}
+
+/// Makes paste files reference the content they're stored with, or stop doing so when
+/// `referenced` is false.
+async fn set_content_key(manager: &SchemaManager<'_>, referenced: bool) -> Result<(), DbErr> {
+    if crate::is_sqlite(manager) {
+        return crate::rebuild_tables(manager, vec![paste_files(referenced)], Vec::new()).await;
+    }
+
+    if referenced {
+        manager.create_foreign_key(content_key()).await
+    } else {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name("paste_files_content_fkey")
+                    .table(PasteFiles::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+fn content_key() -> ForeignKeyCreateStatement {
+    ForeignKey::create()
+        .name("paste_files_content_fkey")
+        .from(PasteFiles::Table, PasteFiles::ContentHash)
+        .to(PasteContents::Table, PasteContents::Hash)
+        .to_owned()
+}
+
+fn paste_files(referenced: bool) -> TableCreateStatement {
+    let mut table = Table::create()
+        .table(PasteFiles::Table)
+        .col(
+            ColumnDef::new(PasteFiles::PasteDomain)
+                .string_len(255)
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(PasteFiles::PasteId)
+                .string_len(255)
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(PasteFiles::Position)
+                .small_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(PasteFiles::Filename)
+                .string_len(255)
+                .not_null(),
+        )
+        .col(ColumnDef::new(PasteFiles::Language).string_len(32).null())
+        .col(ColumnDef::new(PasteFiles::Content).text().not_null())
+        .col(
+            ColumnDef::new(PasteFiles::ContentHash)
+                .string_len(64)
+                .null(),
+        )
+        .primary_key(
+            Index::create()
+                .col(PasteFiles::PasteDomain)
+                .col(PasteFiles::PasteId)
+                .col(PasteFiles::Position),
+        )
+        .foreign_key(
+            ForeignKey::create()
+                .name("paste_files_paste_fkey")
+                .from(
+                    PasteFiles::Table,
+                    (PasteFiles::PasteDomain, PasteFiles::PasteId),
+                )
+                .to(Pastes::Table, (Pastes::Domain, Pastes::Id))
+                .on_delete(ForeignKeyAction::Cascade)
+                .on_update(ForeignKeyAction::Cascade),
+        )
+        .to_owned();
+    if referenced {
+        table.foreign_key(&mut content_key());
+    }
+    table
+}
+
+#[derive(DeriveIden)]
+enum Pastes {
+    Table,
+    Domain,
+    Id,
+    ContentHash,
+}
+
+#[derive(DeriveIden)]
+enum PasteContents {
+    Table,
+    Hash,
+    Content,
+    ContentCodec,
+    ContentCompressed,
+    RefCount,
+}
+
+#[derive(DeriveIden)]
+enum PasteFiles {
+    Table,
+    PasteDomain,
+    PasteId,
+    Position,
+    Filename,
+    Language,
+    Content,
+    ContentHash,
+}
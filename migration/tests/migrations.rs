@@ -0,0 +1,164 @@
+use migration::sea_orm::{ConnectionTrait, Database, DatabaseConnection};
+use migration::{Migrator, MigratorTrait, SchemaManager};
+
+const TABLES: [&str; 4] = ["users", "pastes", "users_tokens", "paste_files"];
+
+async fn assert_tables(db: &DatabaseConnection, exist: bool) {
+    let manager = SchemaManager::new(db);
+    for table in TABLES {
+        assert_eq!(
+            manager.has_table(table).await.unwrap(),
+            exist,
+            "table {} exists: {}",
+            table,
+            !exist
+        );
+    }
+}
+
+async fn up_down_up(db: &DatabaseConnection) {
+    Migrator::up(db, None).await.unwrap();
+    assert_tables(db, true).await;
+    assert!(Migrator::get_pending_migrations(db)
+        .await
+        .unwrap()
+        .is_empty());
+
+    Migrator::down(db, None).await.unwrap();
+    assert_tables(db, false).await;
+    assert!(Migrator::get_applied_migrations(db)
+        .await
+        .unwrap()
+        .is_empty());
+
+    Migrator::up(db, None).await.unwrap();
+    assert_tables(db, true).await;
+}
+
+#[async_std::test]
+async fn sqlite_up_down_up() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    up_down_up(&db).await;
+}
+
+/// Runs against the Postgres database in `TEST_DATABASE_URL`, which is wiped. Run with
+/// `TEST_DATABASE_URL=postgres://… cargo test -p migration -- --ignored`.
+#[async_std::test]
+#[ignore = "needs a scratch Postgres database in TEST_DATABASE_URL"]
+async fn postgres_up_down_up() {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL isn't set");
+    let db = Database::connect(url).await.unwrap();
+    Migrator::fresh(&db).await.unwrap();
+    up_down_up(&db).await;
+
+    // a database set up by the old single baseline migration, with its citext extension
+    db.execute_unprepared(
+        "DELETE FROM seaql_migrations WHERE version LIKE 'm20220119_%';
+        CREATE EXTENSION IF NOT EXISTS citext;",
+    )
+    .await
+    .unwrap();
+    Migrator::up(&db, None).await.unwrap();
+    assert_tables(&db, true).await;
+
+    Migrator::down(&db, None).await.unwrap();
+    assert_tables(&db, false).await;
+    let citext = db
+        .query_one(migration::sea_orm::Statement::from_string(
+            db.get_database_backend(),
+            "SELECT 1 FROM pg_extension WHERE extname = 'citext'",
+        ))
+        .await
+        .unwrap();
+    assert!(citext.is_none(), "the citext extension was left behind");
+}
+
+/// SQLite rebuilds tables to change their keys, their rows have to make it through that.
+#[async_std::test]
+async fn sqlite_rebuilds_keep_rows() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let before_domains = Migrator::migrations()
+        .iter()
+        .position(|m| m.name() == "m20261019_000006_create_domains_table")
+        .unwrap();
+    Migrator::up(&db, Some(before_domains as u32))
+        .await
+        .unwrap();
+
+    db.execute_unprepared(
+        "INSERT INTO users (email, hashed_password, inserted_at, updated_at)
+            VALUES ('a@example.com', 'x', '2026-10-19', '2026-10-19');
+        INSERT INTO pastes (id, content, belongs_to) VALUES ('abc', 'hello', 1);
+        INSERT INTO reports (paste_id, reason, inserted_at) VALUES ('abc', 'spam', '2026-10-19');
+        INSERT INTO clicks (paste_id, clicked_at, user_agent)
+            VALUES ('abc', '2026-10-19', 'other');",
+    )
+    .await
+    .unwrap();
+
+    let before_file_contents = Migrator::migrations()
+        .iter()
+        .position(|m| m.name() == "m20261019_000016_add_paste_file_contents")
+        .unwrap();
+    Migrator::up(&db, Some((before_file_contents - before_domains) as u32))
+        .await
+        .unwrap();
+    db.execute_unprepared(
+        "INSERT INTO paste_files (paste_domain, paste_id, position, filename, content)
+            VALUES ('', 'abc', 1, 'a.txt', 'hello'), ('', 'abc', 2, 'b.txt', 'world');",
+    )
+    .await
+    .unwrap();
+
+    Migrator::up(&db, None).await.unwrap();
+    let count = |sql: &'static str| {
+        let db = &db;
+        async move {
+            db.query_one(migration::sea_orm::Statement::from_string(
+                db.get_database_backend(),
+                sql,
+            ))
+            .await
+            .unwrap()
+            .unwrap()
+            .try_get_by_index::<i64>(0)
+            .unwrap()
+        }
+    };
+    assert_eq!(count("SELECT COUNT(*) FROM reports").await, 1);
+    assert_eq!(count("SELECT COUNT(*) FROM clicks").await, 1);
+    assert_eq!(
+        count(
+            "SELECT COUNT(*) FROM pastes JOIN paste_contents ON hash = content_hash
+            WHERE domain = '' AND id = 'abc' AND paste_contents.content = 'hello'"
+        )
+        .await,
+        1
+    );
+    assert_eq!(
+        count(
+            "SELECT COUNT(*) FROM paste_files JOIN paste_contents ON hash = content_hash
+            WHERE paste_files.content = '' AND paste_contents.content IN ('hello', 'world')"
+        )
+        .await,
+        2
+    );
+    assert_eq!(
+        count("SELECT ref_count FROM paste_contents WHERE content = 'hello'").await,
+        2
+    );
+
+    // renaming a paste carries its reports along, deleting it takes them with it
+    db.execute_unprepared("UPDATE pastes SET id = 'def' WHERE id = 'abc'")
+        .await
+        .unwrap();
+    assert_eq!(
+        count("SELECT COUNT(*) FROM reports WHERE paste_id = 'def'").await,
+        1
+    );
+    db.execute_unprepared("DELETE FROM pastes").await.unwrap();
+    assert_eq!(count("SELECT COUNT(*) FROM clicks").await, 0);
+
+    Migrator::down(&db, None).await.unwrap();
+    assert_tables(&db, false).await;
+}
@@ -0,0 +1,672 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use futures_util::stream::{BoxStream, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob not found")]
+    NotFound,
+    #[error("invalid blob key {0}")]
+    InvalidKey(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("storage backend returned {0}")]
+    Status(reqwest::StatusCode),
+    /// The data being stored went past the size limit of its source.
+    #[error("blob too large")]
+    TooLarge,
+    /// The data being stored couldn't be read, e.g. an upload that was cut off.
+    #[error("reading the blob failed: {0}")]
+    Source(String),
+}
+
+/// The content of a blob, read as it's stored. An error ends the upload and nothing
+/// is stored.
+pub type BlobStream<'a> = BoxStream<'a, Result<Bytes, BlobError>>;
+
+/// Where uploaded files are kept. Keys are generated by katbin and only contain
+/// ASCII letters, digits, `-`, `_` and `/`.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores the blob and returns its size in bytes.
+    async fn put(
+        &self,
+        key: &str,
+        data: BlobStream<'_>,
+        content_type: &str,
+    ) -> Result<u64, BlobError>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError>;
+    /// Deleting a blob that doesn't exist is not an error.
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+}
+
+/// Content types that are shown inline, anything else is served as a download.
+/// Only set when the file actually starts like one of these formats, see
+/// [`attachment_content_type`].
+const INLINE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// A fresh, unguessable key for a blob below `prefix`.
+pub fn new_blob_key(prefix: &str) -> String {
+    format!("{}/{}", prefix, crate::utils::generate_token(32))
+}
+
+/// The content type an attachment is stored and served with. Images are recognised
+/// by their first bytes rather than trusting the uploader, and anything claiming to be
+/// an image that isn't one of the inline formats (e.g. SVG, which can carry scripts)
+/// becomes a plain download.
+pub fn attachment_content_type(claimed: Option<&str>, head: &[u8]) -> String {
+    let sniffed = if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if head.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    };
+    if let Some(sniffed) = sniffed {
+        return sniffed.to_owned();
+    }
+
+    match claimed.map(|c| c.trim().to_lowercase()) {
+        Some(claimed)
+            if !claimed.starts_with("image/")
+                && claimed.len() <= 255
+                && claimed.contains('/')
+                && claimed
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/.+-_".contains(c)) =>
+        {
+            claimed
+        }
+        _ => String::from("application/octet-stream"),
+    }
+}
+
+/// Whether files of this content type are safe to show in the page.
+pub fn is_inline_type(content_type: &str) -> bool {
+    INLINE_TYPES.contains(&content_type)
+}
+
+fn check_key(key: &str) -> Result<(), BlobError> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && !key.split('/').any(|part| part.is_empty())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/'));
+    if valid {
+        Ok(())
+    } else {
+        Err(BlobError::InvalidKey(key.to_owned()))
+    }
+}
+
+/// Stores blobs as files below a directory.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalBlobStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, BlobError> {
+        check_key(key)?;
+        Ok(self.root.join(Path::new(key)))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(
+        &self,
+        key: &str,
+        data: BlobStream<'_>,
+        _content_type: &str,
+    ) -> Result<u64, BlobError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // write next to the target first so readers never see a partial file
+        let partial = path.with_extension("partial");
+        match write_file(&partial, data).await {
+            Ok((size, _)) => {
+                fs::rename(&partial, &path).await?;
+                Ok(size)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&partial).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(BlobError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stores blobs in a bucket of an S3 compatible service (AWS, MinIO, Garage, ...).
+///
+/// Objects are addressed path-style (`{endpoint}/{bucket}/{key}`), which every S3
+/// compatible server supports, and requests are signed with AWS signature version 4.
+pub struct S3BlobStore {
+    client: reqwest::Client,
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3BlobStore {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, String> {
+        let endpoint = reqwest::Url::parse(endpoint.trim_end_matches('/'))
+            .map_err(|e| format!("invalid S3 endpoint: {}", e))?;
+        if endpoint.host_str().is_none() {
+            return Err(String::from("the S3 endpoint needs a host"));
+        }
+
+        Ok(S3BlobStore {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.to_owned(),
+            region: region.to_owned(),
+            access_key: access_key.to_owned(),
+            secret_key: secret_key.to_owned(),
+        })
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: reqwest::Body,
+        content_length: u64,
+        payload_hash: &str,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::Response, BlobError> {
+        check_key(key)?;
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            self.bucket,
+            key
+        );
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+        // keys are limited to characters that don't need encoding in a path
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, host, payload_hash, timestamp, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date.as_str(), self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("x-amz-date", timestamp)
+            .header("x-amz-content-sha256", payload_hash)
+            // S3 doesn't take chunked uploads, the length has to be known up front
+            .header(reqwest::header::CONTENT_LENGTH, content_length)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    self.access_key, scope, signature
+                ),
+            )
+            .body(body);
+        if let Some(content_type) = content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Sends a request without a body.
+    async fn send_empty(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+    ) -> Result<reqwest::Response, BlobError> {
+        let payload_hash = hex::encode(Sha256::digest([]));
+        self.send(
+            method,
+            key,
+            reqwest::Body::from(Vec::new()),
+            0,
+            &payload_hash,
+            None,
+        )
+        .await
+    }
+
+    async fn put_spooled(
+        &self,
+        key: &str,
+        spool: &Path,
+        data: BlobStream<'_>,
+        content_type: &str,
+    ) -> Result<u64, BlobError> {
+        let (size, payload_hash) = write_file(spool, data).await?;
+        let file = fs::File::open(spool).await?;
+        let response = self
+            .send(
+                reqwest::Method::PUT,
+                key,
+                reqwest::Body::wrap_stream(ReaderStream::new(file)),
+                size,
+                &hex::encode(payload_hash),
+                Some(content_type),
+            )
+            .await?;
+        if !response.status().is_success() {
+            return Err(BlobError::Status(response.status()));
+        }
+        Ok(size)
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(
+        &self,
+        key: &str,
+        data: BlobStream<'_>,
+        content_type: &str,
+    ) -> Result<u64, BlobError> {
+        check_key(key)?;
+        // the signature covers the length and hash of the body, so it's spooled to a
+        // temporary file before it's sent
+        let spool = std::env::temp_dir().join(format!(
+            "katbin-{}.upload",
+            crate::utils::generate_token(16)
+        ));
+        let result = self.put_spooled(key, &spool, data, content_type).await;
+        let _ = fs::remove_file(&spool).await;
+        result
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        let response = self.send_empty(reqwest::Method::GET, key).await?;
+        match response.status() {
+            status if status.is_success() => Ok(response.bytes().await?.to_vec()),
+            reqwest::StatusCode::NOT_FOUND => Err(BlobError::NotFound),
+            status => Err(BlobError::Status(status)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        let response = self.send_empty(reqwest::Method::DELETE, key).await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::NOT_FOUND => Ok(()),
+            status => Err(BlobError::Status(status)),
+        }
+    }
+}
+
+/// Writes `data` to a new file at `path` and returns its size and SHA-256 digest.
+async fn write_file(path: &Path, mut data: BlobStream<'_>) -> Result<(u64, Vec<u8>), BlobError> {
+    let mut file = fs::File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await?;
+    Ok((size, hasher.finalize().to_vec()))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::{Request, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::Router;
+    use futures_util::stream;
+
+    use super::*;
+
+    fn chunks(chunks: &[&'static [u8]]) -> BlobStream<'static> {
+        stream::iter(
+            chunks
+                .iter()
+                .map(|c| Ok(Bytes::from_static(c)))
+                .collect::<Vec<_>>(),
+        )
+        .boxed()
+    }
+
+    /// A stream that fails after its first chunk, like an upload that was cut off.
+    fn failing() -> BlobStream<'static> {
+        stream::iter([Ok(Bytes::from_static(b"start")), Err(BlobError::TooLarge)]).boxed()
+    }
+
+    #[test]
+    fn content_types() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(
+            attachment_content_type(Some("text/plain"), png),
+            "image/png"
+        );
+        assert_eq!(
+            attachment_content_type(None, &[0xff, 0xd8, 0xff, 0xe0]),
+            "image/jpeg"
+        );
+        assert_eq!(attachment_content_type(None, b"GIF89a\x01\0"), "image/gif");
+        assert_eq!(
+            attachment_content_type(None, b"RIFF\0\0\0\0WEBPVP8 "),
+            "image/webp"
+        );
+
+        // images are only what they look like, SVGs can carry scripts
+        let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>alert(1)</script></svg>";
+        assert_eq!(
+            attachment_content_type(Some("image/svg+xml"), svg),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            attachment_content_type(Some("image/png"), b"not a png"),
+            "application/octet-stream"
+        );
+
+        assert_eq!(
+            attachment_content_type(Some(" Text/Plain "), b"hi"),
+            "text/plain"
+        );
+        assert_eq!(
+            attachment_content_type(Some("application/pdf"), b"%PDF"),
+            "application/pdf"
+        );
+        for claimed in ["text/html; charset=utf-8", "nonsense", "text/plain\r\nx: y"] {
+            assert_eq!(
+                attachment_content_type(Some(claimed), b"hi"),
+                "application/octet-stream",
+                "{}",
+                claimed
+            );
+        }
+        assert_eq!(
+            attachment_content_type(None, b"hi"),
+            "application/octet-stream"
+        );
+
+        assert!(is_inline_type("image/png"));
+        assert!(!is_inline_type("image/svg+xml"));
+        assert!(!is_inline_type("text/html"));
+    }
+
+    #[tokio::test]
+    async fn local_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalBlobStore::new(dir.path());
+
+        let size = store
+            .put(
+                "attachments/abc",
+                chunks(&[b"hello ", b"world"]),
+                "text/plain",
+            )
+            .await
+            .unwrap();
+        assert_eq!(size, 11);
+        assert_eq!(store.get("attachments/abc").await.unwrap(), b"hello world");
+
+        store.delete("attachments/abc").await.unwrap();
+        assert!(matches!(
+            store.get("attachments/abc").await,
+            Err(BlobError::NotFound)
+        ));
+        // deleting twice is fine
+        store.delete("attachments/abc").await.unwrap();
+
+        // nothing is left behind by a failed upload
+        let result = store.put("attachments/cut", failing(), "text/plain").await;
+        assert!(matches!(result, Err(BlobError::TooLarge)));
+        assert!(matches!(
+            store.get("attachments/cut").await,
+            Err(BlobError::NotFound)
+        ));
+        let mut files = fs::read_dir(dir.path().join("attachments")).await.unwrap();
+        assert!(files.next_entry().await.unwrap().is_none());
+
+        for key in ["../escape", "/abs", "a//b", "a/./b", ""] {
+            assert!(
+                matches!(store.get(key).await, Err(BlobError::InvalidKey(_))),
+                "{}",
+                key
+            );
+        }
+    }
+
+    /// Objects of the stub S3 server by path, and every request it got.
+    #[derive(Default)]
+    struct Bucket {
+        objects: HashMap<String, Vec<u8>>,
+        requests: Vec<(String, String, HeaderMap)>,
+    }
+
+    /// Serves a bucket that checks request signatures made with `secret` and answers
+    /// 403 to requests that don't match.
+    async fn serve_s3(secret: &'static str) -> (SocketAddr, Arc<Mutex<Bucket>>) {
+        let bucket = Arc::new(Mutex::new(Bucket::default()));
+        let app = Router::new()
+            .fallback(
+                move |State(bucket): State<Arc<Mutex<Bucket>>>, request: Request| async move {
+                    let (parts, body) = request.into_parts();
+                    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+                    let method = parts.method.to_string();
+                    let path = parts.uri.path().to_owned();
+                    let signed = signature_matches(secret, &method, &path, &parts.headers, &body);
+
+                    let mut bucket = bucket.lock().unwrap();
+                    bucket
+                        .requests
+                        .push((method.clone(), path.clone(), parts.headers));
+                    if !signed {
+                        return (StatusCode::FORBIDDEN, Vec::new());
+                    }
+                    match method.as_str() {
+                        "PUT" => {
+                            bucket.objects.insert(path, body.to_vec());
+                            (StatusCode::OK, Vec::new())
+                        }
+                        "GET" => match bucket.objects.get(&path) {
+                            Some(data) => (StatusCode::OK, data.clone()),
+                            None => (StatusCode::NOT_FOUND, Vec::new()),
+                        },
+                        "DELETE" => {
+                            bucket.objects.remove(&path);
+                            (StatusCode::NO_CONTENT, Vec::new())
+                        }
+                        _ => (StatusCode::METHOD_NOT_ALLOWED, Vec::new()),
+                    }
+                },
+            )
+            .with_state(bucket.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (addr, bucket)
+    }
+
+    /// Checks an AWS signature version 4 the way S3 does, from the request as received.
+    fn signature_matches(
+        secret: &str,
+        method: &str,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> bool {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let payload_hash = header("x-amz-content-sha256");
+        let timestamp = header("x-amz-date");
+        if payload_hash != hex::encode(Sha256::digest(body)) || timestamp.len() < 8 {
+            return false;
+        }
+        let Some((credential, signature)) = header("authorization")
+            .strip_prefix("AWS4-HMAC-SHA256 Credential=")
+            .and_then(|auth| {
+                auth.split_once(", SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature=")
+            })
+        else {
+            return false;
+        };
+        let Some((_, scope)) = credential.split_once('/') else {
+            return false;
+        };
+        let parts: Vec<&str> = scope.split('/').collect();
+        if parts.len() != 4 || parts[0] != &timestamp[..8] {
+            return false;
+        }
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, header("host"), payload_hash, timestamp, payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = format!("AWS4{}", secret).into_bytes();
+        for part in &parts {
+            let mut mac = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+            mac.update(part.as_bytes());
+            key = mac.finalize().into_bytes().to_vec();
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+        mac.update(string_to_sign.as_bytes());
+        hex::encode(mac.finalize().into_bytes()) == signature
+    }
+
+    #[tokio::test]
+    async fn s3_round_trip() {
+        let (addr, bucket) = serve_s3("secret").await;
+        let endpoint = format!("http://{}/storage/", addr);
+        let store = S3BlobStore::new(&endpoint, "katbin", "eu-west-1", "AKID", "secret").unwrap();
+
+        let size = store
+            .put(
+                "attachments/abc",
+                chunks(&[b"hello ", b"world"]),
+                "text/plain",
+            )
+            .await
+            .unwrap();
+        assert_eq!(size, 11);
+        assert_eq!(store.get("attachments/abc").await.unwrap(), b"hello world");
+        store.delete("attachments/abc").await.unwrap();
+        assert!(matches!(
+            store.get("attachments/abc").await,
+            Err(BlobError::NotFound)
+        ));
+
+        {
+            let bucket = bucket.lock().unwrap();
+            let (method, path, headers) = &bucket.requests[0];
+            assert_eq!(method, "PUT");
+            // path-style below the endpoint's own path
+            assert_eq!(path, "/storage/katbin/attachments/abc");
+            assert_eq!(headers["content-type"], "text/plain");
+            assert_eq!(headers["content-length"], "11");
+            let auth = headers["authorization"].to_str().unwrap();
+            assert!(
+                auth.starts_with("AWS4-HMAC-SHA256 Credential=AKID/"),
+                "{}",
+                auth
+            );
+            assert!(auth.contains("/eu-west-1/s3/aws4_request, "), "{}", auth);
+            assert_eq!(bucket.requests.len(), 4);
+        }
+
+        // a failed upload never reaches the bucket
+        let result = store.put("attachments/cut", failing(), "text/plain").await;
+        assert!(matches!(result, Err(BlobError::TooLarge)));
+        assert_eq!(bucket.lock().unwrap().requests.len(), 4);
+
+        // requests signed with the wrong secret are refused
+        let store = S3BlobStore::new(&endpoint, "katbin", "eu-west-1", "AKID", "wrong").unwrap();
+        let result = store
+            .put("attachments/abc", chunks(&[b"x"]), "text/plain")
+            .await;
+        assert!(matches!(
+            result,
+            Err(BlobError::Status(StatusCode::FORBIDDEN))
+        ));
+        assert!(bucket.lock().unwrap().objects.is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::OnceLock;
+
+use entity::paste_contents::{self, Codec};
+use sea_orm::{ActiveValue, DbErr};
+
+static COMPRESSION_THRESHOLD: OnceLock<usize> = OnceLock::new();
+
+/// Content of at least this many bytes is compressed unless configured otherwise.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 32 * 1024;
+/// zstd's own default, a good trade-off for text.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Sets the size in bytes from which paste content is stored compressed, `0` turns
+/// compression off. Can only be set once.
+pub fn set_compression_threshold(bytes: usize) {
+    let threshold = if bytes == 0 { usize::MAX } else { bytes };
+    if COMPRESSION_THRESHOLD.set(threshold).is_err() {
+        tracing::warn!("compression threshold was already set");
+    }
+}
+
+pub(crate) fn compression_threshold() -> usize {
+    *COMPRESSION_THRESHOLD.get_or_init(|| DEFAULT_COMPRESSION_THRESHOLD)
+}
+
+/// Sets the content of a stored body, compressing it when it's large enough.
+pub(crate) fn set_content(row: &mut paste_contents::ActiveModel, content: &str) {
+    let compressed = if content.len() >= compression_threshold() {
+        zstd::encode_all(content.as_bytes(), ZSTD_LEVEL)
+            .ok()
+            .filter(|compressed| compressed.len() < content.len())
+    } else {
+        None
+    };
+
+    match compressed {
+        Some(compressed) => {
+            row.content = ActiveValue::Set(String::new());
+            row.content_codec = ActiveValue::Set(Some(Codec::Zstd));
+            row.content_compressed = ActiveValue::Set(Some(compressed));
+        }
+        None => {
+            row.content = ActiveValue::Set(content.to_owned());
+            row.content_codec = ActiveValue::Set(None);
+            row.content_compressed = ActiveValue::Set(None);
+        }
+    }
+}
+
+/// The text of a stored body. Compressed bodies can also be sent as they are to clients
+/// that accept the encoding.
+pub fn decompress_content(row: &paste_contents::Model) -> Result<String, DbErr> {
+    let Some(codec) = row.content_codec else {
+        return Ok(row.content.clone());
+    };
+    let compressed = row.content_compressed.as_deref().unwrap_or_default();
+    let content = match codec {
+        Codec::Zstd => zstd::decode_all(compressed)
+            .map_err(|e| DbErr::Type(format!("invalid zstd content: {}", e)))?,
+    };
+    String::from_utf8(content)
+        .map_err(|e| DbErr::Type(format!("compressed content is not UTF-8: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::TryIntoModel;
+
+    use super::*;
+
+    fn stored(content: &str) -> paste_contents::Model {
+        let mut row = paste_contents::ActiveModel {
+            hash: ActiveValue::Set(String::from("hash")),
+            ref_count: ActiveValue::Set(1),
+            ..Default::default()
+        };
+        set_content(&mut row, content);
+        row.try_into_model().unwrap()
+    }
+
+    #[test]
+    fn large_content_is_compressed() {
+        let content = "fn main() {\n    println!(\"héllo wörld 👋\");\n}\n"
+            .repeat(DEFAULT_COMPRESSION_THRESHOLD / 40 + 1);
+        assert!(content.len() >= compression_threshold());
+
+        let row = stored(&content);
+        assert_eq!(row.content_codec, Some(Codec::Zstd));
+        assert_eq!(row.content, "");
+        let compressed = row.content_compressed.as_deref().unwrap();
+        assert!(compressed.len() < content.len());
+        assert_eq!(zstd::decode_all(compressed).unwrap(), content.as_bytes());
+        assert_eq!(decompress_content(&row).unwrap(), content);
+    }
+
+    #[test]
+    fn small_content_is_kept_as_is() {
+        let content = "a".repeat(compression_threshold() - 1);
+        let row = stored(&content);
+        assert_eq!(row.content_codec, None);
+        assert_eq!(row.content_compressed, None);
+        assert_eq!(row.content, content);
+        assert_eq!(decompress_content(&row).unwrap(), content);
+    }
+
+    #[test]
+    fn broken_content_is_an_error() {
+        let mut row = stored(&"a".repeat(compression_threshold()));
+        row.content_compressed = Some(b"not zstd".to_vec());
+        assert!(decompress_content(&row).is_err());
+    }
+}
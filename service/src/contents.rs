@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use entity::{paste_contents, paste_files, pastes};
+use sea_orm::sea_query::{Expr, IntoCondition, OnConflict};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QuerySelect,
+};
+use sha2::{Digest, Sha256};
+
+use crate::compression;
+
+/// Adds a reference to `content`, storing it first if no other paste or file uses it yet.
+/// Returns the key pastes refer to it by.
+pub(crate) async fn acquire<C: ConnectionTrait>(db: &C, content: &str) -> Result<String, DbErr> {
+    let hash = hex::encode(Sha256::digest(content.as_bytes()));
+
+    // most duplicates are found here, without compressing the content again
+    let counted = paste_contents::Entity::update_many()
+        .col_expr(
+            paste_contents::Column::RefCount,
+            Expr::col(paste_contents::Column::RefCount).add(1),
+        )
+        .filter(paste_contents::Column::Hash.eq(&hash))
+        .exec(db)
+        .await?;
+    if counted.rows_affected > 0 {
+        return Ok(hash);
+    }
+
+    let mut row = paste_contents::ActiveModel {
+        hash: ActiveValue::Set(hash.clone()),
+        ref_count: ActiveValue::Set(1),
+        ..Default::default()
+    };
+    compression::set_content(&mut row, content);
+    // someone else may have stored the same content in the meantime
+    paste_contents::Entity::insert(row)
+        .on_conflict(
+            OnConflict::column(paste_contents::Column::Hash)
+                .value(
+                    paste_contents::Column::RefCount,
+                    Expr::col((paste_contents::Entity, paste_contents::Column::RefCount)).add(1),
+                )
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(hash)
+}
+
+/// Drops a reference for every hash, removing contents nothing uses anymore. The pastes
+/// and files have to be deleted or pointed elsewhere first.
+pub(crate) async fn release<C: ConnectionTrait>(
+    db: &C,
+    hashes: impl IntoIterator<Item = String>,
+) -> Result<(), DbErr> {
+    let mut counts: HashMap<String, i32> = HashMap::new();
+    for hash in hashes {
+        *counts.entry(hash).or_default() += 1;
+    }
+
+    for (hash, count) in counts {
+        paste_contents::Entity::update_many()
+            .col_expr(
+                paste_contents::Column::RefCount,
+                Expr::col(paste_contents::Column::RefCount).sub(count),
+            )
+            .filter(paste_contents::Column::Hash.eq(&hash))
+            .exec(db)
+            .await?;
+        paste_contents::Entity::delete_many()
+            .filter(paste_contents::Column::Hash.eq(&hash))
+            .filter(paste_contents::Column::RefCount.lte(0))
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Fills in `content` of pastes loaded from the database.
+pub(crate) async fn load<'a, C: ConnectionTrait>(
+    db: &C,
+    pastes: impl IntoIterator<Item = &'a mut pastes::Model>,
+) -> Result<(), DbErr> {
+    let pastes: Vec<_> = pastes
+        .into_iter()
+        .filter_map(|paste| Some((paste.content_hash.clone()?, &mut paste.content)))
+        .collect();
+    fill(db, pastes).await
+}
+
+/// Fills in `content` of paste files loaded from the database.
+pub(crate) async fn load_files<'a, C: ConnectionTrait>(
+    db: &C,
+    files: impl IntoIterator<Item = &'a mut paste_files::Model>,
+) -> Result<(), DbErr> {
+    let files: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| Some((file.content_hash.clone()?, &mut file.content)))
+        .collect();
+    fill(db, files).await
+}
+
+/// Sets every content to the one stored under its hash.
+async fn fill<C: ConnectionTrait>(
+    db: &C,
+    contents: Vec<(String, &mut String)>,
+) -> Result<(), DbErr> {
+    if contents.is_empty() {
+        return Ok(());
+    }
+
+    let hashes: Vec<&String> = contents.iter().map(|(hash, _)| hash).collect();
+    let rows: HashMap<String, paste_contents::Model> = paste_contents::Entity::find()
+        .filter(paste_contents::Column::Hash.is_in(hashes))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| (row.hash.clone(), row))
+        .collect();
+
+    for (hash, content) in contents {
+        let row = rows.get(&hash).ok_or(DbErr::RecordNotFound(String::from(
+            "paste content not found",
+        )))?;
+        *content = compression::decompress_content(row)?;
+    }
+    Ok(())
+}
+
+/// The content hashes of the files matching `condition`, for releasing them once the
+/// files are deleted.
+pub(crate) async fn file_hashes<C: ConnectionTrait>(
+    db: &C,
+    condition: impl IntoCondition,
+) -> Result<Vec<String>, DbErr> {
+    paste_files::Entity::find()
+        .select_only()
+        .column(paste_files::Column::ContentHash)
+        .filter(condition)
+        .filter(paste_files::Column::ContentHash.is_not_null())
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+/// Sets the content of a paste. Short links keep their url in the paste itself,
+/// anything else is stored in `paste_contents` and referenced by its hash.
+pub(crate) async fn set_content<C: ConnectionTrait>(
+    db: &C,
+    paste: &mut pastes::ActiveModel,
+    content: &str,
+    is_url: bool,
+) -> Result<(), DbErr> {
+    if is_url {
+        paste.content = ActiveValue::Set(content.to_owned());
+        paste.content_hash = ActiveValue::Set(None);
+    } else {
+        paste.content = ActiveValue::Set(String::new());
+        paste.content_hash = ActiveValue::Set(Some(acquire(db, content).await?));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use entity::moderation_actions::Action;
+    use entity::schema::{ModerationPost, PastePost};
+    use entity::users::{self, Role};
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ActiveModelTrait, Database, DatabaseConnection};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::Mutation;
+
+    async fn database() -> (TempDir, DatabaseConnection) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("katbin.db").display()
+        );
+        let migrations = migration::sea_orm::Database::connect(&url).await.unwrap();
+        Migrator::up(&migrations, None).await.unwrap();
+        (dir, Database::connect(&url).await.unwrap())
+    }
+
+    async fn moderator(db: &DatabaseConnection) -> users::Model {
+        users::ActiveModel {
+            email: ActiveValue::Set(String::from("admin@example.com")),
+            hashed_password: ActiveValue::Set(String::new()),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            updated_at: ActiveValue::Set(Utc::now().naive_utc()),
+            role: ActiveValue::Set(Role::Admin),
+            password_reset_required: ActiveValue::Set(false),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+    }
+
+    async fn paste(db: &DatabaseConnection, content: &str) -> pastes::Model {
+        let form = PastePost {
+            content: content.to_owned(),
+            ..Default::default()
+        };
+        Mutation::create_paste(db, &form, None, "").await.unwrap()
+    }
+
+    async fn delete(db: &DatabaseConnection, moderator: &users::Model, paste: &pastes::Model) {
+        let form = ModerationPost {
+            action: Action::Delete,
+            note: None,
+        };
+        Mutation::takedown_paste(db, &paste.domain, &paste.id, moderator, &form)
+            .await
+            .unwrap();
+    }
+
+    /// How many pastes and files use `content`, none when it isn't stored.
+    async fn references(db: &DatabaseConnection, content: &str) -> Option<i32> {
+        let hash = hex::encode(Sha256::digest(content.as_bytes()));
+        paste_contents::Entity::find_by_id(hash)
+            .one(db)
+            .await
+            .unwrap()
+            .map(|row| row.ref_count)
+    }
+
+    #[tokio::test]
+    async fn identical_pastes_share_their_content() {
+        let (_dir, db) = database().await;
+        let moderator = moderator(&db).await;
+
+        let first = paste(&db, "the same text").await;
+        let second = paste(&db, "the same text").await;
+        assert_eq!(first.content_hash, second.content_hash);
+        assert_eq!(
+            paste_contents::Entity::find().all(&db).await.unwrap().len(),
+            1
+        );
+        assert_eq!(references(&db, "the same text").await, Some(2));
+
+        delete(&db, &moderator, &first).await;
+        assert_eq!(references(&db, "the same text").await, Some(1));
+
+        delete(&db, &moderator, &second).await;
+        assert_eq!(references(&db, "the same text").await, None);
+    }
+
+    #[tokio::test]
+    async fn edits_release_the_old_content() {
+        let (_dir, db) = database().await;
+        let kept = paste(&db, "before").await;
+        let edited = paste(&db, "before").await;
+        assert_eq!(references(&db, "before").await, Some(2));
+
+        let form = PastePost {
+            content: String::from("after"),
+            ..Default::default()
+        };
+        Mutation::update_paste_content(&db, &form, None, &edited.domain, &edited.id)
+            .await
+            .unwrap();
+        assert_eq!(references(&db, "before").await, Some(1));
+        assert_eq!(references(&db, "after").await, Some(1));
+
+        // an edit to the same content keeps it
+        let form = PastePost {
+            content: String::from("before"),
+            ..Default::default()
+        };
+        Mutation::update_paste_content(&db, &form, None, &kept.domain, &kept.id)
+            .await
+            .unwrap();
+        assert_eq!(references(&db, "before").await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn released_hashes_count_every_reference() {
+        let (_dir, db) = database().await;
+        let hash = acquire(&db, "shared").await.unwrap();
+        assert_eq!(acquire(&db, "shared").await.unwrap(), hash);
+        assert_eq!(acquire(&db, "shared").await.unwrap(), hash);
+        assert_eq!(references(&db, "shared").await, Some(3));
+
+        release(&db, [hash.clone(), hash.clone()]).await.unwrap();
+        assert_eq!(references(&db, "shared").await, Some(1));
+
+        release(&db, [hash]).await.unwrap();
+        assert_eq!(references(&db, "shared").await, None);
+    }
+}
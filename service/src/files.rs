@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use entity::{paste_files, pastes, schema};
+use serde::Serialize;
+
+/// Most files a paste can have.
+pub const MAX_PASTE_FILES: usize = 20;
+const MAX_LANGUAGE_LENGTH: usize = 32;
+
+/// Languages guessed from the file extension when none is given.
+const EXTENSION_LANGUAGES: [(&str, &str); 31] = [
+    ("c", "c"),
+    ("cpp", "cpp"),
+    ("cs", "csharp"),
+    ("css", "css"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("go", "go"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("html", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("kt", "kotlin"),
+    ("lua", "lua"),
+    ("md", "markdown"),
+    ("php", "php"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("sql", "sql"),
+    ("swift", "swift"),
+    ("tera", "html"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("txt", "text"),
+    ("xml", "xml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+];
+
+/// One file of a paste. Most pastes consist of a single unnamed file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PasteFile {
+    /// Only the first file can be unnamed, and only when it's the only one.
+    pub filename: Option<String>,
+    pub language: Option<String>,
+    pub content: String,
+}
+
+impl PasteFile {
+    /// Name used for downloads, an unnamed file is named after the paste.
+    pub fn download_name(&self, paste_id: &str) -> String {
+        self.filename
+            .clone()
+            .unwrap_or_else(|| format!("{}.txt", paste_id))
+    }
+}
+
+/// The files of a submitted form as they were entered, e.g. to show them again after
+/// an error. Blank extra files are left out.
+pub fn form_files(form: &schema::PastePost) -> Vec<PasteFile> {
+    let mut files = vec![PasteFile {
+        filename: non_empty(form.filename.as_deref()),
+        language: non_empty(form.language.as_deref()),
+        content: form.content.clone(),
+    }];
+
+    let count = form.file_name.len().max(form.file_content.len());
+    for i in 0..count {
+        let filename = non_empty(form.file_name.get(i).map(String::as_str));
+        let content = form.file_content.get(i).cloned().unwrap_or_default();
+        if filename.is_none() && content.trim().is_empty() {
+            continue;
+        }
+        files.push(PasteFile {
+            filename,
+            language: non_empty(form.file_language.get(i).map(String::as_str)),
+            content,
+        });
+    }
+    files
+}
+
+/// All files of a stored paste, the first one first. `extra` are the paste's rows in
+/// `paste_files`, the content of both has to be loaded.
+pub(crate) fn stored_files(
+    paste: &pastes::Model,
+    mut extra: Vec<paste_files::Model>,
+) -> Vec<PasteFile> {
+    extra.sort_by_key(|f| f.position);
+    let first = PasteFile {
+        filename: paste.filename.clone(),
+        language: paste.language.clone(),
+        content: paste.content.clone(),
+    };
+    std::iter::once(first)
+        .chain(extra.into_iter().map(|f| PasteFile {
+            filename: Some(f.filename),
+            language: f.language,
+            content: f.content,
+        }))
+        .collect()
+}
+
+/// Checks the names of submitted files and fills in languages from the file extensions.
+///
+/// Names end up in URLs (`/raw/:id/:filename`) and in zip archives, so they can't
+/// contain slashes and have to be unique within the paste.
+pub(crate) fn check_files(files: Vec<PasteFile>) -> Result<Vec<PasteFile>, String> {
+    if files.len() > MAX_PASTE_FILES {
+        return Err(format!(
+            "A paste can have at most {} files.",
+            MAX_PASTE_FILES
+        ));
+    }
+
+    let several = files.len() > 1;
+    let mut names = HashSet::new();
+    files
+        .into_iter()
+        .map(|file| {
+            let filename = match file.filename {
+                Some(name) => Some(check_filename(&name)?),
+                None if several => {
+                    return Err(String::from(
+                        "Every file needs a name when a paste has several files.",
+                    ))
+                }
+                None => None,
+            };
+            if let Some(name) = &filename {
+                if !names.insert(name.clone()) {
+                    return Err(format!("There is more than one file called {}.", name));
+                }
+            }
+
+            let language = match file.language {
+                Some(language) => Some(check_language(&language)?),
+                None => filename.as_deref().and_then(guess_language),
+            };
+            Ok(PasteFile {
+                filename,
+                language,
+                content: file.content,
+            })
+        })
+        .collect()
+}
+
+fn check_filename(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name.chars().count() <= 255
+        && name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control());
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err(format!(
+            "{:?} can't be used as a file name, names can't contain slashes and are at most 255 characters long.",
+            name
+        ))
+    }
+}
+
+fn check_language(language: &str) -> Result<String, String> {
+    let language = language.trim().to_lowercase();
+    let valid = language.len() <= MAX_LANGUAGE_LENGTH
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+#._-".contains(c));
+    if valid {
+        Ok(language)
+    } else {
+        Err(format!("{} is not a valid language name.", language))
+    }
+}
+
+fn guess_language(filename: &str) -> Option<String> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    let extension = extension.to_lowercase();
+    EXTENSION_LANGUAGES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, language)| (*language).to_owned())
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_owned)
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use entity::filter_rules::{self, Kind};
+use regex::{Regex, RegexBuilder};
+use url::Url;
+
+use crate::utils::host_matches;
+
+/// Links in free text, good enough to count them and pull out their hosts.
+fn link_regex() -> &'static Regex {
+    static LINKS: OnceLock<Regex> = OnceLock::new();
+    LINKS
+        .get_or_init(|| Regex::new(r#"(?i)\bhttps?://[^\s<>"'`]+"#).expect("link pattern is valid"))
+}
+
+fn link_hosts(content: &str) -> Vec<String> {
+    link_regex()
+        .find_iter(content)
+        .filter_map(|m| Url::parse(m.as_str()).ok())
+        .filter_map(|url| url.host_str().map(str::to_lowercase))
+        .collect()
+}
+
+/// Checks that a rule's pattern makes sense for its kind before it is stored.
+pub fn validate_filter_rule(kind: Kind, pattern: &str) -> Result<(), String> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(String::from("The pattern can't be empty."));
+    }
+
+    match kind {
+        Kind::Regex => compile(pattern)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid regular expression: {}", e)),
+        Kind::MaxLinks => pattern
+            .parse::<usize>()
+            .map(|_| ())
+            .map_err(|_| String::from("The maximum number of links must be a number.")),
+        Kind::Domain | Kind::Keyword => Ok(()),
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).size_limit(1 << 20).build()
+}
+
+/// Compiled regexes of regex rules by rule id, along with the pattern they were compiled
+/// from so a rule that changed is compiled again. Invalid patterns are kept as `None`.
+type CompiledRules = HashMap<i64, (String, Option<Regex>)>;
+
+fn compiled_rules() -> &'static Mutex<CompiledRules> {
+    static COMPILED: OnceLock<Mutex<CompiledRules>> = OnceLock::new();
+    COMPILED.get_or_init(Default::default)
+}
+
+/// The regex of a regex rule, compiled the first time the rule is evaluated.
+fn rule_regex(rule: &filter_rules::Model) -> Option<Regex> {
+    let mut compiled = compiled_rules().lock().unwrap();
+    if let Some((pattern, regex)) = compiled.get(&rule.id) {
+        if *pattern == rule.pattern {
+            return regex.clone();
+        }
+    }
+
+    let regex = compile(rule.pattern.trim())
+        .map_err(|e| tracing::warn!("Skipping filter rule {} with invalid regex: {}", rule.id, e))
+        .ok();
+    compiled.insert(rule.id, (rule.pattern.clone(), regex.clone()));
+    regex
+}
+
+/// Drops the compiled regex of a deleted rule.
+pub(crate) fn forget_filter_rule(rule_id: i64) {
+    compiled_rules().lock().unwrap().remove(&rule_id);
+}
+
+fn matches(rule: &filter_rules::Model, content: &str, hosts: &[String]) -> bool {
+    let pattern = rule.pattern.trim();
+    match rule.kind {
+        Kind::Regex => rule_regex(rule).is_some_and(|regex| regex.is_match(content)),
+        Kind::Domain => hosts.iter().any(|host| host_matches(host, pattern)),
+        Kind::MaxLinks => pattern.parse::<usize>().is_ok_and(|max| hosts.len() > max),
+        Kind::Keyword => content.to_lowercase().contains(&pattern.to_lowercase()),
+    }
+}
+
+/// Returns the most severe rule matching `content`, if any.
+pub fn evaluate_filter_rules<'a>(
+    rules: &'a [filter_rules::Model],
+    content: &str,
+) -> Option<&'a filter_rules::Model> {
+    let hosts = link_hosts(content);
+    rules
+        .iter()
+        .filter(|rule| rule.enabled && matches(rule, content, &hosts))
+        .max_by_key(|rule| rule.action)
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::filter_rules::Action;
+
+    use super::*;
+
+    fn rule(id: i64, kind: Kind, pattern: &str) -> filter_rules::Model {
+        filter_rules::Model {
+            id,
+            kind,
+            pattern: pattern.to_owned(),
+            action: Action::Reject,
+            enabled: true,
+            inserted_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn regex_rules_follow_pattern_changes() {
+        let mut regex = rule(1_000_001, Kind::Regex, r"casino\d+");
+        assert!(
+            evaluate_filter_rules(std::slice::from_ref(&regex), "visit casino24 now").is_some()
+        );
+        assert!(evaluate_filter_rules(std::slice::from_ref(&regex), "visit the casino").is_none());
+
+        // same rule id with another pattern, as after a change elsewhere
+        regex.pattern = String::from("the casino");
+        assert!(evaluate_filter_rules(std::slice::from_ref(&regex), "visit the casino").is_some());
+
+        forget_filter_rule(regex.id);
+        assert!(!compiled_rules().lock().unwrap().contains_key(&regex.id));
+    }
+
+    #[test]
+    fn invalid_regex_rules_never_match() {
+        let invalid = rule(1_000_002, Kind::Regex, "(unclosed");
+        assert!(evaluate_filter_rules(std::slice::from_ref(&invalid), "(unclosed").is_none());
+        assert!(evaluate_filter_rules(&[invalid], "(unclosed").is_none());
+    }
+
+    #[test]
+    fn other_rule_kinds() {
+        let content = "see https://spam.example.com/a and https://ok.org/b";
+        let domain = rule(1, Kind::Domain, "example.com");
+        let max_links = rule(2, Kind::MaxLinks, "1");
+        let keyword = rule(3, Kind::Keyword, "SEE");
+        assert!(evaluate_filter_rules(&[domain], content).is_some());
+        assert!(evaluate_filter_rules(&[max_links], content).is_some());
+        assert!(evaluate_filter_rules(&[keyword], content).is_some());
+        assert!(evaluate_filter_rules(&[rule(4, Kind::Domain, "other.net")], content).is_none());
+    }
+}
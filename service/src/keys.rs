@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use rand::{prelude::SliceRandom, Rng};
+
+static KEY_CONFIG: OnceLock<KeyConfig> = OnceLock::new();
+static RESERVED_IDS: OnceLock<HashSet<String>> = OnceLock::new();
+/// Extra length added to generated keys once collisions show the keyspace is filling up.
+/// Only kept in memory, after a restart it grows back on the first collisions.
+static KEY_GROWTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Longest a generated key can get, the id column holds 255 characters.
+const MAX_KEY_LENGTH: usize = 64;
+/// Most words in a key of the `Words` style.
+const MAX_KEY_WORDS: usize = 8;
+/// Shortest custom url a user can pick.
+const MIN_CUSTOM_URL_LENGTH: usize = 3;
+
+const VOWELS: [char; 5] = ['a', 'e', 'i', 'o', 'u'];
+const CONSONANTS: [char; 21] = [
+    'b', 'c', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm', 'n', 'p', 'q', 'r', 's', 't', 'v', 'w', 'x',
+    'y', 'z',
+];
+const BASE62: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const ADJECTIVES: [&str; 48] = [
+    "happy", "brave", "calm", "eager", "fancy", "gentle", "jolly", "kind", "lucky", "merry",
+    "nice", "proud", "quiet", "silly", "witty", "zany", "bold", "bright", "clever", "cosy",
+    "daring", "fast", "fuzzy", "giant", "grand", "humble", "icy", "lazy", "little", "loud",
+    "mighty", "noble", "odd", "polite", "quick", "rapid", "shy", "sleepy", "smart", "snowy",
+    "sunny", "swift", "tiny", "tidy", "vivid", "warm", "wild", "wise",
+];
+const COLOURS: [&str; 24] = [
+    "amber", "azure", "beige", "black", "blue", "bronze", "brown", "coral", "cyan", "gold",
+    "green", "grey", "indigo", "ivory", "lime", "navy", "olive", "orange", "pink", "plum", "red",
+    "silver", "teal", "white",
+];
+const ANIMALS: [&str; 48] = [
+    "ant", "bat", "bear", "bee", "bison", "boar", "cat", "crab", "crow", "deer", "dog", "dove",
+    "duck", "eagle", "eel", "elk", "emu", "falcon", "fox", "frog", "goat", "hare", "hawk", "horse",
+    "koala", "lemur", "lion", "llama", "lynx", "mole", "moose", "mouse", "newt", "otter", "owl",
+    "panda", "pig", "puma", "quail", "seal", "shark", "sheep", "sloth", "snail", "swan", "tiger",
+    "wolf", "yak",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyStyle {
+    /// Alternating consonants and vowels, e.g. `kolibemuta`.
+    Pronounceable,
+    /// Random characters from the alphabet, base62 unless configured otherwise.
+    Random,
+    /// Dash separated words, e.g. `happy-blue-cat`. The length is the number of words.
+    Words,
+}
+
+impl FromStr for KeyStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pronounceable" => Ok(KeyStyle::Pronounceable),
+            "base62" | "random" => Ok(KeyStyle::Random),
+            "words" => Ok(KeyStyle::Words),
+            other => Err(format!(
+                "unknown key style {}, expected pronounceable, base62 or words",
+                other
+            )),
+        }
+    }
+}
+
+/// How ids of new pastes are generated.
+#[derive(Clone, Debug)]
+pub struct KeyConfig {
+    pub style: KeyStyle,
+    /// Characters (or words) in a key before it starts growing.
+    pub length: usize,
+    /// Characters used by the `Random` style.
+    pub alphabet: Vec<char>,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            style: KeyStyle::Pronounceable,
+            length: 10,
+            alphabet: BASE62.chars().collect(),
+        }
+    }
+}
+
+impl KeyConfig {
+    /// Checks the configuration, the alphabet can only contain characters that are
+    /// safe in a URL path.
+    pub fn new(style: KeyStyle, length: usize, alphabet: Option<&str>) -> Result<Self, String> {
+        let max_length = match style {
+            KeyStyle::Words => MAX_KEY_WORDS,
+            _ => MAX_KEY_LENGTH,
+        };
+        if length == 0 || length > max_length {
+            return Err(format!("key length has to be between 1 and {}", max_length));
+        }
+
+        let alphabet = match alphabet {
+            Some(alphabet) => {
+                let mut chars: Vec<char> = alphabet.chars().collect();
+                chars.sort_unstable();
+                chars.dedup();
+                if let Some(c) = chars
+                    .iter()
+                    .find(|c| !(c.is_ascii_alphanumeric() || **c == '-' || **c == '_'))
+                {
+                    return Err(format!("{:?} can't be used in keys", c));
+                }
+                if chars.len() < 2 {
+                    return Err(String::from("the key alphabet needs at least 2 characters"));
+                }
+                chars
+            }
+            None => BASE62.chars().collect(),
+        };
+
+        Ok(KeyConfig {
+            style,
+            length,
+            alphabet,
+        })
+    }
+}
+
+/// Sets how paste ids are generated. Can only be set once, the default matches
+/// the ids katbin has always generated.
+pub fn set_key_config(config: KeyConfig) {
+    if KEY_CONFIG.set(config).is_err() {
+        tracing::warn!("key config was already set");
+    }
+}
+
+fn key_config() -> &'static KeyConfig {
+    KEY_CONFIG.get_or_init(KeyConfig::default)
+}
+
+/// Sets the ids that can't be used as custom urls because they would shadow a route.
+/// Can only be set once.
+pub fn set_reserved_ids(ids: &[&str]) {
+    let ids = ids.iter().map(|id| id.to_lowercase()).collect();
+    if RESERVED_IDS.set(ids).is_err() {
+        tracing::warn!("reserved ids were already set");
+    }
+}
+
+/// Checks a custom url and returns the id it's stored under.
+///
+/// Custom urls are folded to lowercase so `Docs` and `docs` can't be two different
+/// links, and are limited to ASCII letters, digits, `-` and `_`. Anything else would
+/// either break routing (`/`, `.` starts a file extension) or allow look-alike ids.
+pub fn normalize_custom_url(custom_url: &str) -> Result<String, String> {
+    let id = custom_url.trim().to_lowercase();
+    if id.len() < MIN_CUSTOM_URL_LENGTH || id.len() > MAX_KEY_LENGTH {
+        return Err(format!(
+            "Custom URLs have to be between {} and {} characters long.",
+            MIN_CUSTOM_URL_LENGTH, MAX_KEY_LENGTH
+        ));
+    }
+    if !id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(String::from(
+            "Custom URLs can only contain letters, digits, - and _.",
+        ));
+    }
+    if !id.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(String::from(
+            "Custom URLs have to start with a letter or a digit.",
+        ));
+    }
+    if RESERVED_IDS
+        .get()
+        .is_some_and(|reserved| reserved.contains(&id))
+    {
+        return Err(format!("{} is reserved and can't be used.", id));
+    }
+
+    Ok(id)
+}
+
+/// Generates a paste id. `attempt` counts the collisions seen so far for this paste,
+/// every retry gets a longer key.
+pub(crate) fn generate_key(attempt: usize) -> String {
+    let config = key_config();
+    let length = (config.length + KEY_GROWTH.load(Ordering::Relaxed) + attempt).min(MAX_KEY_LENGTH);
+
+    match config.style {
+        KeyStyle::Pronounceable => pronounceable(length),
+        KeyStyle::Random => random(&config.alphabet, length),
+        KeyStyle::Words => words(length.min(MAX_KEY_WORDS)),
+    }
+}
+
+/// Called when a generated key was already taken. Two collisions in a row for the same
+/// paste mean keys of the current length are getting crowded, so all later keys grow.
+pub(crate) fn key_collision(attempt: usize) {
+    if attempt >= 1 {
+        let growth = KEY_GROWTH.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::warn!(
+            "paste ids keep colliding, generating keys {} longer",
+            growth
+        );
+    }
+}
+
+fn pronounceable(length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let mut key = String::with_capacity(length);
+    let random: bool = rng.gen();
+
+    for i in 0..length {
+        if i % 2 == (random as usize) {
+            key.push(
+                *CONSONANTS
+                    .choose(&mut rng)
+                    .expect("consonants aren't empty"),
+            );
+        } else {
+            key.push(*VOWELS.choose(&mut rng).expect("vowels aren't empty"));
+        }
+    }
+
+    key
+}
+
+fn random(alphabet: &[char], length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..length)
+        .map(|_| *alphabet.choose(&mut rng).expect("alphabet isn't empty"))
+        .collect()
+}
+
+/// Adjectives, then a colour and an animal, e.g. `happy-blue-cat` for three words.
+fn words(count: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let mut words = Vec::with_capacity(count);
+    for i in (0..count).rev() {
+        let list: &[&str] = match i {
+            0 => &ANIMALS,
+            1 => &COLOURS,
+            _ => &ADJECTIVES,
+        };
+        words.push(*list.choose(&mut rng).expect("word lists aren't empty"));
+    }
+    words.join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_consonant(c: char) -> bool {
+        CONSONANTS.contains(&c)
+    }
+
+    #[test]
+    fn pronounceable_keys_alternate() {
+        for _ in 0..50 {
+            let key = pronounceable(11);
+            assert_eq!(key.len(), 11);
+            let chars: Vec<char> = key.chars().collect();
+            assert!(chars.iter().all(|c| is_consonant(*c) || VOWELS.contains(c)));
+            assert!(chars
+                .windows(2)
+                .all(|pair| is_consonant(pair[0]) != is_consonant(pair[1])));
+        }
+    }
+
+    #[test]
+    fn random_keys_use_the_alphabet() {
+        let alphabet: Vec<char> = "ab-".chars().collect();
+        for _ in 0..50 {
+            let key = random(&alphabet, 20);
+            assert_eq!(key.len(), 20);
+            assert!(key.chars().all(|c| alphabet.contains(&c)));
+        }
+    }
+
+    #[test]
+    fn word_keys_end_in_a_colour_and_an_animal() {
+        for count in 1..=MAX_KEY_WORDS {
+            let key = words(count);
+            let words: Vec<&str> = key.split('-').collect();
+            assert_eq!(words.len(), count);
+            let (animal, rest) = words.split_last().unwrap();
+            assert!(ANIMALS.contains(animal));
+            if let Some((colour, adjectives)) = rest.split_last() {
+                assert!(COLOURS.contains(colour));
+                assert!(adjectives.iter().all(|a| ADJECTIVES.contains(a)));
+            }
+        }
+    }
+
+    #[test]
+    fn key_config_checks_lengths() {
+        assert!(KeyConfig::new(KeyStyle::Pronounceable, 0, None).is_err());
+        assert!(KeyConfig::new(KeyStyle::Pronounceable, MAX_KEY_LENGTH, None).is_ok());
+        assert!(KeyConfig::new(KeyStyle::Random, MAX_KEY_LENGTH + 1, None).is_err());
+        assert!(KeyConfig::new(KeyStyle::Words, MAX_KEY_WORDS, None).is_ok());
+        assert!(KeyConfig::new(KeyStyle::Words, MAX_KEY_WORDS + 1, None).is_err());
+    }
+
+    #[test]
+    fn key_config_checks_alphabets() {
+        let config = KeyConfig::new(KeyStyle::Random, 8, None).unwrap();
+        assert_eq!(config.alphabet.len(), 62);
+
+        let config = KeyConfig::new(KeyStyle::Random, 8, Some("cab_-ac")).unwrap();
+        assert_eq!(config.alphabet, ['-', '_', 'a', 'b', 'c']);
+
+        for alphabet in ["ab/", "ab.", "ab ", "abé", "a", "aaaa", ""] {
+            assert!(
+                KeyConfig::new(KeyStyle::Random, 8, Some(alphabet)).is_err(),
+                "{:?} was accepted",
+                alphabet
+            );
+        }
+    }
+
+    #[test]
+    fn keys_grow_with_collisions() {
+        // the only test touching the growth, others would see it change
+        let length = generate_key(0).len();
+        assert_eq!(
+            length,
+            key_config().length + KEY_GROWTH.load(Ordering::Relaxed)
+        );
+        assert_eq!(generate_key(2).len(), length + 2);
+
+        // a single collision can be bad luck
+        key_collision(0);
+        assert_eq!(generate_key(0).len(), length);
+        key_collision(1);
+        assert_eq!(generate_key(0).len(), length + 1);
+
+        assert_eq!(generate_key(1000).len(), MAX_KEY_LENGTH);
+    }
+
+    #[test]
+    fn custom_urls_are_normalized() {
+        set_reserved_ids(&["users", "static", "v"]);
+        let longest = "a".repeat(MAX_KEY_LENGTH);
+
+        let accepted = [
+            ("abc", "abc"),
+            ("My-Notes", "my-notes"),
+            ("  padded_id  ", "padded_id"),
+            ("2024-report", "2024-report"),
+            (longest.as_str(), longest.as_str()),
+            ("USERS2", "users2"),
+        ];
+        for (input, id) in accepted {
+            assert_eq!(
+                normalize_custom_url(input).as_deref(),
+                Ok(id),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn custom_urls_are_rejected() {
+        set_reserved_ids(&["users", "static", "v"]);
+        let too_long = "a".repeat(MAX_KEY_LENGTH + 1);
+
+        let rejected = [
+            ("ab", "between"),
+            (too_long.as_str(), "between"),
+            ("   ", "between"),
+            ("  ab  ", "between"),
+            ("notes.md", "can only contain"),
+            ("a/b/c", "can only contain"),
+            ("..", "between"),
+            ("...", "can only contain"),
+            ("with space", "can only contain"),
+            // Cyrillic а and е, and fullwidth letters that look like ASCII
+            ("pаypаl", "can only contain"),
+            ("gооgle", "can only contain"),
+            ("ａｂｃ", "can only contain"),
+            ("-abc", "start with"),
+            ("_abc", "start with"),
+            ("users", "reserved"),
+            ("Users", "reserved"),
+            ("STATIC", "reserved"),
+            ("v", "between"),
+        ];
+        for (input, error) in rejected {
+            let result = normalize_custom_url(input);
+            assert!(
+                result.as_ref().is_err_and(|e| e.contains(error)),
+                "{:?} gave {:?}",
+                input,
+                result
+            );
+        }
+    }
+}
@@ -1,8 +1,33 @@
+mod blobs;
+mod compression;
+mod contents;
+mod files;
+mod filters;
+mod keys;
 mod mutation;
 mod query;
+mod search;
+#[cfg(feature = "tantivy")]
+mod search_index;
+mod secrets;
 mod utils;
 
 pub use sea_orm;
 
+pub use blobs::{
+    attachment_content_type, is_inline_type, new_blob_key, BlobError, BlobStore, BlobStream,
+    LocalBlobStore, S3BlobStore,
+};
+pub use compression::{
+    decompress_content, set_compression_threshold, DEFAULT_COMPRESSION_THRESHOLD,
+};
+pub use files::{form_files, PasteFile, MAX_PASTE_FILES};
+pub use filters::*;
+pub use keys::{normalize_custom_url, set_key_config, set_reserved_ids, KeyConfig, KeyStyle};
 pub use mutation::*;
 pub use query::*;
+pub use search::{SearchHit, SEARCH_PAGE_SIZE};
+#[cfg(feature = "tantivy")]
+pub use search_index::open_search_index;
+pub use secrets::*;
+pub use utils::{is_instance_host, load_domain_blocklist, set_instance_hosts};
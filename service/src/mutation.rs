@@ -1,69 +1,540 @@
-use chrono::Utc;
-use entity::{pastes, schema, users};
+use chrono::{NaiveDateTime, Utc};
+use entity::filter_rules::Action as FilterAction;
+use entity::moderation_actions::Action;
+use entity::schema::SecretsPolicy;
+use entity::users::Role;
+use entity::{
+    attachments, clicks, domains, filter_rules, idempotency_keys, link_checks, moderation_actions,
+    paste_aliases, paste_contents, paste_files, pastes, reports, schema, users, users_tokens,
+};
+use sea_orm::sea_query::{Condition, Expr, Func};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, PaginatorTrait,
-    QueryFilter,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, SqlErr, TransactionTrait,
 };
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
+    compression, contents, evaluate_filter_rules,
+    files::{self, PasteFile},
+    filters, keys, redact_secrets, scan_secrets, search,
     utils::{self, is_url},
-    Query,
+    validate_filter_rule, Query,
 };
 
 pub struct Mutation;
 
+/// Largest number of links that can be shortened in one batch.
+pub const MAX_LINK_BATCH: usize = 1000;
+
+/// Most files that can be attached to one paste.
+pub const MAX_ATTACHMENTS_PER_PASTE: u64 = 10;
+
+/// Most API tokens one user can have at a time.
+pub const MAX_API_TOKENS: u64 = 10;
+
+/// How often a generated id is retried when it's already taken.
+const GENERATED_ID_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    Created,
+    /// The custom url is already taken.
+    Conflict,
+    Invalid,
+}
+
+/// Outcome of one row of a bulk shortening request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkResult {
+    /// Position of the row in the request, starting at 1.
+    pub row: usize,
+    pub url: String,
+    pub status: LinkStatus,
+    pub id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkBatch {
+    pub domain: String,
+    pub links: Vec<LinkResult>,
+    /// Whether this is the stored result of an earlier request with the same idempotency key.
+    pub replayed: bool,
+}
+
 impl Mutation {
-    #[tracing::instrument]
+    #[tracing::instrument(skip(form_data))]
     pub async fn create_paste(
         db: &DbConn,
-        form_data: &pastes::Model,
+        form_data: &schema::PastePost,
         current_user: Option<users::Model>,
+        domain: &str,
     ) -> Result<pastes::Model, DbErr> {
+        Mutation::check_domain_owner(db, current_user.as_ref(), domain).await?;
+
         // if the user defined a custom url, use it
-        let id = match (current_user.as_ref(), &form_data.custom_url) {
-            (Some(_user), Some(custom_url)) => {
-                if !custom_url.is_empty() {
-                    tracing::debug!("Custom URL is not empty");
-                    custom_url.clone()
-                } else {
-                    tracing::debug!("Custom URL is empty, generating random key");
-                    utils::generate_key(10)
-                }
+        let custom_url = match (current_user.as_ref(), &form_data.custom_url) {
+            (Some(_user), Some(custom_url)) if !custom_url.trim().is_empty() => {
+                tracing::debug!("Custom URL is not empty");
+                Some(keys::normalize_custom_url(custom_url).map_err(DbErr::Custom)?)
             }
             _ => {
-                tracing::debug!("Current user is empty, generating random key");
-                utils::generate_key(10)
+                tracing::debug!("No custom URL, generating random key");
+                None
             }
         };
-        let is_url = is_url(&form_data.content);
+        let (first, files) = Mutation::prepare_files(form_data)?;
+        let text = Mutation::files_text(&first, &files);
+        let names = search::file_names(&first, &files);
+        let rule = Mutation::apply_filter_rules(db, &text).await?;
+        let is_url = files.is_empty() && first.filename.is_none() && is_url(&first.content);
+        let held = rule
+            .as_ref()
+            .is_some_and(|r| r.action == FilterAction::Hold);
 
-        let paste = pastes::ActiveModel {
-            id: ActiveValue::Set(id),
-            content: ActiveValue::Set(form_data.content.to_owned()),
+        let txn = db.begin().await?;
+        let mut paste = pastes::ActiveModel {
+            domain: ActiveValue::Set(domain.to_lowercase()),
             is_url: ActiveValue::Set(is_url),
+            filename: ActiveValue::Set(first.filename),
+            language: ActiveValue::Set(first.language),
             belongs_to: match current_user {
                 Some(user) => ActiveValue::Set(Some(user.id)),
                 None => ActiveValue::NotSet,
             },
+            hidden_at: ActiveValue::Set(held.then(|| Utc::now().naive_utc())),
+            hidden_reason: ActiveValue::Set(held.then(|| String::from("held"))),
+            inserted_at: ActiveValue::Set(Some(Utc::now().naive_utc())),
+            ..Default::default()
+        };
+        contents::set_content(&txn, &mut paste, &first.content, is_url).await?;
+        let mut paste = Mutation::insert_paste(&txn, paste, custom_url.as_deref()).await?;
+        Mutation::insert_paste_files(&txn, &paste, files).await?;
+        if let Some(rule) = rule {
+            Mutation::report_filter_match(&txn, &paste, &rule).await?;
+        }
+        search::index_paste(&txn, &paste, &names, &text).await?;
+        txn.commit().await?;
+        search::add_to_index(&paste, &names, &text).await;
+
+        paste.content = first.content;
+        Ok(paste)
+    }
+
+    /// Inserts a paste under `custom_url`, or under a generated id that is retried
+    /// (and made longer) while it collides with an existing paste.
+    ///
+    /// Each attempt runs in its own savepoint so a collision doesn't abort the
+    /// surrounding transaction. Fails with a unique constraint violation when the
+    /// custom url is taken or no free id was found, and with a custom error when the
+    /// custom url is the old id of a renamed paste.
+    async fn insert_paste<C: ConnectionTrait + TransactionTrait>(
+        db: &C,
+        paste: pastes::ActiveModel,
+        custom_url: Option<&str>,
+    ) -> Result<pastes::Model, DbErr> {
+        let attempts = if custom_url.is_some() {
+            1
+        } else {
+            GENERATED_ID_ATTEMPTS
         };
 
-        paste.insert(db).await
+        let mut attempt = 0;
+        loop {
+            let id = custom_url.map_or_else(|| keys::generate_key(attempt), str::to_owned);
+            // ids and aliases share one namespace per domain
+            if Mutation::is_alias(db, paste.domain.as_ref(), &id).await? {
+                if custom_url.is_some() {
+                    return Err(DbErr::Custom(format!("{} is already taken.", id)));
+                }
+                if attempt + 1 >= attempts {
+                    return Err(DbErr::Custom(String::from(
+                        "Couldn't find a free id for the paste, please try again.",
+                    )));
+                }
+                tracing::debug!("Generated key is an alias, retrying");
+                keys::key_collision(attempt);
+                attempt += 1;
+                continue;
+            }
+            let mut paste = paste.clone();
+            paste.id = ActiveValue::Set(id);
+
+            let savepoint = db.begin().await?;
+            match paste.insert(&savepoint).await {
+                Ok(paste) => {
+                    savepoint.commit().await?;
+                    return Ok(paste);
+                }
+                Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                    savepoint.rollback().await?;
+                    if custom_url.is_some() || attempt + 1 >= attempts {
+                        return Err(e);
+                    }
+                    tracing::debug!("Generated key is taken, retrying");
+                    keys::key_collision(attempt);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
+    async fn is_alias<C: ConnectionTrait>(db: &C, domain: &str, id: &str) -> Result<bool, DbErr> {
+        let count = paste_aliases::Entity::find_by_id((domain.to_owned(), id.to_owned()))
+            .count(db)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Gives a paste a new id. The old id keeps working as an alias that redirects
+    /// to the new one, only the owner can rename a paste.
     #[tracing::instrument]
+    pub async fn rename_paste(
+        db: &DbConn,
+        domain: &str,
+        paste_id: &str,
+        current_user: &users::Model,
+        new_id: &str,
+    ) -> Result<pastes::Model, DbErr> {
+        let paste = Query::get_paste_by_id(db, domain, paste_id).await?;
+        if paste.belongs_to != Some(current_user.id) {
+            return Err(DbErr::RecordNotFound(String::from("paste not found")));
+        }
+        if paste.hidden_at.is_some() {
+            return Err(DbErr::Custom(String::from(
+                "This paste has been taken down and can't be renamed",
+            )));
+        }
+
+        let new_id = keys::normalize_custom_url(new_id).map_err(DbErr::Custom)?;
+        if new_id == paste.id {
+            return Err(DbErr::Custom(format!(
+                "This paste is already called {}.",
+                new_id
+            )));
+        }
+
+        let txn = db.begin().await?;
+        // going back to one of its own old ids turns that alias back into the real id
+        match paste_aliases::Entity::find_by_id((paste.domain.clone(), new_id.clone()))
+            .one(&txn)
+            .await?
+        {
+            Some(alias) if alias.paste_id == paste.id => {
+                alias.delete(&txn).await?;
+            }
+            Some(_) => return Err(DbErr::Custom(format!("{} is already taken.", new_id))),
+            None => (),
+        }
+
+        // aliases, clicks, reports and health checks follow through ON UPDATE CASCADE
+        let renamed = pastes::Entity::update_many()
+            .col_expr(pastes::Column::Id, Expr::value(new_id.clone()))
+            .filter(pastes::Column::Domain.eq(&paste.domain))
+            .filter(pastes::Column::Id.eq(&paste.id))
+            .exec(&txn)
+            .await;
+        match renamed {
+            Ok(_) => (),
+            Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                return Err(DbErr::Custom(format!("{} is already taken.", new_id)))
+            }
+            Err(e) => return Err(e),
+        }
+        moderation_actions::Entity::update_many()
+            .col_expr(
+                moderation_actions::Column::PasteId,
+                Expr::value(new_id.clone()),
+            )
+            .filter(moderation_actions::Column::PasteDomain.eq(&paste.domain))
+            .filter(moderation_actions::Column::PasteId.eq(&paste.id))
+            .exec(&txn)
+            .await?;
+
+        paste_aliases::ActiveModel {
+            domain: ActiveValue::Set(paste.domain.clone()),
+            id: ActiveValue::Set(paste.id.clone()),
+            paste_id: ActiveValue::Set(new_id.clone()),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+
+        let renamed = Query::get_paste_by_id(db, &paste.domain, &new_id).await?;
+        search::rename_paste(db, &paste.id, &renamed).await;
+        Ok(renamed)
+    }
+
+    #[tracing::instrument(skip(form_data))]
     pub async fn update_paste_content(
         db: &DbConn,
-        form_data: &pastes::Model,
+        form_data: &schema::PastePost,
         current_user: Option<users::Model>,
+        domain: &str,
         paste_id: &str,
     ) -> Result<pastes::Model, DbErr> {
-        let is_url = is_url(&form_data.content);
+        let (first, files) = Mutation::prepare_files(form_data)?;
+        let text = Mutation::files_text(&first, &files);
+        let names = search::file_names(&first, &files);
+        let rule = Mutation::apply_filter_rules(db, &text).await?;
+        let is_url = files.is_empty() && first.filename.is_none() && is_url(&first.content);
 
-        let mut paste: pastes::ActiveModel = Query::get_paste_by_id(db, paste_id).await?.into();
-        paste.content = ActiveValue::Set(form_data.content.clone());
+        let paste = Query::get_paste_by_id(db, domain, paste_id).await?;
+        if paste.hidden_at.is_some() {
+            return Err(DbErr::Custom(String::from(
+                "This paste has been taken down and can't be edited",
+            )));
+        }
+
+        let txn = db.begin().await?;
+        // the files are saved together, so the old ones are replaced as a whole
+        let files_of_paste = Condition::all()
+            .add(paste_files::Column::PasteDomain.eq(&paste.domain))
+            .add(paste_files::Column::PasteId.eq(&paste.id));
+        let mut old_hashes = contents::file_hashes(&txn, files_of_paste.clone()).await?;
+        paste_files::Entity::delete_many()
+            .filter(files_of_paste)
+            .exec(&txn)
+            .await?;
+        old_hashes.extend(paste.content_hash.clone());
+        let mut paste: pastes::ActiveModel = paste.into();
+        contents::set_content(&txn, &mut paste, &first.content, is_url).await?;
         paste.is_url = ActiveValue::Set(is_url);
+        paste.filename = ActiveValue::Set(first.filename);
+        paste.language = ActiveValue::Set(first.language);
+        // a new target gets checked from scratch
+        paste.checked_at = ActiveValue::Set(None);
+        paste.broken_at = ActiveValue::Set(None);
+        if rule
+            .as_ref()
+            .is_some_and(|r| r.action == FilterAction::Hold)
+        {
+            paste.hidden_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+            paste.hidden_reason = ActiveValue::Set(Some(String::from("held")));
+        }
+        let mut paste = paste.update(&txn).await?;
+        contents::release(&txn, old_hashes).await?;
+        Mutation::insert_paste_files(&txn, &paste, files).await?;
+        if let Some(rule) = rule {
+            Mutation::report_filter_match(&txn, &paste, &rule).await?;
+        }
+        search::index_paste(&txn, &paste, &names, &text).await?;
+        txn.commit().await?;
+        search::add_to_index(&paste, &names, &text).await;
 
-        paste.update(db).await
+        paste.content = first.content;
+        Ok(paste)
+    }
+
+    /// Checks the files of a submitted paste and applies the secrets policy to each of
+    /// them. Returns the first file and the others separately, as they're stored apart.
+    fn prepare_files(form_data: &schema::PastePost) -> Result<(PasteFile, Vec<PasteFile>), DbErr> {
+        let mut files = files::check_files(files::form_files(form_data)).map_err(DbErr::Custom)?;
+        for file in files.iter_mut() {
+            let content = std::mem::take(&mut file.content);
+            file.content = Mutation::apply_secrets_policy(form_data.secrets, content)?;
+        }
+        let first = files.remove(0);
+        Ok((first, files))
+    }
+
+    /// Everything the content filter looks at.
+    fn files_text(first: &PasteFile, files: &[PasteFile]) -> String {
+        std::iter::once(first)
+            .chain(files)
+            .map(|f| f.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Stores the second and later files of a paste, their content goes to
+    /// `paste_contents` like the first one's.
+    async fn insert_paste_files<C: ConnectionTrait>(
+        db: &C,
+        paste: &pastes::Model,
+        files: Vec<PasteFile>,
+    ) -> Result<(), DbErr> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        let mut rows = Vec::with_capacity(files.len());
+        for (i, file) in files.into_iter().enumerate() {
+            rows.push(paste_files::ActiveModel {
+                paste_domain: ActiveValue::Set(paste.domain.clone()),
+                paste_id: ActiveValue::Set(paste.id.clone()),
+                position: ActiveValue::Set(i as i16 + 1),
+                filename: ActiveValue::Set(file.filename.unwrap_or_default()),
+                language: ActiveValue::Set(file.language),
+                content: ActiveValue::Set(String::new()),
+                content_hash: ActiveValue::Set(Some(contents::acquire(db, &file.content).await?)),
+            });
+        }
+        paste_files::Entity::insert_many(rows).exec(db).await?;
+        Ok(())
+    }
+
+    /// Rejects content that links to a blocklisted domain or matches a `reject` rule,
+    /// otherwise returns the most severe matching rule.
+    async fn apply_filter_rules(
+        db: &DbConn,
+        content: &str,
+    ) -> Result<Option<filter_rules::Model>, DbErr> {
+        let rules = Query::get_filter_rules(db, true).await?;
+        Mutation::check_filter_rules(&rules, content)
+    }
+
+    /// Same as [`Mutation::apply_filter_rules`] for rules that have already been loaded.
+    fn check_filter_rules(
+        rules: &[filter_rules::Model],
+        content: &str,
+    ) -> Result<Option<filter_rules::Model>, DbErr> {
+        if let Some(host) = utils::blocked_url_host(content) {
+            return Err(DbErr::Custom(format!(
+                "Short links to {} are not allowed.",
+                host
+            )));
+        }
+
+        match evaluate_filter_rules(rules, content) {
+            Some(rule) if rule.action == FilterAction::Reject => {
+                tracing::debug!("Paste rejected by filter rule {}", rule.id);
+                Err(DbErr::Custom(String::from(
+                    "This paste was rejected by the content filter.",
+                )))
+            }
+            rule => Ok(rule.cloned()),
+        }
+    }
+
+    /// Pastes on a custom domain can only be created by the owner of the domain.
+    async fn check_domain_owner(
+        db: &DbConn,
+        current_user: Option<&users::Model>,
+        domain: &str,
+    ) -> Result<(), DbErr> {
+        if domain.is_empty() {
+            return Ok(());
+        }
+
+        let owned = match (current_user, Query::get_verified_domain(db, domain).await?) {
+            (Some(user), Some(domain)) => domain.user_id == user.id,
+            _ => false,
+        };
+        if !owned {
+            return Err(DbErr::Custom(format!(
+                "Only the owner of {} can create pastes on it.",
+                domain
+            )));
+        }
+        Ok(())
+    }
+
+    /// Puts a paste that matched a `hold` or `flag` rule into the moderation queue.
+    async fn report_filter_match<C: ConnectionTrait>(
+        db: &C,
+        paste: &pastes::Model,
+        rule: &filter_rules::Model,
+    ) -> Result<reports::Model, DbErr> {
+        let verb = match rule.action {
+            FilterAction::Hold => "Held",
+            _ => "Flagged",
+        };
+
+        reports::ActiveModel {
+            paste_domain: ActiveValue::Set(paste.domain.clone()),
+            paste_id: ActiveValue::Set(paste.id.clone()),
+            reporter_id: ActiveValue::Set(None),
+            reason: ActiveValue::Set(format!(
+                "{} by content filter rule #{} ({:?}: {})",
+                verb, rule.id, rule.kind, rule.pattern
+            )),
+            status: ActiveValue::Set(String::from("open")),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    #[tracing::instrument]
+    pub async fn create_filter_rule(
+        db: &DbConn,
+        form_data: &schema::FilterRulePost,
+    ) -> Result<filter_rules::Model, DbErr> {
+        validate_filter_rule(form_data.kind, &form_data.pattern).map_err(DbErr::Custom)?;
+
+        filter_rules::ActiveModel {
+            kind: ActiveValue::Set(form_data.kind),
+            pattern: ActiveValue::Set(form_data.pattern.trim().to_owned()),
+            action: ActiveValue::Set(form_data.action),
+            enabled: ActiveValue::Set(true),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    #[tracing::instrument]
+    pub async fn toggle_filter_rule(
+        db: &DbConn,
+        rule_id: i64,
+    ) -> Result<filter_rules::Model, DbErr> {
+        let rule = filter_rules::Entity::find_by_id(rule_id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("rule not found")))?;
+        let enabled = rule.enabled;
+
+        let mut rule: filter_rules::ActiveModel = rule.into();
+        rule.enabled = ActiveValue::Set(!enabled);
+        rule.update(db).await
+    }
+
+    #[tracing::instrument]
+    pub async fn delete_filter_rule(db: &DbConn, rule_id: i64) -> Result<(), DbErr> {
+        let result = filter_rules::Entity::delete_by_id(rule_id).exec(db).await?;
+        if result.rows_affected == 0 {
+            return Err(DbErr::RecordNotFound(String::from("rule not found")));
+        }
+        filters::forget_filter_rule(rule_id);
+        Ok(())
+    }
+
+    /// Runs the secret scanner over the submitted content and returns what should be stored.
+    fn apply_secrets_policy(
+        policy: Option<SecretsPolicy>,
+        content: String,
+    ) -> Result<String, DbErr> {
+        let findings = scan_secrets(&content);
+        if findings.is_empty() {
+            return Ok(content);
+        }
+
+        match policy.unwrap_or_default() {
+            SecretsPolicy::Publish => {
+                tracing::debug!(
+                    "Publishing paste with {} possible secret(s)",
+                    findings.len()
+                );
+                Ok(content)
+            }
+            SecretsPolicy::Redact => Ok(redact_secrets(&content)),
+            SecretsPolicy::Reject => {
+                let mut kinds: Vec<_> = findings.iter().map(|f| f.kind).collect();
+                kinds.sort();
+                kinds.dedup();
+                Err(DbErr::Custom(format!(
+                    "This paste looks like it contains secrets ({}).",
+                    kinds.join(", ")
+                )))
+            }
+        }
     }
 
     #[tracing::instrument]
@@ -71,8 +542,9 @@ impl Mutation {
         db: &DbConn,
         form_data: &schema::LoginPost,
     ) -> Result<users::Model, DbErr> {
+        let email = utils::normalize_email(&form_data.email);
         let existing_user = users::Entity::find()
-            .filter(users::Column::Email.eq(form_data.email.clone()))
+            .filter(users::Column::Email.eq(&email))
             .count(db)
             .await?;
         if existing_user != 0 {
@@ -83,7 +555,7 @@ impl Mutation {
             .map_err(|_| DbErr::Custom(String::from("Passwords do not match")))?;
 
         let user = users::ActiveModel {
-            email: ActiveValue::Set(form_data.email.clone()),
+            email: ActiveValue::Set(email),
             hashed_password: ActiveValue::Set(hashed_password),
             inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
             updated_at: ActiveValue::Set(Utc::now().naive_utc()),
@@ -91,4 +563,851 @@ impl Mutation {
         };
         user.insert(db).await
     }
+
+    #[tracing::instrument]
+    pub async fn create_report(
+        db: &DbConn,
+        domain: &str,
+        paste_id: &str,
+        form_data: &schema::ReportPost,
+        current_user: Option<users::Model>,
+    ) -> Result<reports::Model, DbErr> {
+        let reason = form_data.reason.trim();
+        if reason.is_empty() {
+            return Err(DbErr::Custom(String::from(
+                "Please tell us what is wrong with this paste.",
+            )));
+        }
+        if reason.len() > 2000 {
+            return Err(DbErr::Custom(String::from(
+                "Reports are limited to 2000 characters.",
+            )));
+        }
+
+        let paste = Query::get_paste_by_id(db, domain, paste_id).await?;
+        let report = reports::ActiveModel {
+            paste_domain: ActiveValue::Set(paste.domain),
+            paste_id: ActiveValue::Set(paste.id),
+            reporter_id: ActiveValue::Set(current_user.map(|u| u.id)),
+            reason: ActiveValue::Set(reason.to_owned()),
+            status: ActiveValue::Set(String::from("open")),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        report.insert(db).await
+    }
+
+    /// Resolves a report with the given action and records it in the moderation log.
+    /// Every other open report for the same paste is resolved along with it.
+    #[tracing::instrument]
+    pub async fn moderate_report(
+        db: &DbConn,
+        report_id: i64,
+        moderator: &users::Model,
+        form_data: &schema::ModerationPost,
+    ) -> Result<moderation_actions::Model, DbErr> {
+        let txn = db.begin().await?;
+
+        let report = reports::Entity::find_by_id(report_id)
+            .one(&txn)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("report not found")))?;
+        let paste =
+            pastes::Entity::find_by_id((report.paste_domain.clone(), report.paste_id.clone()))
+                .one(&txn)
+                .await?
+                .ok_or(DbErr::RecordNotFound(String::from("paste not found")))?;
+
+        let status = match form_data.action {
+            Action::Dismiss => "dismissed",
+            _ => "resolved",
+        };
+        reports::Entity::update_many()
+            .col_expr(reports::Column::Status, status.into())
+            .col_expr(reports::Column::ResolvedAt, Utc::now().naive_utc().into())
+            .filter(reports::Column::PasteDomain.eq(paste.domain.clone()))
+            .filter(reports::Column::PasteId.eq(paste.id.clone()))
+            .filter(reports::Column::Status.eq("open"))
+            .exec(&txn)
+            .await?;
+
+        let entry =
+            Mutation::apply_moderation(&txn, paste, Some(report.id), moderator, form_data).await?;
+
+        txn.commit().await?;
+        Ok(entry)
+    }
+
+    /// Takes a paste down (or restores it) without a report, e.g. from the admin paste search.
+    #[tracing::instrument]
+    pub async fn takedown_paste(
+        db: &DbConn,
+        domain: &str,
+        paste_id: &str,
+        moderator: &users::Model,
+        form_data: &schema::ModerationPost,
+    ) -> Result<moderation_actions::Model, DbErr> {
+        let txn = db.begin().await?;
+
+        let paste = pastes::Entity::find_by_id((domain.to_owned(), paste_id.to_owned()))
+            .one(&txn)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("paste not found")))?;
+        let entry = Mutation::apply_moderation(&txn, paste, None, moderator, form_data).await?;
+
+        txn.commit().await?;
+        Ok(entry)
+    }
+
+    async fn apply_moderation<C: ConnectionTrait>(
+        db: &C,
+        paste: pastes::Model,
+        report_id: Option<i64>,
+        moderator: &users::Model,
+        form_data: &schema::ModerationPost,
+    ) -> Result<moderation_actions::Model, DbErr> {
+        let now = Utc::now().naive_utc();
+
+        match form_data.action {
+            // dismissing the report for a paste held by the content filter approves it
+            Action::Dismiss if paste.hidden_reason.as_deref() == Some("held") => {
+                Mutation::set_paste_hidden(db, paste.clone(), None).await?;
+            }
+            Action::Dismiss => {}
+            Action::Hide => {
+                Mutation::set_paste_hidden(db, paste.clone(), Some("removed")).await?;
+            }
+            Action::HideLegal => {
+                Mutation::set_paste_hidden(db, paste.clone(), Some("legal")).await?;
+            }
+            Action::Restore => {
+                Mutation::set_paste_hidden(db, paste.clone(), None).await?;
+            }
+            Action::Delete => {
+                let mut hashes = contents::file_hashes(
+                    db,
+                    Condition::all()
+                        .add(paste_files::Column::PasteDomain.eq(&paste.domain))
+                        .add(paste_files::Column::PasteId.eq(&paste.id)),
+                )
+                .await?;
+                hashes.extend(paste.content_hash.clone());
+                // the files go with the paste
+                pastes::Entity::delete_by_id((paste.domain.clone(), paste.id.clone()))
+                    .exec(db)
+                    .await?;
+                contents::release(db, hashes).await?;
+                search::remove_paste(&paste.domain, &paste.id).await;
+            }
+            Action::BanAuthor => {
+                if let Some(author_id) = paste.belongs_to {
+                    users::Entity::update_many()
+                        .col_expr(users::Column::DisabledAt, now.into())
+                        .filter(users::Column::Id.eq(author_id))
+                        .exec(db)
+                        .await?;
+                }
+                Mutation::set_paste_hidden(db, paste.clone(), Some("removed")).await?;
+            }
+        }
+
+        moderation_actions::ActiveModel {
+            paste_domain: ActiveValue::Set(paste.domain),
+            paste_id: ActiveValue::Set(paste.id),
+            report_id: ActiveValue::Set(report_id),
+            moderator_id: ActiveValue::Set(Some(moderator.id)),
+            action: ActiveValue::Set(form_data.action),
+            note: ActiveValue::Set(form_data.note.clone().filter(|n| !n.trim().is_empty())),
+            inserted_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    async fn set_paste_hidden<C: ConnectionTrait>(
+        db: &C,
+        paste: pastes::Model,
+        reason: Option<&str>,
+    ) -> Result<pastes::Model, DbErr> {
+        let mut paste: pastes::ActiveModel = paste.into();
+        paste.hidden_at = ActiveValue::Set(reason.map(|_| Utc::now().naive_utc()));
+        paste.hidden_reason = ActiveValue::Set(reason.map(str::to_owned));
+        paste.update(db).await
+    }
+
+    /// Promotes the accounts with the given emails to admins. Used to bootstrap
+    /// the first admins from the environment.
+    pub async fn promote_admins(db: &DbConn, emails: &[String]) -> Result<u64, DbErr> {
+        if emails.is_empty() {
+            return Ok(0);
+        }
+
+        let result = users::Entity::update_many()
+            .col_expr(users::Column::Role, Role::Admin.into())
+            .filter(users::Column::Email.is_in(emails.iter().map(|e| utils::normalize_email(e))))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    #[tracing::instrument]
+    pub async fn set_user_role(
+        db: &DbConn,
+        user_id: i64,
+        role: Role,
+    ) -> Result<users::Model, DbErr> {
+        let mut user: users::ActiveModel = Query::get_user_by_id(db, user_id).await?.into();
+        user.role = ActiveValue::Set(role);
+        user.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+        user.update(db).await
+    }
+
+    /// Disables an enabled account and re-enables a disabled one.
+    #[tracing::instrument]
+    pub async fn toggle_user_disabled(db: &DbConn, user_id: i64) -> Result<users::Model, DbErr> {
+        let user = Query::get_user_by_id(db, user_id).await?;
+        let disabled_at = match user.disabled_at {
+            Some(_) => None,
+            None => Some(Utc::now().naive_utc()),
+        };
+
+        let mut user: users::ActiveModel = user.into();
+        user.disabled_at = ActiveValue::Set(disabled_at);
+        user.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+        user.update(db).await
+    }
+
+    /// Makes the user pick a new password the next time they use the site.
+    #[tracing::instrument]
+    pub async fn require_password_reset(db: &DbConn, user_id: i64) -> Result<users::Model, DbErr> {
+        let mut user: users::ActiveModel = Query::get_user_by_id(db, user_id).await?.into();
+        user.password_reset_required = ActiveValue::Set(true);
+        user.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+        user.update(db).await
+    }
+
+    #[tracing::instrument(skip(form_data))]
+    pub async fn reset_password(
+        db: &DbConn,
+        user: users::Model,
+        form_data: &schema::PasswordResetPost,
+    ) -> Result<users::Model, DbErr> {
+        if form_data.password != form_data.password_confirmation {
+            return Err(DbErr::Custom(String::from("Passwords do not match")));
+        }
+        if form_data.password.len() < 8 {
+            return Err(DbErr::Custom(String::from(
+                "Passwords must be at least 8 characters long",
+            )));
+        }
+
+        let hashed_password = bcrypt::hash(form_data.password.clone(), 10)
+            .map_err(|_| DbErr::Custom(String::from("Could not update the password")))?;
+
+        let mut user: users::ActiveModel = user.into();
+        user.hashed_password = ActiveValue::Set(hashed_password);
+        user.password_reset_required = ActiveValue::Set(false);
+        user.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+        user.update(db).await
+    }
+
+    /// Stores a batch of recorded link clicks.
+    pub async fn record_clicks(db: &DbConn, batch: Vec<clicks::ActiveModel>) -> Result<(), DbErr> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        clicks::Entity::insert_many(batch).exec(db).await?;
+        Ok(())
+    }
+
+    /// Updates how a short link redirects. Only the owner of the link can change it.
+    #[tracing::instrument]
+    pub async fn update_link_settings(
+        db: &DbConn,
+        domain: &str,
+        paste_id: &str,
+        current_user: &users::Model,
+        form_data: &schema::LinkSettingsPost,
+    ) -> Result<pastes::Model, DbErr> {
+        let paste = Query::get_paste_by_id(db, domain, paste_id).await?;
+        if !paste.is_url || paste.belongs_to != Some(current_user.id) {
+            return Err(DbErr::RecordNotFound(String::from("paste not found")));
+        }
+
+        if ![301, 302, 307, 308].contains(&form_data.redirect_code) {
+            return Err(DbErr::Custom(String::from(
+                "Redirects can only use 301, 302, 307 or 308.",
+            )));
+        }
+
+        let expires_at = match form_data.expires_at.trim() {
+            "" => None,
+            value => Some(
+                NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+                    .map_err(|_| DbErr::Custom(String::from("The expiry date is not valid.")))?,
+            ),
+        };
+        let max_clicks = match form_data.max_clicks.trim() {
+            "" => None,
+            value => match value.parse::<i32>() {
+                Ok(max) if max > 0 => Some(max),
+                _ => {
+                    return Err(DbErr::Custom(String::from(
+                        "The click limit has to be a positive number.",
+                    )))
+                }
+            },
+        };
+
+        let mut paste: pastes::ActiveModel = paste.into();
+        paste.redirect_code = ActiveValue::Set(form_data.redirect_code);
+        paste.preview = ActiveValue::Set(form_data.preview.is_some());
+        paste.expires_at = ActiveValue::Set(expires_at);
+        paste.max_clicks = ActiveValue::Set(max_clicks);
+        paste.update(db).await
+    }
+
+    /// Shortens a batch of links in one transaction, reporting the outcome of every row.
+    ///
+    /// Rows that fail validation or whose custom url is taken are skipped without failing
+    /// the whole batch. When an idempotency key is given, retrying the same batch returns
+    /// the stored result instead of creating the links again.
+    #[tracing::instrument(skip(form_data))]
+    pub async fn create_links(
+        db: &DbConn,
+        current_user: &users::Model,
+        domain: &str,
+        form_data: &schema::LinkBatchPost,
+        idempotency_key: Option<&str>,
+    ) -> Result<LinkBatch, DbErr> {
+        let domain = domain.to_lowercase();
+        if form_data.links.is_empty() {
+            return Err(DbErr::Custom(String::from(
+                "There are no links to shorten.",
+            )));
+        }
+        if form_data.links.len() > MAX_LINK_BATCH {
+            return Err(DbErr::Custom(format!(
+                "At most {} links can be shortened at once.",
+                MAX_LINK_BATCH
+            )));
+        }
+        Mutation::check_domain_owner(db, Some(current_user), &domain).await?;
+
+        let idempotency_key = idempotency_key.map(str::trim).filter(|key| !key.is_empty());
+        let request_hash = {
+            let request = serde_json::to_string(&(&domain, &form_data.links))
+                .map_err(|e| DbErr::Custom(e.to_string()))?;
+            format!("{:x}", Sha256::digest(request.as_bytes()))
+        };
+        if let Some(key) = idempotency_key {
+            if key.len() > 255 {
+                return Err(DbErr::Custom(String::from(
+                    "The idempotency key can be at most 255 characters long.",
+                )));
+            }
+            if let Some(batch) =
+                Mutation::replay_link_batch(db, current_user, key, &request_hash, &domain).await?
+            {
+                return Ok(batch);
+            }
+        }
+
+        let rules = Query::get_filter_rules(db, true).await?;
+        let txn = db.begin().await?;
+        let mut links = Vec::with_capacity(form_data.links.len());
+        for (i, row) in form_data.links.iter().enumerate() {
+            let url = row.url.trim();
+            let custom_url = row
+                .custom_url
+                .as_deref()
+                .filter(|custom_url| !custom_url.trim().is_empty())
+                .map(keys::normalize_custom_url);
+            let mut result = LinkResult {
+                row: i + 1,
+                url: url.to_owned(),
+                status: LinkStatus::Invalid,
+                id: None,
+                error: None,
+            };
+
+            if !is_url(url) {
+                result.error = Some(String::from("This is not a valid URL."));
+                links.push(result);
+                continue;
+            }
+            let custom_url = match custom_url.transpose() {
+                Ok(custom_url) => custom_url,
+                Err(msg) => {
+                    result.error = Some(msg);
+                    links.push(result);
+                    continue;
+                }
+            };
+            let rule = match Mutation::check_filter_rules(&rules, url) {
+                Ok(rule) => rule,
+                Err(DbErr::Custom(msg)) => {
+                    result.error = Some(msg);
+                    links.push(result);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let held = rule
+                .as_ref()
+                .is_some_and(|r| r.action == FilterAction::Hold);
+
+            let paste = pastes::ActiveModel {
+                domain: ActiveValue::Set(domain.clone()),
+                content: ActiveValue::Set(url.to_owned()),
+                is_url: ActiveValue::Set(true),
+                belongs_to: ActiveValue::Set(Some(current_user.id)),
+                hidden_at: ActiveValue::Set(held.then(|| Utc::now().naive_utc())),
+                hidden_reason: ActiveValue::Set(held.then(|| String::from("held"))),
+                inserted_at: ActiveValue::Set(Some(Utc::now().naive_utc())),
+                ..Default::default()
+            };
+            // every row gets its own savepoint, so a conflict doesn't abort the whole batch
+            match Mutation::insert_paste(&txn, paste, custom_url.as_deref()).await {
+                Ok(paste) => {
+                    if let Some(rule) = &rule {
+                        Mutation::report_filter_match(&txn, &paste, rule).await?;
+                    }
+                    result.status = LinkStatus::Created;
+                    result.id = Some(paste.id);
+                }
+                Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                    result.status = LinkStatus::Conflict;
+                    result.error = Some(match custom_url {
+                        Some(custom_url) => format!("{} is already taken.", custom_url),
+                        None => String::from("Could not generate a free id, try again."),
+                    });
+                }
+                Err(DbErr::Custom(msg)) => {
+                    result.status = LinkStatus::Conflict;
+                    result.error = Some(msg);
+                }
+                Err(e) => return Err(e),
+            }
+            links.push(result);
+        }
+
+        if let Some(key) = idempotency_key {
+            let response =
+                serde_json::to_string(&links).map_err(|e| DbErr::Custom(e.to_string()))?;
+            let stored = idempotency_keys::ActiveModel {
+                user_id: ActiveValue::Set(current_user.id),
+                key: ActiveValue::Set(key.to_owned()),
+                request_hash: ActiveValue::Set(request_hash.clone()),
+                response: ActiveValue::Set(response),
+                inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await;
+
+            if let Err(e) = stored {
+                txn.rollback().await?;
+                // a concurrent retry with the same key got there first
+                if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                    if let Some(batch) =
+                        Mutation::replay_link_batch(db, current_user, key, &request_hash, &domain)
+                            .await?
+                    {
+                        return Ok(batch);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        txn.commit().await?;
+
+        Ok(LinkBatch {
+            domain,
+            links,
+            replayed: false,
+        })
+    }
+
+    /// Looks up the stored result of an earlier batch sent with the same idempotency key.
+    async fn replay_link_batch(
+        db: &DbConn,
+        current_user: &users::Model,
+        key: &str,
+        request_hash: &str,
+        domain: &str,
+    ) -> Result<Option<LinkBatch>, DbErr> {
+        let Some(stored) = idempotency_keys::Entity::find()
+            .filter(idempotency_keys::Column::UserId.eq(current_user.id))
+            .filter(idempotency_keys::Column::Key.eq(key))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        if stored.request_hash != request_hash {
+            return Err(DbErr::Custom(String::from(
+                "This idempotency key was already used for a different batch.",
+            )));
+        }
+        let links =
+            serde_json::from_str(&stored.response).map_err(|e| DbErr::Custom(e.to_string()))?;
+        Ok(Some(LinkBatch {
+            domain: domain.to_owned(),
+            links,
+            replayed: true,
+        }))
+    }
+
+    /// Counts a click on a link with a click limit. Returns `false` once the limit is used up.
+    ///
+    /// The check and the increment happen in one statement so concurrent clicks can't
+    /// go over the limit.
+    pub async fn register_limited_click(db: &DbConn, paste: &pastes::Model) -> Result<bool, DbErr> {
+        if paste.max_clicks.is_none() {
+            return Ok(true);
+        }
+
+        let result = pastes::Entity::update_many()
+            .col_expr(
+                pastes::Column::ClickCount,
+                Expr::col(pastes::Column::ClickCount).add(1),
+            )
+            .filter(pastes::Column::Domain.eq(&paste.domain))
+            .filter(pastes::Column::Id.eq(&paste.id))
+            .filter(Expr::col(pastes::Column::ClickCount).lt(Expr::col(pastes::Column::MaxClicks)))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Stores the outcome of a link health check.
+    ///
+    /// A link is marked broken after `failures` unhealthy checks in a row and recovers
+    /// as soon as a check succeeds again.
+    pub async fn record_link_check(
+        db: &DbConn,
+        paste: &pastes::Model,
+        healthy: bool,
+        status_code: Option<i16>,
+        error: Option<String>,
+        failures: u64,
+    ) -> Result<pastes::Model, DbErr> {
+        let now = Utc::now().naive_utc();
+        let txn = db.begin().await?;
+        link_checks::ActiveModel {
+            paste_domain: ActiveValue::Set(paste.domain.clone()),
+            paste_id: ActiveValue::Set(paste.id.clone()),
+            checked_at: ActiveValue::Set(now),
+            healthy: ActiveValue::Set(healthy),
+            status_code: ActiveValue::Set(status_code),
+            error: ActiveValue::Set(error),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let broken_at = if healthy {
+            None
+        } else if paste.broken_at.is_some() {
+            paste.broken_at
+        } else {
+            let recent = Query::get_link_checks(&txn, paste, failures).await?;
+            let broken = recent.len() as u64 >= failures && recent.iter().all(|c| !c.healthy);
+            broken.then_some(now)
+        };
+
+        let mut model: pastes::ActiveModel = paste.clone().into();
+        model.checked_at = ActiveValue::Set(Some(now));
+        model.broken_at = ActiveValue::Set(broken_at);
+        let paste = model.update(&txn).await?;
+        txn.commit().await?;
+
+        Ok(paste)
+    }
+
+    /// Pushes a link back in the check queue without recording a result, e.g. when
+    /// robots.txt doesn't allow checking it.
+    pub async fn skip_link_check(db: &DbConn, paste: &pastes::Model) -> Result<(), DbErr> {
+        pastes::Entity::update_many()
+            .col_expr(
+                pastes::Column::CheckedAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(pastes::Column::Domain.eq(&paste.domain))
+            .filter(pastes::Column::Id.eq(&paste.id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Adds a custom domain for the user. It can only be used once it has been verified.
+    #[tracing::instrument]
+    pub async fn create_domain(
+        db: &DbConn,
+        current_user: &users::Model,
+        form_data: &schema::DomainPost,
+    ) -> Result<domains::Model, DbErr> {
+        let host = form_data.host.trim().trim_end_matches('.').to_lowercase();
+        let valid = matches!(url::Host::parse(&host), Ok(url::Host::Domain(_)))
+            && host.contains('.')
+            && host.len() <= 253;
+        if !valid {
+            return Err(DbErr::Custom(format!(
+                "{} is not a valid domain name.",
+                host
+            )));
+        }
+        if utils::is_instance_host(&host) {
+            return Err(DbErr::Custom(String::from(
+                "The instance's own domains can't be added.",
+            )));
+        }
+
+        let taken = domains::Entity::find()
+            .filter(domains::Column::Host.eq(&host))
+            .count(db)
+            .await?;
+        if taken > 0 {
+            return Err(DbErr::Custom(format!("{} has already been added.", host)));
+        }
+
+        domains::ActiveModel {
+            host: ActiveValue::Set(host),
+            user_id: ActiveValue::Set(current_user.id),
+            verification_token: ActiveValue::Set(utils::generate_token(32)),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    pub async fn mark_domain_verified(
+        db: &DbConn,
+        domain: domains::Model,
+    ) -> Result<domains::Model, DbErr> {
+        let mut domain: domains::ActiveModel = domain.into();
+        domain.verified_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+        domain.update(db).await
+    }
+
+    /// Removes a custom domain along with every paste created on it.
+    #[tracing::instrument]
+    pub async fn delete_domain(
+        db: &DbConn,
+        current_user: &users::Model,
+        domain_id: i64,
+    ) -> Result<(), DbErr> {
+        let domain = Mutation::get_owned_domain(db, current_user, domain_id).await?;
+
+        let txn = db.begin().await?;
+        let mut hashes: Vec<String> = pastes::Entity::find()
+            .select_only()
+            .column(pastes::Column::ContentHash)
+            .filter(pastes::Column::Domain.eq(&domain.host))
+            .filter(pastes::Column::ContentHash.is_not_null())
+            .into_tuple()
+            .all(&txn)
+            .await?;
+        hashes.extend(
+            contents::file_hashes(&txn, paste_files::Column::PasteDomain.eq(&domain.host)).await?,
+        );
+        pastes::Entity::delete_many()
+            .filter(pastes::Column::Domain.eq(&domain.host))
+            .exec(&txn)
+            .await?;
+        contents::release(&txn, hashes).await?;
+        domains::Entity::delete_by_id(domain.id).exec(&txn).await?;
+        txn.commit().await?;
+        search::remove_domain(&domain.host).await;
+        Ok(())
+    }
+
+    /// A domain belonging to `current_user`, verified or not.
+    pub async fn get_owned_domain(
+        db: &DbConn,
+        current_user: &users::Model,
+        domain_id: i64,
+    ) -> Result<domains::Model, DbErr> {
+        domains::Entity::find_by_id(domain_id)
+            .filter(domains::Column::UserId.eq(current_user.id))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("domain not found")))
+    }
+
+    /// Issues a new API token for `current_user`. Only its SHA-256 digest is stored, so the
+    /// returned token can't be shown again.
+    pub async fn create_api_token(
+        db: &DbConn,
+        current_user: &users::Model,
+    ) -> Result<String, DbErr> {
+        let count = users_tokens::Entity::find()
+            .filter(users_tokens::Column::UserId.eq(current_user.id))
+            .filter(users_tokens::Column::Context.eq("api"))
+            .count(db)
+            .await?;
+        if count >= MAX_API_TOKENS {
+            return Err(DbErr::Custom(format!(
+                "You can have at most {} API tokens, revoke one first.",
+                MAX_API_TOKENS
+            )));
+        }
+
+        let token = utils::generate_token(40);
+        users_tokens::ActiveModel {
+            user_id: ActiveValue::Set(current_user.id),
+            token: ActiveValue::Set(Sha256::digest(token.as_bytes()).to_vec()),
+            context: ActiveValue::Set(String::from("api")),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Revokes one of `current_user`'s API tokens.
+    pub async fn delete_api_token(
+        db: &DbConn,
+        current_user: &users::Model,
+        token_id: i64,
+    ) -> Result<(), DbErr> {
+        let res = users_tokens::Entity::delete_many()
+            .filter(users_tokens::Column::Id.eq(token_id))
+            .filter(users_tokens::Column::UserId.eq(current_user.id))
+            .filter(users_tokens::Column::Context.eq("api"))
+            .exec(db)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DbErr::RecordNotFound(String::from("token not found")));
+        }
+        Ok(())
+    }
+
+    /// Records a file uploaded to `storage_key` as an attachment of the paste. Only the
+    /// owner can attach files.
+    #[tracing::instrument(skip(paste))]
+    pub async fn create_attachment(
+        db: &DbConn,
+        paste: &pastes::Model,
+        current_user: &users::Model,
+        filename: &str,
+        content_type: &str,
+        size: i64,
+        storage_key: &str,
+    ) -> Result<attachments::Model, DbErr> {
+        if paste.belongs_to != Some(current_user.id) {
+            return Err(DbErr::RecordNotFound(String::from("paste not found")));
+        }
+        if paste.is_url {
+            return Err(DbErr::Custom(String::from(
+                "Files can only be attached to pastes, not to short links.",
+            )));
+        }
+        if paste.hidden_at.is_some() {
+            return Err(DbErr::Custom(String::from(
+                "This paste has been taken down, files can't be attached to it.",
+            )));
+        }
+        let count = paste.find_related(attachments::Entity).count(db).await?;
+        if count >= MAX_ATTACHMENTS_PER_PASTE {
+            return Err(DbErr::Custom(format!(
+                "A paste can have at most {} attachments.",
+                MAX_ATTACHMENTS_PER_PASTE
+            )));
+        }
+
+        attachments::ActiveModel {
+            paste_domain: ActiveValue::Set(Some(paste.domain.clone())),
+            paste_id: ActiveValue::Set(Some(paste.id.clone())),
+            filename: ActiveValue::Set(utils::clean_filename(filename)),
+            content_type: ActiveValue::Set(content_type.to_owned()),
+            size: ActiveValue::Set(size),
+            storage_key: ActiveValue::Set(storage_key.to_owned()),
+            inserted_at: ActiveValue::Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    /// Removes an attachment from its paste. The file itself stays in storage until it is
+    /// cleaned up with [`Mutation::delete_attachment`].
+    #[tracing::instrument(skip(paste))]
+    pub async fn detach_attachment(
+        db: &DbConn,
+        paste: &pastes::Model,
+        current_user: &users::Model,
+        attachment_id: i64,
+    ) -> Result<attachments::Model, DbErr> {
+        if paste.belongs_to != Some(current_user.id) {
+            return Err(DbErr::RecordNotFound(String::from("paste not found")));
+        }
+        let attachment = Query::get_paste_attachment(db, paste, attachment_id).await?;
+
+        let mut attachment: attachments::ActiveModel = attachment.into();
+        attachment.paste_domain = ActiveValue::Set(None);
+        attachment.paste_id = ActiveValue::Set(None);
+        attachment.update(db).await
+    }
+
+    /// Forgets an attachment whose file has been removed from storage.
+    pub async fn delete_attachment(db: &DbConn, attachment_id: i64) -> Result<(), DbErr> {
+        attachments::Entity::delete_by_id(attachment_id)
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Compresses stored paste contents that are over the compression threshold, e.g.
+    /// ones stored before compression was turned on. Returns how many were compressed.
+    pub async fn compress_stored_contents(db: &DbConn) -> Result<u64, DbErr> {
+        const BATCH_SIZE: u64 = 500;
+        let threshold = compression::compression_threshold();
+        if threshold == usize::MAX {
+            return Ok(0);
+        }
+
+        let mut compressed = 0;
+        // walks the table in key order so rows that don't shrink aren't read twice
+        let mut after: Option<String> = None;
+        loop {
+            let mut select = paste_contents::Entity::find()
+                .filter(paste_contents::Column::ContentCodec.is_null())
+                .filter(
+                    Expr::expr(Func::char_length(Expr::col(
+                        paste_contents::Column::Content,
+                    )))
+                    .gte(threshold as i64),
+                )
+                .order_by_asc(paste_contents::Column::Hash)
+                .limit(BATCH_SIZE);
+            if let Some(hash) = &after {
+                select = select.filter(paste_contents::Column::Hash.gt(hash));
+            }
+            let batch = select.all(db).await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = Some(last.hash.clone());
+
+            for row in batch {
+                let mut update: paste_contents::ActiveModel = row.clone().into();
+                compression::set_content(&mut update, &row.content);
+                if matches!(update.content_codec, ActiveValue::Set(Some(_))) {
+                    update.update(db).await?;
+                    compressed += 1;
+                }
+            }
+            tracing::info!("compressed {} paste content(s) so far", compressed);
+        }
+
+        Ok(compressed)
+    }
 }
@@ -1,19 +1,170 @@
-use entity::{pastes, schema, users};
-use sea_orm::{ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter};
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use entity::{
+    attachments, clicks, domains, filter_rules, link_checks, moderation_actions, paste_aliases,
+    paste_contents, paste_files, pastes, reports, schema, users, users_tokens,
+};
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, JoinType, ModelTrait, Order,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::contents;
+use crate::files::{self, PasteFile};
+use crate::utils;
+
+/// Number of rows shown per page in the admin panel.
+pub const ADMIN_PAGE_SIZE: u64 = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceStats {
+    pub pastes: u64,
+    pub urls: u64,
+    pub users: u64,
+    pub storage: i64,
+}
+
+/// Number of entries shown in each of the "top" tables on the link stats page.
+const TOP_CLICK_SOURCES: u64 = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyClicks {
+    pub day: NaiveDate,
+    pub clicks: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClickSource {
+    pub label: String,
+    pub clicks: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClickStats {
+    pub total: u64,
+    /// One entry per day, oldest first, including days without clicks.
+    pub daily: Vec<DailyClicks>,
+    pub referrers: Vec<ClickSource>,
+    pub user_agents: Vec<ClickSource>,
+    pub countries: Vec<ClickSource>,
+}
 
 pub struct Query;
 
 impl Query {
-    pub async fn get_paste_by_id(db: &DbConn, id: &str) -> Result<pastes::Model, DbErr> {
-        match pastes::Entity::find_by_id(id).one(db).await? {
-            Some(paste) => Ok(paste),
-            None => Err(DbErr::RecordNotFound(String::from("paste not found"))),
+    /// Looks up a paste by id on the given domain, `""` being the instance's own domain.
+    pub async fn get_paste_by_id(
+        db: &DbConn,
+        domain: &str,
+        id: &str,
+    ) -> Result<pastes::Model, DbErr> {
+        let mut paste = Query::get_stored_paste_by_id(db, domain, id).await?;
+        contents::load(db, [&mut paste]).await?;
+        Ok(paste)
+    }
+
+    /// Same as [`Query::get_paste_by_id`], but without loading the content of pastes
+    /// that aren't short links, see [`Query::get_paste_content`].
+    ///
+    /// Generated ids are case sensitive, custom URLs are stored in lowercase, so an id
+    /// that doesn't match as typed is looked up again folded to lowercase.
+    pub async fn get_stored_paste_by_id(
+        db: &DbConn,
+        domain: &str,
+        id: &str,
+    ) -> Result<pastes::Model, DbErr> {
+        if let Some(paste) = Query::find_paste_or_alias(db, domain, id).await? {
+            return Ok(paste);
         }
+        let folded = id.to_lowercase();
+        if folded != id {
+            if let Some(paste) = Query::find_paste_or_alias(db, domain, &folded).await? {
+                return Ok(paste);
+            }
+        }
+        Err(DbErr::RecordNotFound(String::from("paste not found")))
+    }
+
+    async fn find_paste_or_alias(
+        db: &DbConn,
+        domain: &str,
+        id: &str,
+    ) -> Result<Option<pastes::Model>, DbErr> {
+        if let Some(paste) = pastes::Entity::find_by_id((domain.to_owned(), id.to_owned()))
+            .one(db)
+            .await?
+        {
+            return Ok(Some(paste));
+        }
+
+        // the old id of a renamed paste
+        match paste_aliases::Entity::find_by_id((domain.to_owned(), id.to_owned()))
+            .one(db)
+            .await?
+        {
+            Some(alias) => alias.find_related(pastes::Entity).one(db).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The stored body of a paste, `None` for short links.
+    pub async fn get_paste_content(
+        db: &DbConn,
+        paste: &pastes::Model,
+    ) -> Result<Option<paste_contents::Model>, DbErr> {
+        if paste.content_hash.is_none() {
+            return Ok(None);
+        }
+        paste.find_related(paste_contents::Entity).one(db).await
+    }
+
+    /// The stored content of the file of a paste named `filename`, leaving out the first
+    /// file. `None` when the paste has no such file.
+    pub async fn get_paste_file_content(
+        db: &DbConn,
+        paste: &pastes::Model,
+        filename: &str,
+    ) -> Result<Option<paste_contents::Model>, DbErr> {
+        let file = paste
+            .find_related(paste_files::Entity)
+            .filter(paste_files::Column::Filename.eq(filename))
+            .one(db)
+            .await?;
+        match file {
+            Some(file) => file.find_related(paste_contents::Entity).one(db).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Every file of a paste, the first one first.
+    pub async fn get_paste_files(
+        db: &DbConn,
+        paste: &pastes::Model,
+    ) -> Result<Vec<PasteFile>, DbErr> {
+        let mut extra = paste.find_related(paste_files::Entity).all(db).await?;
+        contents::load_files(db, extra.iter_mut()).await?;
+        Ok(files::stored_files(paste, extra))
+    }
+
+    /// Old ids of a paste that still redirect to it.
+    pub async fn get_paste_aliases(
+        db: &DbConn,
+        paste: &pastes::Model,
+    ) -> Result<Vec<paste_aliases::Model>, DbErr> {
+        paste.find_related(paste_aliases::Entity).all(db).await
     }
 
     pub async fn login(db: &DbConn, form: &schema::LoginPost) -> Result<users::Model, DbErr> {
         let user = Query::get_user_by_email(db, &form.email).await?;
 
+        if user.disabled_at.is_some() {
+            return Err(DbErr::RecordNotFound(String::from(
+                "This account has been disabled",
+            )));
+        }
+
         let verified = bcrypt::verify(&form.password, &user.hashed_password)
             .map_err(|_| DbErr::RecordNotFound(String::from("Passwords do not match")))?;
         if !verified {
@@ -27,13 +178,353 @@ impl Query {
 
     pub async fn get_user_by_email(db: &DbConn, email: &str) -> Result<users::Model, DbErr> {
         let user = users::Entity::find()
-            .filter(users::Column::Email.eq(email))
+            .filter(users::Column::Email.eq(utils::normalize_email(email)))
             .one(db)
             .await?;
 
         match user {
             Some(u) => Ok(u),
-            None => Err(DbErr::RecordNotFound(String::from("User not found")).into()),
+            None => Err(DbErr::RecordNotFound(String::from("User not found"))),
         }
     }
+
+    /// Resolves an API token to the user it was issued for. Tokens are stored
+    /// as SHA-256 digests under the `api` context.
+    pub async fn get_user_by_api_token(db: &DbConn, token: &str) -> Result<users::Model, DbErr> {
+        let digest = Sha256::digest(token.as_bytes()).to_vec();
+        let token = users_tokens::Entity::find()
+            .filter(users_tokens::Column::Context.eq("api"))
+            .filter(users_tokens::Column::Token.eq(digest))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("Token not found")))?;
+
+        match token.find_related(users::Entity).one(db).await? {
+            Some(u) => Ok(u),
+            None => Err(DbErr::RecordNotFound(String::from("User not found"))),
+        }
+    }
+
+    /// API tokens issued to a user, newest first.
+    pub async fn get_api_tokens(
+        db: &DbConn,
+        user_id: i64,
+    ) -> Result<Vec<users_tokens::Model>, DbErr> {
+        users_tokens::Entity::find()
+            .filter(users_tokens::Column::UserId.eq(user_id))
+            .filter(users_tokens::Column::Context.eq("api"))
+            .order_by_desc(users_tokens::Column::InsertedAt)
+            .all(db)
+            .await
+    }
+
+    /// Open reports, oldest first, along with the paste they are about.
+    pub async fn get_open_reports(
+        db: &DbConn,
+    ) -> Result<Vec<(reports::Model, Option<pastes::Model>)>, DbErr> {
+        let mut reports = reports::Entity::find()
+            .filter(reports::Column::Status.eq("open"))
+            .order_by_asc(reports::Column::InsertedAt)
+            .find_also_related(pastes::Entity)
+            .all(db)
+            .await?;
+        contents::load(
+            db,
+            reports.iter_mut().filter_map(|(_, paste)| paste.as_mut()),
+        )
+        .await?;
+        Ok(reports)
+    }
+
+    pub async fn get_moderation_log(
+        db: &DbConn,
+        limit: u64,
+    ) -> Result<Vec<(moderation_actions::Model, Option<users::Model>)>, DbErr> {
+        moderation_actions::Entity::find()
+            .order_by_desc(moderation_actions::Column::InsertedAt)
+            .limit(limit)
+            .find_also_related(users::Entity)
+            .all(db)
+            .await
+    }
+
+    pub async fn get_user_by_id(db: &DbConn, id: i64) -> Result<users::Model, DbErr> {
+        match users::Entity::find_by_id(id).one(db).await? {
+            Some(u) => Ok(u),
+            None => Err(DbErr::RecordNotFound(String::from("User not found"))),
+        }
+    }
+
+    /// Users whose email contains `query`, with the total number of pages.
+    pub async fn search_users(
+        db: &DbConn,
+        query: &str,
+        page: u64,
+    ) -> Result<(Vec<users::Model>, u64), DbErr> {
+        let mut select = users::Entity::find().order_by_asc(users::Column::Id);
+        if !query.is_empty() {
+            select = select.filter(users::Column::Email.contains(utils::normalize_email(query)));
+        }
+
+        let paginator = select.paginate(db, ADMIN_PAGE_SIZE);
+        let pages = paginator.num_pages().await?;
+        Ok((paginator.fetch_page(page).await?, pages))
+    }
+
+    /// Pastes whose id or content contains `query`, with the total number of pages.
+    /// Compressed content is only matched by id.
+    pub async fn search_pastes(
+        db: &DbConn,
+        query: &str,
+        page: u64,
+    ) -> Result<(Vec<pastes::Model>, u64), DbErr> {
+        let mut select = pastes::Entity::find().order_by_asc(pastes::Column::Id);
+        if !query.is_empty() {
+            select = select
+                .join(JoinType::LeftJoin, pastes::Relation::PasteContents.def())
+                .filter(
+                    Condition::any()
+                        .add(pastes::Column::Id.contains(query))
+                        .add(pastes::Column::Content.contains(query))
+                        .add(paste_contents::Column::Content.contains(query)),
+                );
+        }
+
+        let paginator = select.paginate(db, ADMIN_PAGE_SIZE);
+        let pages = paginator.num_pages().await?;
+        let mut pastes = paginator.fetch_page(page).await?;
+        contents::load(db, pastes.iter_mut()).await?;
+        Ok((pastes, pages))
+    }
+
+    pub async fn get_instance_stats(db: &DbConn) -> Result<InstanceStats, DbErr> {
+        let pastes = pastes::Entity::find()
+            .filter(pastes::Column::IsUrl.eq(false))
+            .count(db)
+            .await?;
+        let urls = pastes::Entity::find()
+            .filter(pastes::Column::IsUrl.eq(true))
+            .count(db)
+            .await?;
+        let users = users::Entity::find().count(db).await?;
+        // short links, everything else is stored once in paste_contents
+        let link_storage = pastes::Entity::find()
+            .select_only()
+            .column_as(
+                Expr::cust("CAST(COALESCE(SUM(LENGTH(content)), 0) AS BIGINT)"),
+                "storage",
+            )
+            .into_tuple::<i64>()
+            .one(db)
+            .await?
+            .unwrap_or_default();
+        let content_storage = paste_contents::Entity::find()
+            .select_only()
+            .column_as(
+                Expr::cust(
+                    "CAST(COALESCE(SUM(LENGTH(content) + COALESCE(LENGTH(content_compressed), 0)), 0) AS BIGINT)",
+                ),
+                "storage",
+            )
+            .into_tuple::<i64>()
+            .one(db)
+            .await?
+            .unwrap_or_default();
+        Ok(InstanceStats {
+            pastes,
+            urls,
+            users,
+            storage: link_storage + content_storage,
+        })
+    }
+
+    pub async fn get_filter_rules(
+        db: &DbConn,
+        enabled_only: bool,
+    ) -> Result<Vec<filter_rules::Model>, DbErr> {
+        let mut select = filter_rules::Entity::find().order_by_asc(filter_rules::Column::Id);
+        if enabled_only {
+            select = select.filter(filter_rules::Column::Enabled.eq(true));
+        }
+        select.all(db).await
+    }
+
+    /// Click statistics for a short link, with daily counts over the last `days` days.
+    pub async fn get_click_stats(
+        db: &DbConn,
+        paste: &pastes::Model,
+        days: u64,
+    ) -> Result<ClickStats, DbErr> {
+        let total = clicks::Entity::find()
+            .filter(clicks::Column::PasteDomain.eq(&paste.domain))
+            .filter(clicks::Column::PasteId.eq(&paste.id))
+            .count(db)
+            .await?;
+
+        let today = Utc::now().date_naive();
+        let first_day = today - Days::new(days.saturating_sub(1));
+        let counts: Vec<(NaiveDate, i64)> = clicks::Entity::find()
+            .select_only()
+            .column_as(Expr::cust("DATE(clicked_at)"), "day")
+            .column_as(Expr::cust("COUNT(*)"), "clicks")
+            .filter(clicks::Column::PasteDomain.eq(&paste.domain))
+            .filter(clicks::Column::PasteId.eq(&paste.id))
+            .filter(clicks::Column::ClickedAt.gte(first_day.and_hms_opt(0, 0, 0).unwrap()))
+            .group_by(Expr::cust("DATE(clicked_at)"))
+            .into_tuple()
+            .all(db)
+            .await?;
+        let daily = first_day
+            .iter_days()
+            .take_while(|day| *day <= today)
+            .map(|day| DailyClicks {
+                day,
+                clicks: counts
+                    .iter()
+                    .find(|(d, _)| *d == day)
+                    .map(|(_, n)| *n)
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(ClickStats {
+            total,
+            daily,
+            referrers: Query::get_top_click_sources(db, paste, clicks::Column::ReferrerHost)
+                .await?,
+            user_agents: Query::get_top_click_sources(db, paste, clicks::Column::UserAgent).await?,
+            countries: Query::get_top_click_sources(db, paste, clicks::Column::Country).await?,
+        })
+    }
+
+    async fn get_top_click_sources(
+        db: &DbConn,
+        paste: &pastes::Model,
+        column: clicks::Column,
+    ) -> Result<Vec<ClickSource>, DbErr> {
+        let rows: Vec<(Option<String>, i64)> = clicks::Entity::find()
+            .select_only()
+            .column(column)
+            .column_as(Expr::cust("COUNT(*)"), "clicks")
+            .filter(clicks::Column::PasteDomain.eq(&paste.domain))
+            .filter(clicks::Column::PasteId.eq(&paste.id))
+            .group_by(column)
+            .order_by_desc(Expr::cust("COUNT(*)"))
+            .limit(TOP_CLICK_SOURCES)
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(label, clicks)| ClickSource {
+                label: label.unwrap_or_else(|| String::from("Unknown")),
+                clicks,
+            })
+            .collect())
+    }
+
+    /// A verified custom domain by host name.
+    pub async fn get_verified_domain(
+        db: &DbConn,
+        host: &str,
+    ) -> Result<Option<domains::Model>, DbErr> {
+        domains::Entity::find()
+            .filter(domains::Column::Host.eq(host.to_lowercase()))
+            .filter(domains::Column::VerifiedAt.is_not_null())
+            .one(db)
+            .await
+    }
+
+    pub async fn get_user_domains(db: &DbConn, user_id: i64) -> Result<Vec<domains::Model>, DbErr> {
+        domains::Entity::find()
+            .filter(domains::Column::UserId.eq(user_id))
+            .order_by_asc(domains::Column::Host)
+            .all(db)
+            .await
+    }
+
+    /// Short links to web pages due for a health check, never checked ones first.
+    pub async fn get_links_to_check(
+        db: &DbConn,
+        checked_before: NaiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<pastes::Model>, DbErr> {
+        pastes::Entity::find()
+            .filter(pastes::Column::IsUrl.eq(true))
+            .filter(pastes::Column::HiddenAt.is_null())
+            // other schemes can't be checked
+            .filter(
+                Condition::any()
+                    .add(pastes::Column::Content.starts_with("http://"))
+                    .add(pastes::Column::Content.starts_with("https://")),
+            )
+            .filter(
+                Condition::any()
+                    .add(pastes::Column::CheckedAt.is_null())
+                    .add(pastes::Column::CheckedAt.lt(checked_before)),
+            )
+            .order_by(
+                Expr::col(pastes::Column::CheckedAt).is_not_null(),
+                Order::Asc,
+            )
+            .order_by_asc(pastes::Column::CheckedAt)
+            .limit(limit)
+            .all(db)
+            .await
+    }
+
+    /// Most recent health checks of a short link, newest first.
+    pub async fn get_link_checks<C: ConnectionTrait>(
+        db: &C,
+        paste: &pastes::Model,
+        limit: u64,
+    ) -> Result<Vec<link_checks::Model>, DbErr> {
+        link_checks::Entity::find()
+            .filter(link_checks::Column::PasteDomain.eq(&paste.domain))
+            .filter(link_checks::Column::PasteId.eq(&paste.id))
+            .order_by_desc(link_checks::Column::CheckedAt)
+            .limit(limit)
+            .all(db)
+            .await
+    }
+
+    /// Files attached to a paste, in upload order.
+    pub async fn get_paste_attachments(
+        db: &DbConn,
+        paste: &pastes::Model,
+    ) -> Result<Vec<attachments::Model>, DbErr> {
+        paste
+            .find_related(attachments::Entity)
+            .order_by_asc(attachments::Column::Id)
+            .all(db)
+            .await
+    }
+
+    pub async fn get_paste_attachment(
+        db: &DbConn,
+        paste: &pastes::Model,
+        attachment_id: i64,
+    ) -> Result<attachments::Model, DbErr> {
+        paste
+            .find_related(attachments::Entity)
+            .filter(attachments::Column::Id.eq(attachment_id))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from("attachment not found")))
+    }
+
+    /// Attachments that were removed or whose paste was deleted, their files still
+    /// have to be deleted from storage.
+    pub async fn get_orphaned_attachments(
+        db: &DbConn,
+        limit: u64,
+    ) -> Result<Vec<attachments::Model>, DbErr> {
+        attachments::Entity::find()
+            .filter(attachments::Column::PasteId.is_null())
+            .order_by_asc(attachments::Column::Id)
+            .limit(limit)
+            .all(db)
+            .await
+    }
 }
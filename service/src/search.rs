@@ -0,0 +1,702 @@
+use std::collections::HashMap;
+
+use chrono::{Days, NaiveDate, NaiveTime};
+use entity::users::Role;
+use entity::{paste_files, pastes, schema, users};
+use sea_orm::sea_query::{Condition, Expr, SelectStatement};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbBackend, DbConn, DbErr, EntityTrait, Order, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+use serde::Serialize;
+
+use crate::files::{self, PasteFile};
+#[cfg(feature = "tantivy")]
+use crate::search_index;
+use crate::{contents, Mutation, Query};
+
+/// Results per page of a full-text search.
+pub const SEARCH_PAGE_SIZE: u64 = 20;
+/// Bytes of a paste's content that are indexed, a tsvector can't grow past 1 MB.
+const INDEXED_BYTES: usize = 256 * 1024;
+/// Pastes taken from the embedded index for a query, before filtering.
+#[cfg(feature = "tantivy")]
+const EMBEDDED_RESULTS: usize = 1000;
+/// Pastes indexed at a time by [`Mutation::rebuild_search_index`].
+const REBUILD_BATCH_SIZE: u64 = 100;
+/// Parts of the content shown for a result, and characters around each match.
+const SNIPPET_FRAGMENTS: usize = 2;
+const SNIPPET_CONTEXT: usize = 60;
+
+/// A paste found by [`Query::search_user_pastes`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub paste: pastes::Model,
+    /// Where the query matched, HTML escaped with the matching words in `<mark>`.
+    pub snippet: String,
+}
+
+/// Indexes a paste in its search vector, in the transaction that saves it. With the
+/// embedded index open nothing is done here, the paste is added by [`add_to_index`]
+/// once it's committed. `names` are its file names and `text` the content of all its
+/// files, short links aren't indexed.
+///
+/// File names are also indexed in parts, Postgres would otherwise keep `notes.md` as a
+/// single word. The `simple` configuration only lowercases words, which suits code
+/// better than stemming for one particular language.
+pub(crate) async fn index_paste<C: ConnectionTrait>(
+    db: &C,
+    paste: &pastes::Model,
+    names: &str,
+    text: &str,
+) -> Result<(), DbErr> {
+    #[cfg(feature = "tantivy")]
+    if search_index::search_index().is_some() {
+        return Ok(());
+    }
+    if !has_search_vector(db) {
+        return Ok(());
+    }
+
+    let (names, text) = match paste.is_url {
+        true => (None, None),
+        false => (
+            Some(names.to_owned()),
+            Some(truncate(text, INDEXED_BYTES).to_owned()),
+        ),
+    };
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        "UPDATE pastes SET search_vector = setweight(to_tsvector('simple',
+                concat_ws(' ', $1, regexp_replace($1, '[^[:alnum:]]+', ' ', 'g'))), 'A')
+            || setweight(to_tsvector('simple', $2), 'B')
+        WHERE domain = $3 AND id = $4",
+        [
+            names.into(),
+            text.into(),
+            paste.domain.clone().into(),
+            paste.id.clone().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Adds a committed paste to the embedded index when one is open, so it never finds a
+/// paste that was rolled back. Takes the same arguments as [`index_paste`].
+#[cfg_attr(not(feature = "tantivy"), allow(unused_variables))]
+pub(crate) async fn add_to_index(paste: &pastes::Model, names: &str, text: &str) {
+    #[cfg(feature = "tantivy")]
+    if let Some(index) = search_index::search_index() {
+        let paste = paste.clone();
+        let (names, text) = (names.to_owned(), truncate(text, INDEXED_BYTES).to_owned());
+        write_index(move || {
+            index.add(&paste, &names, &text)?;
+            index.commit()
+        })
+        .await;
+    }
+}
+
+/// SQLite databases have no search vector, their pastes can only be found through the
+/// embedded index.
+fn has_search_vector<C: ConnectionTrait>(db: &C) -> bool {
+    db.get_database_backend() != DbBackend::Sqlite
+}
+
+/// Drops a deleted paste from the embedded index, a search vector goes with its row.
+#[cfg_attr(not(feature = "tantivy"), allow(unused_variables))]
+pub(crate) async fn remove_paste(domain: &str, id: &str) {
+    #[cfg(feature = "tantivy")]
+    if let Some(index) = search_index::search_index() {
+        let (domain, id) = (domain.to_owned(), id.to_owned());
+        write_index(move || {
+            index.remove(&domain, &id);
+            index.commit()
+        })
+        .await;
+    }
+}
+
+/// Drops the pastes of a deleted custom domain from the embedded index.
+#[cfg_attr(not(feature = "tantivy"), allow(unused_variables))]
+pub(crate) async fn remove_domain(domain: &str) {
+    #[cfg(feature = "tantivy")]
+    if let Some(index) = search_index::search_index() {
+        let domain = domain.to_owned();
+        write_index(move || {
+            index.remove_domain(&domain);
+            index.commit()
+        })
+        .await;
+    }
+}
+
+/// Moves a renamed paste to its new id in the embedded index, a search vector is kept
+/// by its row.
+#[cfg_attr(not(feature = "tantivy"), allow(unused_variables))]
+pub(crate) async fn rename_paste(db: &DbConn, old_id: &str, paste: &pastes::Model) {
+    #[cfg(feature = "tantivy")]
+    if let Some(index) = search_index::search_index() {
+        let mut extra = match extra_files(db, std::slice::from_ref(paste)).await {
+            Ok(extra) => extra,
+            Err(e) => {
+                tracing::warn!(
+                    "Not moving renamed paste {} in the search index: {}",
+                    paste.id,
+                    e
+                );
+                return;
+            }
+        };
+        let (names, text) = document(paste, extra.remove(&key(paste)).unwrap_or_default());
+        let text = truncate(&text, INDEXED_BYTES).to_owned();
+        let (old_id, paste) = (old_id.to_owned(), paste.clone());
+        write_index(move || {
+            index.remove(&paste.domain, &old_id);
+            index.add(&paste, &names, &text)?;
+            index.commit()
+        })
+        .await;
+    }
+}
+
+/// Writes to the embedded index on a blocking thread, a commit syncs its files to disk
+/// and would otherwise hold up the other requests on the same worker.
+#[cfg(feature = "tantivy")]
+async fn blocking(write: impl FnOnce() -> Result<(), DbErr> + Send + 'static) -> Result<(), DbErr> {
+    tokio::task::spawn_blocking(write)
+        .await
+        .map_err(|e| DbErr::Custom(format!("search index: {}", e)))?
+}
+
+/// Like [`blocking`] for changes the database already has. Failing them would report an
+/// error for a paste that was saved, so they're only logged and the index catches up
+/// with `katbin rebuild-search-index`.
+#[cfg(feature = "tantivy")]
+async fn write_index(write: impl FnOnce() -> Result<(), DbErr> + Send + 'static) {
+    if let Err(e) = blocking(write).await {
+        tracing::warn!("Updating the search index failed: {}", e);
+    }
+}
+
+/// The file names of a paste as they are indexed.
+pub(crate) fn file_names(first: &PasteFile, files: &[PasteFile]) -> String {
+    std::iter::once(first)
+        .chain(files)
+        .filter_map(|f| f.filename.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The file names and text of a stored paste, with its content loaded.
+fn document(paste: &pastes::Model, extra: Vec<paste_files::Model>) -> (String, String) {
+    let files = files::stored_files(paste, extra);
+    let names = files
+        .iter()
+        .filter_map(|f| f.filename.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let text = files
+        .into_iter()
+        .map(|f| f.content)
+        .collect::<Vec<_>>()
+        .join("\n");
+    (names, text)
+}
+
+fn key(paste: &pastes::Model) -> (String, String) {
+    (paste.domain.clone(), paste.id.clone())
+}
+
+/// The second and later files of `pastes` with their content loaded, by paste.
+async fn extra_files<C: ConnectionTrait>(
+    db: &C,
+    pastes: &[pastes::Model],
+) -> Result<HashMap<(String, String), Vec<paste_files::Model>>, DbErr> {
+    let keys = pastes.iter().fold(Condition::any(), |condition, paste| {
+        condition.add(
+            Condition::all()
+                .add(paste_files::Column::PasteDomain.eq(&paste.domain))
+                .add(paste_files::Column::PasteId.eq(&paste.id)),
+        )
+    });
+    let mut extra: HashMap<(String, String), Vec<paste_files::Model>> = HashMap::new();
+    let mut files = paste_files::Entity::find().filter(keys).all(db).await?;
+    contents::load_files(db, files.iter_mut()).await?;
+    for file in files {
+        extra
+            .entry((file.paste_domain.clone(), file.paste_id.clone()))
+            .or_default()
+            .push(file);
+    }
+    Ok(extra)
+}
+
+/// Results with their snippets, loading the content of `pastes`.
+async fn search_hits(
+    db: &DbConn,
+    mut pastes: Vec<pastes::Model>,
+    query: &str,
+) -> Result<Vec<SearchHit>, DbErr> {
+    if pastes.is_empty() {
+        return Ok(Vec::new());
+    }
+    contents::load(db, pastes.iter_mut()).await?;
+    let mut extra = extra_files(db, &pastes).await?;
+
+    let terms = query_terms(query);
+    Ok(pastes
+        .into_iter()
+        .map(|paste| {
+            let (_, text) = document(&paste, extra.remove(&key(&paste)).unwrap_or_default());
+            SearchHit {
+                snippet: snippet(truncate(&text, INDEXED_BYTES), &terms),
+                paste,
+            }
+        })
+        .collect())
+}
+
+impl Query {
+    /// Full-text search over the content and file names of a user's pastes, or of all
+    /// pastes for admins, best matches first. Without a query the newest pastes matching
+    /// the filters are listed. Returns the total number of pages along with the results.
+    pub async fn search_user_pastes(
+        db: &DbConn,
+        user: &users::Model,
+        params: &schema::PasteSearchParams,
+    ) -> Result<(Vec<SearchHit>, u64), DbErr> {
+        let query = params.q.as_deref().unwrap_or_default().trim();
+        let page = params.page.unwrap_or_default();
+        let mut select = pastes::Entity::find().filter(pastes::Column::IsUrl.eq(false));
+        if user.role != Role::Admin {
+            select = select
+                .filter(pastes::Column::BelongsTo.eq(user.id))
+                .filter(pastes::Column::HiddenAt.is_null());
+        }
+
+        if let Some(language) = non_empty(params.language.as_deref()) {
+            let language = language.to_lowercase();
+            select = select.filter(
+                Condition::any()
+                    .add(pastes::Column::Language.eq(&language))
+                    .add(Expr::exists(
+                        SelectStatement::new()
+                            .expr(Expr::val(1))
+                            .from(paste_files::Entity)
+                            .and_where(
+                                Expr::col((paste_files::Entity, paste_files::Column::PasteDomain))
+                                    .equals((pastes::Entity, pastes::Column::Domain)),
+                            )
+                            .and_where(
+                                Expr::col((paste_files::Entity, paste_files::Column::PasteId))
+                                    .equals((pastes::Entity, pastes::Column::Id)),
+                            )
+                            .and_where(
+                                Expr::col((paste_files::Entity, paste_files::Column::Language))
+                                    .eq(&language),
+                            )
+                            .to_owned(),
+                    )),
+            );
+        }
+        if let Some(from) = parse_date("from", params.from.as_deref())? {
+            select = select.filter(pastes::Column::InsertedAt.gte(from.and_time(NaiveTime::MIN)));
+        }
+        if let Some(to) = parse_date("to", params.to.as_deref())? {
+            let end = to.checked_add_days(Days::new(1)).unwrap_or(to);
+            select = select.filter(pastes::Column::InsertedAt.lt(end.and_time(NaiveTime::MIN)));
+        }
+
+        if !query.is_empty() {
+            #[cfg(feature = "tantivy")]
+            if let Some(index) = search_index::search_index() {
+                let owner = (user.role != Role::Admin).then_some(user.id);
+                let found = index.search(query, owner, EMBEDDED_RESULTS)?;
+                return Query::search_embedded(db, select, found, page, query).await;
+            }
+            if !has_search_vector(db) {
+                return Err(DbErr::Custom(String::from(
+                    "Full-text search isn't available on this instance.",
+                )));
+            }
+
+            select = select
+                .filter(Expr::cust_with_values(
+                    "pastes.search_vector @@ websearch_to_tsquery('simple', $1)",
+                    [query],
+                ))
+                .order_by(
+                    Expr::cust_with_values(
+                        "ts_rank(pastes.search_vector, websearch_to_tsquery('simple', $1))",
+                        [query],
+                    ),
+                    Order::Desc,
+                );
+        }
+        // pastes without a creation date are the oldest
+        let select = select
+            .order_by_asc(pastes::Column::InsertedAt.is_null())
+            .order_by_desc(pastes::Column::InsertedAt)
+            .order_by_asc(pastes::Column::Id);
+
+        let paginator = select.paginate(db, SEARCH_PAGE_SIZE);
+        let pages = paginator.num_pages().await?;
+        let pastes = paginator.fetch_page(page).await?;
+        Ok((search_hits(db, pastes, query).await?, pages))
+    }
+
+    /// Loads what the embedded index found through the remaining filters, keeping its
+    /// ranking. Pages are cut here as the database doesn't know the ranking.
+    #[cfg(feature = "tantivy")]
+    async fn search_embedded(
+        db: &DbConn,
+        select: sea_orm::Select<pastes::Entity>,
+        found: Vec<(String, String)>,
+        page: u64,
+        query: &str,
+    ) -> Result<(Vec<SearchHit>, u64), DbErr> {
+        if found.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+        let keys = found
+            .iter()
+            .fold(Condition::any(), |condition, (domain, id)| {
+                condition.add(
+                    Condition::all()
+                        .add(pastes::Column::Domain.eq(domain))
+                        .add(pastes::Column::Id.eq(id)),
+                )
+            });
+        let mut pastes = select.filter(keys).all(db).await?;
+        pastes.sort_by_key(|paste| found.iter().position(|found| *found == key(paste)));
+
+        let pages = (pastes.len() as u64).div_ceil(SEARCH_PAGE_SIZE);
+        let pastes = pastes
+            .into_iter()
+            .skip((page * SEARCH_PAGE_SIZE) as usize)
+            .take(SEARCH_PAGE_SIZE as usize)
+            .collect();
+        Ok((search_hits(db, pastes, query).await?, pages))
+    }
+}
+
+impl Mutation {
+    /// Indexes every paste again, in the embedded index when one is open and in the
+    /// search vectors otherwise. Returns how many pastes were indexed.
+    pub async fn rebuild_search_index(db: &DbConn) -> Result<u64, DbErr> {
+        #[cfg(feature = "tantivy")]
+        let index = search_index::search_index();
+        #[cfg(feature = "tantivy")]
+        if let Some(index) = index {
+            index.clear()?;
+        }
+        #[cfg(feature = "tantivy")]
+        let embedded = index.is_some();
+        #[cfg(not(feature = "tantivy"))]
+        let embedded = false;
+        if !embedded && !has_search_vector(db) {
+            return Err(DbErr::Custom(String::from(
+                "SQLite databases can only be searched with the embedded index, set SEARCH_INDEX_PATH",
+            )));
+        }
+
+        let mut count = 0;
+        let mut after: Option<(String, String)> = None;
+        loop {
+            let mut select = pastes::Entity::find().filter(pastes::Column::IsUrl.eq(false));
+            if let Some((domain, id)) = &after {
+                select = select.filter(
+                    Condition::any().add(pastes::Column::Domain.gt(domain)).add(
+                        Condition::all()
+                            .add(pastes::Column::Domain.eq(domain))
+                            .add(pastes::Column::Id.gt(id)),
+                    ),
+                );
+            }
+            let mut pastes = select
+                .order_by_asc(pastes::Column::Domain)
+                .order_by_asc(pastes::Column::Id)
+                .limit(REBUILD_BATCH_SIZE)
+                .all(db)
+                .await?;
+            let Some(last) = pastes.last() else {
+                break;
+            };
+            after = Some(key(last));
+
+            contents::load(db, pastes.iter_mut()).await?;
+            let mut extra = extra_files(db, &pastes).await?;
+            for paste in &pastes {
+                let (names, text) = document(paste, extra.remove(&key(paste)).unwrap_or_default());
+                #[cfg(feature = "tantivy")]
+                if let Some(index) = index {
+                    index.add(paste, &names, truncate(&text, INDEXED_BYTES))?;
+                    count += 1;
+                    continue;
+                }
+                index_paste(db, paste, &names, &text).await?;
+                count += 1;
+            }
+        }
+
+        #[cfg(feature = "tantivy")]
+        if let Some(index) = index {
+            blocking(|| index.commit()).await?;
+        }
+        Ok(count)
+    }
+}
+
+fn parse_date(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>, DbErr> {
+    non_empty(value)
+        .map(|value| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| DbErr::Custom(format!("{} must be a date like 2024-01-31.", name)))
+        })
+        .transpose()
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// The words of a query that are highlighted, leaving out excluded words and `or`.
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .filter(|word| !word.starts_with('-') && !word.eq_ignore_ascii_case("or"))
+        .flat_map(words)
+        .map(|(_, word)| word.to_lowercase())
+        .collect()
+}
+
+/// Runs of letters and digits with their offsets, close to how Postgres splits text
+/// into words.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &text[s..]));
+    }
+    words
+}
+
+/// Up to [`SNIPPET_FRAGMENTS`] pieces of `text` around words of the query. Pastes that
+/// only matched on a file name, or were listed without a query, show their beginning.
+fn snippet(text: &str, terms: &[String]) -> String {
+    let matches: Vec<(usize, usize)> = words(text)
+        .into_iter()
+        .filter(|(_, word)| terms.contains(&word.to_lowercase()))
+        .map(|(start, word)| (start, start + word.len()))
+        .collect();
+
+    let mut fragments: Vec<(usize, usize)> = Vec::new();
+    for &(start, end) in &matches {
+        if fragments
+            .last()
+            .is_some_and(|&(_, last_end)| start < last_end)
+        {
+            continue;
+        }
+        if fragments.len() == SNIPPET_FRAGMENTS {
+            break;
+        }
+        // don't cut words in half where there's whitespace to stop at
+        let mut fragment_start = chars_before(text, start, SNIPPET_CONTEXT);
+        if fragment_start > 0 {
+            if let Some((i, c)) = text[fragment_start..start]
+                .char_indices()
+                .find(|(_, c)| c.is_whitespace())
+            {
+                fragment_start += i + c.len_utf8();
+            }
+        }
+        let mut fragment_end = chars_after(text, end, SNIPPET_CONTEXT);
+        if fragment_end < text.len() {
+            if let Some(i) = text[end..fragment_end].rfind(char::is_whitespace) {
+                fragment_end = end + i;
+            }
+        }
+        fragments.push((fragment_start, fragment_end));
+    }
+    if fragments.is_empty() {
+        fragments.push((0, chars_after(text, 0, 2 * SNIPPET_CONTEXT)));
+    }
+
+    let mut snippet = String::new();
+    for (i, &(start, end)) in fragments.iter().enumerate() {
+        if i > 0 || start > 0 {
+            snippet.push_str(" … ");
+        }
+        let mut position = start;
+        for &(match_start, match_end) in &matches {
+            if match_start < position || match_end > end {
+                continue;
+            }
+            snippet.push_str(&escape_html(&text[position..match_start]));
+            snippet.push_str("<mark>");
+            snippet.push_str(&escape_html(&text[match_start..match_end]));
+            snippet.push_str("</mark>");
+            position = match_end;
+        }
+        snippet.push_str(&escape_html(&text[position..end]));
+    }
+    if fragments.last().is_some_and(|&(_, end)| end < text.len()) {
+        snippet.push_str(" …");
+    }
+    snippet.trim().to_owned()
+}
+
+fn chars_before(text: &str, position: usize, count: usize) -> usize {
+    text[..position]
+        .char_indices()
+        .rev()
+        .nth(count.saturating_sub(1))
+        .map_or(0, |(i, _)| i)
+}
+
+fn chars_after(text: &str, position: usize, count: usize) -> usize {
+    text[position..]
+        .char_indices()
+        .nth(count)
+        .map_or(text.len(), |(i, _)| position + i)
+}
+
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_terms_leave_out_exclusions() {
+        assert_eq!(
+            query_terms("Needle -hay OR thread or"),
+            ["needle", "thread"]
+        );
+        assert_eq!(
+            query_terms("notes.md \"Exact Phrase\""),
+            ["notes", "md", "exact", "phrase"]
+        );
+        assert!(query_terms("-only -excluded").is_empty());
+    }
+
+    #[test]
+    fn words_have_byte_offsets() {
+        assert_eq!(
+            words("héllo, wörld_x 日本語"),
+            [(0, "héllo"), (8, "wörld"), (15, "x"), (17, "日本語")]
+        );
+        assert!(words("  ... ").is_empty());
+    }
+
+    #[test]
+    fn snippets_mark_the_query() {
+        assert_eq!(
+            snippet("a needle in a haystack", &query_terms("needle")),
+            "a <mark>needle</mark> in a haystack"
+        );
+        assert_eq!(
+            snippet("NEEDLE and Needle", &query_terms("needle")),
+            "<mark>NEEDLE</mark> and <mark>Needle</mark>"
+        );
+        // excluded words and `or` only narrow the search
+        assert_eq!(
+            snippet("hay or needle", &query_terms("needle or -hay")),
+            "hay or <mark>needle</mark>"
+        );
+    }
+
+    #[test]
+    fn snippets_escape_html() {
+        assert_eq!(
+            snippet("<b>needle</b> & 'quotes\"", &query_terms("needle")),
+            "&lt;b&gt;<mark>needle</mark>&lt;/b&gt; &amp; &#x27;quotes&quot;"
+        );
+        assert_eq!(
+            snippet("<script>alert(1)</script>", &query_terms("script")),
+            "&lt;<mark>script</mark>&gt;alert(1)&lt;/<mark>script</mark>&gt;"
+        );
+    }
+
+    #[test]
+    fn snippets_cut_around_matches() {
+        let text = format!("{} needle {}", "word ".repeat(40), "word ".repeat(40));
+        let snippet = snippet(&text, &query_terms("needle"));
+        assert!(snippet.starts_with("… word"), "{}", snippet);
+        assert!(snippet.ends_with("word …"), "{}", snippet);
+        assert!(snippet.contains(" <mark>needle</mark> "));
+
+        // without a match the beginning is shown
+        let beginning = super::snippet(&text, &[]);
+        assert!(beginning.starts_with("word word"), "{}", beginning);
+        assert!(beginning.ends_with(" …"), "{}", beginning);
+        assert_eq!(beginning.chars().count(), 2 * SNIPPET_CONTEXT + 2);
+    }
+
+    #[test]
+    fn snippets_cut_multibyte_text() {
+        for n in 0..2 * SNIPPET_CONTEXT {
+            let text = format!("{} needle {}", "é".repeat(n), "😀".repeat(n));
+            let snippet = snippet(&text, &query_terms("needle"));
+            assert!(snippet.contains("<mark>needle</mark>"), "{}", snippet);
+        }
+
+        // without whitespace to stop at, fragments end mid-word on a character boundary
+        let text = format!("{}→needle←{}", "ü".repeat(100), "ö".repeat(100));
+        assert_eq!(
+            snippet(&text, &query_terms("needle")),
+            format!(
+                "… {}→<mark>needle</mark>←{} …",
+                "ü".repeat(59),
+                "ö".repeat(59)
+            )
+        );
+    }
+
+    #[test]
+    fn truncate_keeps_whole_characters() {
+        assert_eq!(truncate("abc", 3), "abc");
+        assert_eq!(truncate("abc", 2), "ab");
+        assert_eq!(truncate("aé", 2), "a");
+        assert_eq!(truncate("😀😀", 7), "😀");
+        assert_eq!(truncate("😀", 3), "");
+        assert_eq!(truncate("", 0), "");
+    }
+}
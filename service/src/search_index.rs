@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use entity::pastes;
+use sea_orm::{DbErr, RuntimeErr};
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, INDEXED, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, TantivyDocument, Term};
+
+static SEARCH_INDEX: OnceLock<SearchIndex> = OnceLock::new();
+
+/// Memory the index writer buffers documents in before writing a segment.
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// A full-text index of pastes kept on disk next to the application, used instead of
+/// Postgres' text search when it's opened. It only finds pastes, they're still loaded
+/// and filtered from the database.
+pub(crate) struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    key: Field,
+    domain: Field,
+    id: Field,
+    owner: Field,
+    names: Field,
+    content: Field,
+}
+
+/// Opens the embedded search index in `path`, creating it when it doesn't exist yet.
+/// New and edited pastes are indexed there from then on, existing ones are added with
+/// `katbin rebuild-search-index`.
+pub fn open_search_index(path: &Path) -> tantivy::Result<()> {
+    std::fs::create_dir_all(path)?;
+
+    let mut schema = Schema::builder();
+    let key = schema.add_text_field("key", STRING);
+    let domain = schema.add_text_field("domain", STRING | STORED);
+    let id = schema.add_text_field("id", STORED);
+    let owner = schema.add_i64_field("owner", INDEXED);
+    let names = schema.add_text_field("names", TEXT);
+    let content = schema.add_text_field("content", TEXT);
+
+    let index = Index::open_or_create(MmapDirectory::open(path)?, schema.build())?;
+    let reader = index.reader()?;
+    let writer = Mutex::new(index.writer(WRITER_MEMORY)?);
+    let _ = SEARCH_INDEX.set(SearchIndex {
+        index,
+        reader,
+        writer,
+        key,
+        domain,
+        id,
+        owner,
+        names,
+        content,
+    });
+    Ok(())
+}
+
+pub(crate) fn search_index() -> Option<&'static SearchIndex> {
+    SEARCH_INDEX.get()
+}
+
+impl SearchIndex {
+    /// Indexes a paste in place of what was indexed for it before, short links are only
+    /// removed. Searches see it after the next [`SearchIndex::commit`].
+    pub(crate) fn add(&self, paste: &pastes::Model, names: &str, text: &str) -> Result<(), DbErr> {
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(self.key_term(&paste.domain, &paste.id));
+        if paste.is_url {
+            return Ok(());
+        }
+
+        let mut document = doc!(
+            self.key => key(&paste.domain, &paste.id),
+            self.domain => paste.domain.as_str(),
+            self.id => paste.id.as_str(),
+            self.names => names,
+            self.content => text,
+        );
+        if let Some(owner) = paste.belongs_to {
+            document.add_i64(self.owner, owner);
+        }
+        writer.add_document(document).map_err(index_error)?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, domain: &str, id: &str) {
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(self.key_term(domain, id));
+    }
+
+    pub(crate) fn remove_domain(&self, domain: &str) {
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.domain, domain));
+    }
+
+    pub(crate) fn clear(&self) -> Result<(), DbErr> {
+        let writer = self.writer.lock().unwrap();
+        writer.delete_all_documents().map_err(index_error)?;
+        Ok(())
+    }
+
+    /// Writes the changes so far to disk and makes them visible to searches.
+    pub(crate) fn commit(&self) -> Result<(), DbErr> {
+        self.writer.lock().unwrap().commit().map_err(index_error)?;
+        self.reader.reload().map_err(index_error)
+    }
+
+    /// The domain and id of up to `limit` pastes matching `query`, best matches first.
+    /// Only pastes of `owner` are searched when one is given.
+    ///
+    /// All words have to match like with Postgres' `websearch_to_tsquery`, words in
+    /// file names count twice.
+    pub(crate) fn search(
+        &self,
+        query: &str,
+        owner: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, DbErr> {
+        let mut parser = QueryParser::for_index(&self.index, vec![self.names, self.content]);
+        parser.set_conjunction_by_default();
+        parser.set_field_boost(self.names, 2.0);
+        let (query, _) = parser.parse_query_lenient(query);
+        let query: Box<dyn Query> = match owner {
+            Some(owner) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, query),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_i64(self.owner, owner),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ])),
+            None => query,
+        };
+
+        let searcher = self.reader.searcher();
+        searcher
+            .search(&query, &TopDocs::with_limit(limit))
+            .map_err(index_error)?
+            .into_iter()
+            .map(|(_, address)| {
+                let document: TantivyDocument = searcher.doc(address).map_err(index_error)?;
+                let field = |field| {
+                    document
+                        .get_first(field)
+                        .and_then(|value| value.as_str())
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                Ok((field(self.domain), field(self.id)))
+            })
+            .collect()
+    }
+
+    fn key_term(&self, domain: &str, id: &str) -> Term {
+        Term::from_field_text(self.key, &key(domain, id))
+    }
+}
+
+/// Identifies a paste in the index, ids never contain a slash.
+fn key(domain: &str, id: &str) -> String {
+    format!("{}/{}", domain, id)
+}
+
+fn index_error(e: tantivy::TantivyError) -> DbErr {
+    DbErr::Query(RuntimeErr::Internal(format!("search index: {}", e)))
+}
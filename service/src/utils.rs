@@ -1,36 +1,41 @@
-use rand::{prelude::SliceRandom, Rng};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::{fs, io};
+
+use rand::{distributions::Alphanumeric, Rng};
 use url::Url;
 
-fn rand_vowel() -> char {
-    let vowels = ['a', 'e', 'i', 'o', 'u'];
-    let mut rng = rand::thread_rng();
-    *vowels.choose(&mut rng).expect("failed to get a vowel") // this should never panic unless the slice is empty
-}
+static BLOCKED_DOMAINS: OnceLock<HashSet<String>> = OnceLock::new();
+static INSTANCE_HOSTS: OnceLock<Vec<String>> = OnceLock::new();
 
-fn rand_consonant() -> char {
-    let consonants = [
-        'b', 'c', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm', 'n', 'p', 'q', 'r', 's', 't', 'v', 'w',
-        'x', 'y', 'z',
-    ];
-    let mut rng = rand::thread_rng();
-    *consonants
-        .choose(&mut rng)
-        .expect("failed to get a consonant") // this should never panic unless the slice is empty
+/// Random alphanumeric string, e.g. for API and verification tokens.
+pub(crate) fn generate_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
 }
 
-pub(crate) fn generate_key(length: usize) -> String {
-    let mut key = String::with_capacity(length);
-    let random: bool = rand::thread_rng().gen();
-
-    for i in 0..length {
-        if i % 2 == (random as usize) {
-            key.push(rand_consonant());
-        } else {
-            key.push(rand_vowel());
-        }
+/// Filename of an upload as it's shown and offered for download: without any directory
+/// part, quotes or control characters and at most 255 characters long.
+pub(crate) fn clean_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => String::from("attachment"),
+        name => name.to_owned(),
     }
+}
 
-    key
+/// Emails are stored and looked up lowercased, which makes them case-insensitive on
+/// every database.
+pub(crate) fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
 }
 
 #[tracing::instrument]
@@ -43,8 +48,73 @@ pub(crate) fn is_url(url: &str) -> bool {
             tracing::debug!("URL could be parsed, scheme: {}, host: {}", scheme, host);
             ["http", "https", "mailto"].contains(&scheme)
                 && host.contains('.')
-                && !host.contains("katb.in")
+                && !is_instance_host(host)
+                && !is_blocked_host(host)
         }
         Err(_) => false,
     }
 }
+
+/// Loads the short link domain blocklist, one domain per line. Blank lines and
+/// lines starting with `#` are ignored. Can only be loaded once.
+pub fn load_domain_blocklist(path: &str) -> io::Result<usize> {
+    let domains: HashSet<String> = fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim().trim_start_matches("*.").to_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let count = domains.len();
+    BLOCKED_DOMAINS
+        .set(domains)
+        .map_err(|_| io::Error::other("blocklist already loaded"))?;
+    Ok(count)
+}
+
+/// Sets the hosts this instance is served from, so short links pointing back at the
+/// instance (and redirecting in a loop) are treated as plain pastes. Can only be set once.
+pub fn set_instance_hosts(hosts: Vec<String>) {
+    let hosts = hosts.into_iter().map(|h| h.to_lowercase()).collect();
+    if INSTANCE_HOSTS.set(hosts).is_err() {
+        tracing::warn!("instance hosts were already set");
+    }
+}
+
+pub fn is_instance_host(host: &str) -> bool {
+    INSTANCE_HOSTS
+        .get()
+        .is_some_and(|hosts| hosts.iter().any(|own| host_matches(host, own)))
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub(crate) fn host_matches(host: &str, domain: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn is_blocked_host(host: &str) -> bool {
+    let Some(blocked) = BLOCKED_DOMAINS.get() else {
+        return false;
+    };
+
+    // check the host and every parent domain against the list
+    let host = host.trim_end_matches('.').to_lowercase();
+    let mut candidate = host.as_str();
+    loop {
+        if blocked.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => return false,
+        }
+    }
+}
+
+/// The host of `url` if it is a link to a blocklisted domain.
+pub(crate) fn blocked_url_host(url: &str) -> Option<String> {
+    let parsed = Url::parse(url.trim()).ok()?;
+    let host = parsed.host_str()?;
+    is_blocked_host(host).then(|| host.to_owned())
+}